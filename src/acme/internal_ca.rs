@@ -0,0 +1,286 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Certificate, Error, Result};
+use crate::config::{get_current_config, CertificateConf};
+use crate::proxy::init_certificates;
+use crate::service::{CommonServiceTask, ServiceTask};
+use crate::util;
+use ahash::AHashMap;
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use once_cell::sync::Lazy;
+use rcgen::{
+    CertificateParams, DnType, ExtendedKeyUsagePurpose, KeyPair,
+    KeyUsagePurpose,
+};
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+// default validity of certificate issued by internal ca: 30 days
+static DEFAULT_VALIDITY: Duration = Duration::from_secs(30 * 24 * 3600);
+
+// issued certificates of internal ca, the key is the hash of certificate config
+static INTERNAL_CERTIFICATES: Lazy<
+    ArcSwap<AHashMap<String, Arc<Certificate>>>,
+> = Lazy::new(|| ArcSwap::from_pointee(AHashMap::new()));
+
+fn new_rcgen_error(category: &str, source: rcgen::Error) -> Error {
+    Error::Rcgen {
+        category: category.to_string(),
+        source,
+    }
+}
+
+// The certificate should be renewed when less than one third
+// of its lifetime remains.
+fn should_renew(cert: &Certificate) -> bool {
+    let now = util::now().as_secs() as i64;
+    let lifetime = cert.not_after - cert.not_before;
+    cert.not_after - now < lifetime / 3
+}
+
+/// Issue a leaf certificate for the domains, which is signed by the ca.
+fn issue_certificate(
+    domains: &[String],
+    ca_cert: &str,
+    ca_key: &str,
+    validity: Duration,
+) -> Result<Certificate> {
+    let ca_key_pair = KeyPair::from_pem(ca_key)
+        .map_err(|e| new_rcgen_error("ca_key_from_pem", e))?;
+    let ca = CertificateParams::from_ca_cert_pem(ca_cert)
+        .map_err(|e| new_rcgen_error("ca_cert_from_pem", e))?
+        .self_signed(&ca_key_pair)
+        .map_err(|e| new_rcgen_error("ca_self_signed", e))?;
+
+    let mut params = CertificateParams::new(domains.to_vec())
+        .map_err(|e| new_rcgen_error("new_params", e))?;
+    let now = time::OffsetDateTime::now_utc();
+    // allow one hour clock skew
+    params.not_before = now - time::Duration::hours(1);
+    params.not_after = now + validity;
+    if let Some(domain) = domains.first() {
+        params
+            .distinguished_name
+            .push(DnType::CommonName, domain.as_str());
+    }
+    let not_before = params.not_before.unix_timestamp();
+    let not_after = params.not_after.unix_timestamp();
+    params.use_authority_key_identifier_extension = true;
+    params.key_usages = vec![KeyUsagePurpose::DigitalSignature];
+    params.extended_key_usages = vec![ExtendedKeyUsagePurpose::ServerAuth];
+
+    let key_pair = KeyPair::generate()
+        .map_err(|e| new_rcgen_error("generate_key_pair", e))?;
+    let cert = params
+        .signed_by(&key_pair, &ca, &ca_key_pair)
+        .map_err(|e| new_rcgen_error("signed_by", e))?;
+
+    Ok(Certificate {
+        domains: domains.to_vec(),
+        not_before,
+        not_after,
+        pem: util::base64_encode(cert.pem()),
+        key: util::base64_encode(key_pair.serialize_pem()),
+    })
+}
+
+fn get_storage_pem(name: &Option<String>) -> Result<String> {
+    let name = name.clone().unwrap_or_default();
+    let value = get_current_config().get_storage_value(&name).map_err(|e| {
+        Error::Fail {
+            category: "get_storage_value".to_string(),
+            message: e.to_string(),
+        }
+    })?;
+    let buf = util::convert_certificate_bytes(&Some(value)).unwrap_or_default();
+    if buf.is_empty() {
+        return Err(Error::NotFound {
+            message: format!("storage({name}) is empty"),
+        });
+    }
+    String::from_utf8(buf).map_err(|e| Error::Fail {
+        category: "pem_from_utf8".to_string(),
+        message: e.to_string(),
+    })
+}
+
+/// Get the pem data of internal ca certificate from storage.
+pub fn get_internal_ca_pem(conf: &CertificateConf) -> Result<String> {
+    get_storage_pem(&conf.ca_cert_storage)
+}
+
+/// Get the certificate issued by internal ca,
+/// a new one will be issued if it's not found or should be renewed.
+pub fn get_internal_ca_certificate(
+    conf: &CertificateConf,
+) -> Result<Arc<Certificate>> {
+    let key = conf.hash_key();
+    if let Some(cert) = INTERNAL_CERTIFICATES.load().get(&key) {
+        if !should_renew(cert) {
+            return Ok(cert.clone());
+        }
+    }
+    let domains: Vec<String> = conf
+        .domains
+        .clone()
+        .unwrap_or_default()
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+        .collect();
+    let cert = Arc::new(issue_certificate(
+        &domains,
+        &get_storage_pem(&conf.ca_cert_storage)?,
+        &get_storage_pem(&conf.ca_key_storage)?,
+        conf.validity.unwrap_or(DEFAULT_VALIDITY),
+    )?);
+    info!(
+        domains = domains.join(","),
+        not_after = cert.not_after,
+        "issue certificate from internal ca"
+    );
+    let mut certificates: AHashMap<String, Arc<Certificate>> =
+        INTERNAL_CERTIFICATES.load().as_ref().clone();
+    certificates.insert(key, cert.clone());
+    INTERNAL_CERTIFICATES.store(Arc::new(certificates));
+    Ok(cert)
+}
+
+struct InternalCaService {}
+
+#[async_trait]
+impl ServiceTask for InternalCaService {
+    async fn run(&self) -> Option<bool> {
+        let certificates = get_current_config().certificates.clone();
+        let keys: Vec<String> = certificates
+            .values()
+            .filter(|item| item.is_internal_ca())
+            .map(|item| item.hash_key())
+            .collect();
+        let current = INTERNAL_CERTIFICATES.load();
+        // remove the certificates whose config has been removed or modified
+        let retained: AHashMap<String, Arc<Certificate>> = current
+            .iter()
+            .filter(|(key, _)| keys.contains(key))
+            .map(|(key, cert)| (key.to_string(), cert.clone()))
+            .collect();
+        let should_renew_now = keys.iter().any(|key| {
+            retained
+                .get(key)
+                .map(|cert| should_renew(cert))
+                .unwrap_or(true)
+        });
+        INTERNAL_CERTIFICATES.store(Arc::new(retained));
+        if !should_renew_now {
+            return None;
+        }
+        // certificates will be issued again when they are initialized,
+        // and the parse fail will be sent to webhook
+        init_certificates(&certificates);
+        let issued = INTERNAL_CERTIFICATES.load();
+        let renewed = keys.iter().all(|key| {
+            issued
+                .get(key)
+                .map(|cert| !should_renew(cert))
+                .unwrap_or(false)
+        });
+        if renewed {
+            info!("renew internal ca certificate success");
+        } else {
+            error!("renew internal ca certificate fail");
+        }
+        None
+    }
+    fn description(&self) -> String {
+        "InternalCa".to_string()
+    }
+}
+
+/// Create an internal ca service, which renews the certificates
+/// issued by internal ca before they expire.
+pub fn new_internal_ca_service() -> CommonServiceTask {
+    CommonServiceTask::new(
+        // check interval: ten minutes
+        Duration::from_secs(10 * 60),
+        InternalCaService {},
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{issue_certificate, should_renew};
+    use crate::acme::{get_certificate_info, Certificate};
+    use crate::util;
+    use pretty_assertions::assert_eq;
+    use rcgen::{BasicConstraints, CertificateParams, DnType, IsCa, KeyPair};
+    use std::time::Duration;
+
+    #[test]
+    fn test_issue_certificate() {
+        let ca_key = KeyPair::generate().unwrap();
+        let mut params =
+            CertificateParams::new(vec!["Pingap CA".to_string()]).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "Pingap CA");
+        let ca_cert = params.self_signed(&ca_key).unwrap();
+
+        let cert = issue_certificate(
+            &["pingap.io".to_string(), "*.pingap.io".to_string()],
+            &ca_cert.pem(),
+            &ca_key.serialize_pem(),
+            Duration::from_secs(24 * 3600),
+        )
+        .unwrap();
+        assert_eq!(
+            r#"["pingap.io", "*.pingap.io"]"#,
+            format!("{:?}", cert.domains)
+        );
+        assert_eq!(25 * 3600, cert.not_after - cert.not_before);
+        assert_eq!(false, should_renew(&cert));
+
+        let info = get_certificate_info(&cert.get_cert()).unwrap();
+        assert_eq!(cert.not_after, info.not_after);
+        assert_eq!("Pingap CA", info.get_issuer_common_name());
+
+        let result = issue_certificate(
+            &["pingap.io".to_string()],
+            &ca_cert.pem(),
+            "",
+            Duration::from_secs(24 * 3600),
+        );
+        assert_eq!(true, result.is_err());
+    }
+
+    #[test]
+    fn test_should_renew() {
+        let now = util::now().as_secs() as i64;
+        let cert = Certificate {
+            not_before: now - 20 * 24 * 3600,
+            not_after: now + 10 * 24 * 3600 + 60,
+            ..Default::default()
+        };
+        assert_eq!(false, should_renew(&cert));
+        let cert = Certificate {
+            not_before: now - 20 * 24 * 3600,
+            not_after: now + 9 * 24 * 3600,
+            ..Default::default()
+        };
+        assert_eq!(true, should_renew(&cert));
+    }
+}
@@ -114,12 +114,21 @@ impl Certificate {
     }
 }
 
+mod internal_ca;
 mod lets_encrypt;
+mod ocsp;
 mod validity_checker;
 
+pub use internal_ca::{
+    get_internal_ca_certificate, get_internal_ca_pem, new_internal_ca_service,
+};
 pub use lets_encrypt::{
     get_lets_encrypt_certificate, handle_lets_encrypt, new_lets_encrypt_service,
 };
+pub use ocsp::{
+    get_fingerprint, get_ocsp_response, get_ocsp_stapling_failures,
+    new_ocsp_stapling_service,
+};
 pub use validity_checker::new_tls_validity_service;
 
 #[cfg(test)]
@@ -0,0 +1,361 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Error, Result};
+use crate::proxy::get_certificate_issuer_list;
+use crate::service::{CommonServiceTask, ServiceTask};
+use crate::util;
+use ahash::AHashMap;
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use foreign_types::ForeignTypeRef;
+use once_cell::sync::Lazy;
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::ocsp::{
+    OcspBasicResponseRef, OcspCertId, OcspCertIdRef, OcspCertStatus,
+    OcspRequest, OcspResponse, OcspResponseStatus,
+};
+use pingora::tls::x509::{X509Ref, X509};
+use std::ptr;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{error, info};
+
+#[derive(Debug, Clone)]
+struct OcspStaple {
+    // the der data of ocsp response
+    data: Vec<u8>,
+    // the next update time of ocsp response(seconds)
+    next_update: i64,
+}
+
+// ocsp staple of certificate, the key is the fingerprint of certificate
+static OCSP_STAPLES: Lazy<ArcSwap<AHashMap<String, OcspStaple>>> =
+    Lazy::new(|| ArcSwap::from_pointee(AHashMap::new()));
+
+// ocsp stapling fail of certificate, the key is the name of certificate
+static OCSP_FAILURES: Lazy<ArcSwap<AHashMap<String, String>>> =
+    Lazy::new(|| ArcSwap::from_pointee(AHashMap::new()));
+
+// the validity of ocsp response without next update time,
+// it's refreshed one day before expired(the next hour check)
+const OCSP_DEFAULT_VALIDITY: i64 = 24 * 3600 + 60;
+
+/// Get the sha256 fingerprint of certificate, it's the key of ocsp staple.
+pub fn get_fingerprint(cert: &X509Ref) -> String {
+    cert.digest(MessageDigest::sha256())
+        .map(hex::encode)
+        .unwrap_or_default()
+}
+
+/// Get the ocsp response of certificate(fingerprint) for stapling,
+/// it will return none if the response is not found or expired.
+pub fn get_ocsp_response(fingerprint: &str) -> Option<Vec<u8>> {
+    let staples = OCSP_STAPLES.load();
+    let staple = staples.get(fingerprint)?;
+    if staple.next_update <= util::now().as_secs() as i64 {
+        return None;
+    }
+    Some(staple.data.clone())
+}
+
+/// Get the ocsp stapling fail list of certificates.
+pub fn get_ocsp_stapling_failures() -> Vec<(String, String)> {
+    let mut failures: Vec<(String, String)> = OCSP_FAILURES
+        .load()
+        .iter()
+        .map(|(name, message)| (name.to_string(), message.to_string()))
+        .collect();
+    failures.sort();
+    failures
+}
+
+// Convert the time of asn1 to timestamp, which is calculated by
+// the diff between now and the time.
+fn get_asn1_timestamp(time: &Asn1TimeRef) -> Result<i64> {
+    let now = util::now().as_secs() as i64;
+    let current = Asn1Time::from_unix(now as libc::time_t)
+        .map_err(|e| new_ocsp_error("asn1_time", e.to_string()))?;
+    let diff = current
+        .diff(time)
+        .map_err(|e| new_ocsp_error("asn1_time", e.to_string()))?;
+    Ok(now + diff.days as i64 * 24 * 3600 + diff.secs as i64)
+}
+
+fn new_ocsp_error(category: &str, message: String) -> Error {
+    Error::Fail {
+        category: category.to_string(),
+        message,
+    }
+}
+
+/// Find the status of certificate and check its validity,
+/// returns the status and next update time. The next update time
+/// is optional in ocsp response, so `find_status` of openssl crate
+/// isn't used(it converts the null pointer to reference).
+fn find_cert_status(
+    basic: &OcspBasicResponseRef,
+    cert_id: &OcspCertIdRef,
+) -> Result<(OcspCertStatus, Option<i64>)> {
+    let mut status = openssl_sys::V_OCSP_CERTSTATUS_UNKNOWN;
+    let mut reason = openssl_sys::OCSP_REVOKED_STATUS_NOSTATUS;
+    let mut revocation_time = ptr::null_mut();
+    let mut this_update = ptr::null_mut();
+    let mut next_update = ptr::null_mut();
+    let found = unsafe {
+        openssl_sys::OCSP_resp_find_status(
+            basic.as_ptr(),
+            cert_id.as_ptr(),
+            &mut status,
+            &mut reason,
+            &mut revocation_time,
+            &mut this_update,
+            &mut next_update,
+        )
+    };
+    if found != 1 {
+        return Err(new_ocsp_error(
+            "ocsp_response",
+            "certificate status is not found".to_string(),
+        ));
+    }
+    // allow 5 minutes clock skew, the next update can be null
+    let valid = unsafe {
+        openssl_sys::OCSP_check_validity(this_update, next_update, 5 * 60, -1)
+    };
+    if valid != 1 {
+        return Err(new_ocsp_error(
+            "ocsp_validity",
+            ErrorStack::get().to_string(),
+        ));
+    }
+    if next_update.is_null() {
+        return Ok((OcspCertStatus::from_raw(status), None));
+    }
+    // ASN1_GENERALIZEDTIME and ASN1_TIME are the same ASN1_STRING type,
+    // so the generalized time can be used as asn1 time to diff
+    let next_update = get_asn1_timestamp(unsafe {
+        Asn1TimeRef::from_ptr(next_update.cast())
+    })?;
+    Ok((OcspCertStatus::from_raw(status), Some(next_update)))
+}
+
+/// Get the ocsp responder url of certificate,
+/// it will return none if the certificate has no ocsp responder.
+fn get_ocsp_responder(cert: &X509Ref) -> Option<String> {
+    cert.ocsp_responders()
+        .ok()?
+        .iter()
+        .next()
+        .map(|item| item.to_string())
+}
+
+/// Fetch the ocsp response from the responder of certificate.
+async fn fetch_ocsp_staple(
+    url: &str,
+    cert: &X509,
+    issuer: &X509,
+) -> Result<OcspStaple> {
+    let new_cert_id = || {
+        OcspCertId::from_cert(MessageDigest::sha1(), cert, issuer)
+            .map_err(|e| new_ocsp_error("ocsp_cert_id", e.to_string()))
+    };
+    let mut req = OcspRequest::new()
+        .map_err(|e| new_ocsp_error("ocsp_request", e.to_string()))?;
+    req.add_id(new_cert_id()?)
+        .map_err(|e| new_ocsp_error("ocsp_request", e.to_string()))?;
+    let body = req
+        .to_der()
+        .map_err(|e| new_ocsp_error("ocsp_request", e.to_string()))?;
+
+    let data = reqwest::Client::new()
+        .post(url)
+        .header(http::header::CONTENT_TYPE, "application/ocsp-request")
+        .body(body)
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await
+        .map_err(|e| new_ocsp_error("ocsp_fetch", e.to_string()))?
+        .error_for_status()
+        .map_err(|e| new_ocsp_error("ocsp_fetch", e.to_string()))?
+        .bytes()
+        .await
+        .map_err(|e| new_ocsp_error("ocsp_fetch", e.to_string()))?;
+
+    let resp = OcspResponse::from_der(&data)
+        .map_err(|e| new_ocsp_error("ocsp_response", e.to_string()))?;
+    if resp.status() != OcspResponseStatus::SUCCESSFUL {
+        return Err(new_ocsp_error(
+            "ocsp_response",
+            format!("ocsp response status is {:?}", resp.status()),
+        ));
+    }
+    let basic = resp
+        .basic()
+        .map_err(|e| new_ocsp_error("ocsp_response", e.to_string()))?;
+    let cert_id = new_cert_id()?;
+    let (status, next_update) = find_cert_status(&basic, &cert_id)?;
+    if status != OcspCertStatus::GOOD {
+        return Err(new_ocsp_error(
+            "ocsp_response",
+            format!("certificate status is {status:?}"),
+        ));
+    }
+    let next_update = next_update.unwrap_or_else(|| {
+        util::now().as_secs() as i64 + OCSP_DEFAULT_VALIDITY
+    });
+
+    Ok(OcspStaple {
+        data: data.to_vec(),
+        next_update,
+    })
+}
+
+struct OcspStapling {
+    // refresh the ocsp response before it expires
+    refresh_before: i64,
+}
+
+#[async_trait]
+impl ServiceTask for OcspStapling {
+    async fn run(&self) -> Option<bool> {
+        let now = util::now().as_secs() as i64;
+        let current_staples = OCSP_STAPLES.load();
+        let mut staples = AHashMap::new();
+        let mut failures = AHashMap::new();
+        for (name, cert, issuer) in get_certificate_issuer_list() {
+            // the certificate without ocsp responder(e.g. issued by
+            // internal ca) doesn't support stapling, skip it
+            let Some(url) = get_ocsp_responder(&cert) else {
+                continue;
+            };
+            let fingerprint = get_fingerprint(&cert);
+            let current = current_staples.get(&fingerprint);
+            if let Some(staple) = current {
+                if staple.next_update - now > self.refresh_before {
+                    staples.insert(fingerprint, staple.clone());
+                    continue;
+                }
+            }
+            match fetch_ocsp_staple(&url, &cert, &issuer).await {
+                Ok(staple) => {
+                    info!(
+                        name,
+                        next_update = staple.next_update,
+                        "fetch ocsp response success"
+                    );
+                    staples.insert(fingerprint, staple);
+                },
+                Err(e) => {
+                    error!(
+                        name,
+                        error = e.to_string(),
+                        "fetch ocsp response fail"
+                    );
+                    // keep the response which is not expired
+                    if let Some(staple) = current {
+                        if staple.next_update > now {
+                            staples.insert(fingerprint, staple.clone());
+                        }
+                    }
+                    failures.insert(name, e.to_string());
+                },
+            };
+        }
+        OCSP_STAPLES.store(Arc::new(staples));
+        OCSP_FAILURES.store(Arc::new(failures));
+        None
+    }
+    fn description(&self) -> String {
+        let offset_human: humantime::Duration =
+            Duration::from_secs(self.refresh_before as u64).into();
+        format!("OcspStapling: {offset_human}")
+    }
+}
+
+/// Create a ocsp stapling service, which fetches the ocsp response
+/// of certificates and refreshes them before they expire.
+pub fn new_ocsp_stapling_service() -> CommonServiceTask {
+    CommonServiceTask::new(
+        // check interval: one hour
+        Duration::from_secs(60 * 60),
+        OcspStapling {
+            // refresh one day before expired
+            refresh_before: 24 * 3600,
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_asn1_timestamp, get_fingerprint, get_ocsp_responder,
+        get_ocsp_response, OcspStaple, OCSP_STAPLES,
+    };
+    use crate::util;
+    use ahash::AHashMap;
+    use openssl::asn1::Asn1Time;
+    use pingora::tls::x509::X509;
+    use pretty_assertions::assert_eq;
+    use std::sync::Arc;
+
+    #[test]
+    fn test_get_asn1_timestamp() {
+        let time = Asn1Time::from_unix(1730419200).unwrap();
+        assert_eq!(1730419200, get_asn1_timestamp(&time).unwrap());
+        let time = Asn1Time::from_str("20991111000000Z").unwrap();
+        assert_eq!(4098038400, get_asn1_timestamp(&time).unwrap());
+    }
+
+    #[test]
+    fn test_get_ocsp_responder() {
+        let cert = X509::from_pem(include_bytes!("../assets/r11.pem")).unwrap();
+        assert_eq!(true, get_ocsp_responder(&cert).is_none());
+    }
+
+    #[test]
+    fn test_get_ocsp_response() {
+        let cert = X509::from_pem(include_bytes!("../assets/r11.pem")).unwrap();
+        let fingerprint = get_fingerprint(&cert);
+        assert_eq!(true, get_ocsp_response(&fingerprint).is_none());
+
+        let now = util::now().as_secs() as i64;
+        let mut staples = AHashMap::new();
+        staples.insert(
+            get_fingerprint(&cert),
+            OcspStaple {
+                data: b"pingap".to_vec(),
+                next_update: now + 60,
+            },
+        );
+        OCSP_STAPLES.store(Arc::new(staples));
+        assert_eq!(
+            b"pingap".to_vec(),
+            get_ocsp_response(&fingerprint).unwrap()
+        );
+
+        let mut staples = AHashMap::new();
+        staples.insert(
+            get_fingerprint(&cert),
+            OcspStaple {
+                data: b"pingap".to_vec(),
+                next_update: now - 60,
+            },
+        );
+        OCSP_STAPLES.store(Arc::new(staples));
+        assert_eq!(true, get_ocsp_response(&fingerprint).is_none());
+    }
+}
@@ -12,7 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::CertificateInfo;
+use super::{get_ocsp_stapling_failures, CertificateInfo};
 use crate::proxy::get_certificate_info_list;
 use crate::service::{CommonServiceTask, ServiceTask};
 use crate::util;
@@ -69,6 +69,23 @@ impl ServiceTask for ValidityChecker {
                 ..Default::default()
             });
         }
+        let ocsp_failures = get_ocsp_stapling_failures();
+        if !ocsp_failures.is_empty() {
+            let message = ocsp_failures
+                .iter()
+                .map(|(name, message)| {
+                    format!("{name} ocsp stapling fail, {message}")
+                })
+                .collect::<Vec<String>>()
+                .join(";");
+            warn!(message);
+            webhook::send(webhook::SendNotificationParams {
+                level: webhook::NotificationLevel::Warn,
+                category: webhook::NotificationCategory::TlsValidity,
+                msg: message,
+                ..Default::default()
+            });
+        }
         None
     }
     fn description(&self) -> String {
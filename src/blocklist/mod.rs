@@ -0,0 +1,391 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::service::{CommonServiceTask, ServiceTask};
+use crate::util;
+use async_trait::async_trait;
+use humantime::parse_duration;
+use ip_network::IpNetwork;
+use ip_network_table::IpNetworkTable;
+use once_cell::sync::Lazy;
+use serde::Serialize;
+use snafu::Snafu;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use tracing::{error, info};
+
+#[derive(Debug, Snafu)]
+pub enum Error {
+    #[snafu(display("Invalid error, {message}"))]
+    Invalid { message: String },
+    #[snafu(display("Io error, {source}, {file}"))]
+    Io {
+        source: std::io::Error,
+        file: String,
+    },
+}
+type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The source of block entry.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, strum::Display)]
+#[serde(rename_all = "snake_case")]
+#[strum(serialize_all = "snake_case")]
+pub enum BlockSource {
+    File,
+    Admin,
+    Limit,
+}
+
+#[derive(Debug, Clone)]
+struct BlockValue {
+    source: BlockSource,
+    // expired time(unix timestamp of seconds), none means never expired
+    expired_at: Option<u64>,
+    reason: Option<String>,
+}
+
+impl BlockValue {
+    #[inline]
+    fn is_expired(&self, now: u64) -> bool {
+        self.expired_at
+            .map(|value| value <= now)
+            .unwrap_or_default()
+    }
+}
+
+/// The block entry of ip or cidr, it's used for admin api.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlockEntry {
+    pub network: String,
+    pub source: BlockSource,
+    pub expired_at: Option<u64>,
+    pub reason: Option<String>,
+}
+
+// ip network table uses tree bitmap for matching,
+// so it's fast enough for tens of thousands of cidrs
+static BLOCKLIST: Lazy<RwLock<IpNetworkTable<BlockValue>>> =
+    Lazy::new(|| RwLock::new(IpNetworkTable::new()));
+
+/// Parse the value to ip network, it supports single ip or cidr.
+fn parse_network(value: &str) -> Result<IpNetwork> {
+    let value = value.trim();
+    let result = if let Some((ip, netmask)) = value.split_once('/') {
+        let ip = IpAddr::from_str(ip).map_err(|e| Error::Invalid {
+            message: format!("{value}, {e}"),
+        })?;
+        let netmask = netmask.parse::<u8>().map_err(|e| Error::Invalid {
+            message: format!("{value}, {e}"),
+        })?;
+        IpNetwork::new_truncate(ip, netmask).map_err(|e| Error::Invalid {
+            message: format!("{value}, {e}"),
+        })?
+    } else {
+        IpAddr::from_str(value)
+            .map_err(|e| Error::Invalid {
+                message: format!("{value}, {e}"),
+            })?
+            .into()
+    };
+    Ok(result)
+}
+
+#[inline]
+fn now_secs() -> u64 {
+    util::now().as_secs()
+}
+
+/// Returns true if the ip is blocked by any unexpired entry.
+pub fn is_blocked(ip: &str) -> bool {
+    let Ok(table) = BLOCKLIST.read() else {
+        return false;
+    };
+    if table.is_empty() {
+        return false;
+    }
+    let Ok(ip) = IpAddr::from_str(ip) else {
+        return false;
+    };
+    let now = now_secs();
+    let blocked = table.matches(ip).any(|(_, value)| !value.is_expired(now));
+    blocked
+}
+
+/// Add ip or cidr to the blocklist, the entry will be expired after ttl.
+pub fn add(
+    network: &str,
+    ttl: Option<Duration>,
+    source: BlockSource,
+    reason: Option<String>,
+) -> Result<()> {
+    let network = parse_network(network)?;
+    let expired_at = ttl.map(|ttl| now_secs() + ttl.as_secs());
+    if let Ok(mut table) = BLOCKLIST.write() {
+        table.insert(
+            network,
+            BlockValue {
+                source,
+                expired_at,
+                reason,
+            },
+        );
+    }
+    Ok(())
+}
+
+/// Remove the ip or cidr from the blocklist,
+/// returns true if the entry exists.
+pub fn remove(network: &str) -> Result<bool> {
+    let network = parse_network(network)?;
+    let removed = if let Ok(mut table) = BLOCKLIST.write() {
+        table.remove(network).is_some()
+    } else {
+        false
+    };
+    Ok(removed)
+}
+
+/// List all unexpired entries of the blocklist.
+pub fn list() -> Vec<BlockEntry> {
+    let now = now_secs();
+    let mut entries = vec![];
+    let Ok(table) = BLOCKLIST.read() else {
+        return entries;
+    };
+    for (network, value) in table.iter() {
+        if value.is_expired(now) {
+            continue;
+        }
+        entries.push(BlockEntry {
+            network: network.to_string(),
+            source: value.source,
+            expired_at: value.expired_at,
+            reason: value.reason.clone(),
+        });
+    }
+    entries
+}
+
+/// Remove the expired entries, returns the count of removed entries.
+pub fn prune() -> usize {
+    let now = now_secs();
+    let Ok(mut table) = BLOCKLIST.write() else {
+        return 0;
+    };
+    let size = table.len();
+    table.retain(|_, value| !value.is_expired(now));
+    let (ipv4_size, ipv6_size) = table.len();
+    let (prev_ipv4_size, prev_ipv6_size) = size;
+    prev_ipv4_size + prev_ipv6_size - ipv4_size - ipv6_size
+}
+
+/// Parse the blocklist file content, each line is `ip_or_cidr [ttl]`,
+/// empty line and line starts with `#` will be ignored.
+fn parse_blocklist(data: &str) -> Vec<(IpNetwork, Option<Duration>)> {
+    let mut result = vec![];
+    for line in data.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut arr = line.split_whitespace();
+        let network = arr.next().unwrap_or_default();
+        let network = match parse_network(network) {
+            Ok(network) => network,
+            Err(e) => {
+                error!(error = e.to_string(), "parse blocklist fail");
+                continue;
+            },
+        };
+        let ttl = arr.next().and_then(|value| parse_duration(value).ok());
+        result.push((network, ttl));
+    }
+    result
+}
+
+/// Load the blocklist file, all entries from file source will be replaced.
+pub fn load_file(file: &str) -> Result<usize> {
+    let data = std::fs::read_to_string(file).map_err(|e| Error::Io {
+        source: e,
+        file: file.to_string(),
+    })?;
+    let entries = parse_blocklist(&data);
+    let now = now_secs();
+    let count = entries.len();
+    let Ok(mut table) = BLOCKLIST.write() else {
+        return Ok(0);
+    };
+    table.retain(|_, value| value.source != BlockSource::File);
+    for (network, ttl) in entries {
+        table.insert(
+            network,
+            BlockValue {
+                source: BlockSource::File,
+                expired_at: ttl.map(|ttl| now + ttl.as_secs()),
+                reason: None,
+            },
+        );
+    }
+    Ok(count)
+}
+
+struct BlocklistService {
+    file: Option<String>,
+    modified: Mutex<Option<SystemTime>>,
+}
+
+#[async_trait]
+impl ServiceTask for BlocklistService {
+    async fn run(&self) -> Option<bool> {
+        if let Some(file) = &self.file {
+            let modified = std::fs::metadata(file)
+                .and_then(|metadata| metadata.modified())
+                .ok();
+            let mut changed = false;
+            if let Ok(mut prev) = self.modified.lock() {
+                changed = modified.is_some() && *prev != modified;
+                *prev = modified;
+            }
+            if changed {
+                match load_file(file) {
+                    Ok(count) => {
+                        info!(file, count, "reload blocklist success")
+                    },
+                    Err(e) => {
+                        error!(error = e.to_string(), "reload blocklist fail")
+                    },
+                };
+            }
+        }
+        let count = prune();
+        if count != 0 {
+            info!(count, "prune expired blocklist entries");
+        }
+        None
+    }
+    fn description(&self) -> String {
+        let file = self.file.clone().unwrap_or_default();
+        format!("Blocklist: {file}")
+    }
+}
+
+/// Create a blocklist service, which reloads the blocklist file
+/// when it is modified and prunes the expired entries.
+pub fn new_blocklist_service(file: Option<String>) -> CommonServiceTask {
+    CommonServiceTask::new(
+        // check interval: ten seconds
+        Duration::from_secs(10),
+        BlocklistService {
+            file: file.map(|file| util::resolve_path(&file)),
+            modified: Mutex::new(None),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::io::Write;
+    use tempfile::NamedTempFile;
+
+    #[test]
+    fn test_parse_network() {
+        assert_eq!("1.1.1.1/32", parse_network("1.1.1.1").unwrap().to_string());
+        assert_eq!(
+            "10.1.0.0/16",
+            parse_network("10.1.2.3/16").unwrap().to_string()
+        );
+        assert_eq!(
+            "2001:db8::/32",
+            parse_network("2001:db8::1/32").unwrap().to_string()
+        );
+        assert_eq!(
+            "Invalid error, 1.1.1, invalid IP address syntax",
+            parse_network("1.1.1").err().unwrap().to_string()
+        );
+        assert_eq!(true, parse_network("1.1.1.1/33").is_err());
+    }
+
+    #[test]
+    fn test_parse_blocklist() {
+        let entries = parse_blocklist(
+            r#"
+# comment
+1.1.1.1
+10.0.0.0/8 1h
+abc
+"#,
+        );
+        assert_eq!(2, entries.len());
+        assert_eq!("1.1.1.1/32", entries[0].0.to_string());
+        assert_eq!(None, entries[0].1);
+        assert_eq!("10.0.0.0/8", entries[1].0.to_string());
+        assert_eq!(Some(Duration::from_secs(3600)), entries[1].1);
+    }
+
+    #[test]
+    fn test_blocklist() {
+        // the blocklist is global, use special ranges to avoid conflict
+        add(
+            "198.51.100.0/24",
+            None,
+            BlockSource::Admin,
+            Some("scan".to_string()),
+        )
+        .unwrap();
+        add(
+            "203.0.113.10",
+            Some(Duration::from_secs(0)),
+            BlockSource::Limit,
+            None,
+        )
+        .unwrap();
+        assert_eq!(true, is_blocked("198.51.100.20"));
+        assert_eq!(false, is_blocked("198.51.101.20"));
+        // expired
+        assert_eq!(false, is_blocked("203.0.113.10"));
+        assert_eq!(false, is_blocked("abc"));
+
+        let entry = list()
+            .into_iter()
+            .find(|item| item.network == "198.51.100.0/24")
+            .unwrap();
+        assert_eq!(BlockSource::Admin, entry.source);
+        assert_eq!("scan", entry.reason.unwrap_or_default());
+        assert_eq!(
+            false,
+            list().iter().any(|item| item.network == "203.0.113.10/32")
+        );
+
+        assert_eq!(true, prune() >= 1);
+        assert_eq!(true, remove("198.51.100.0/24").unwrap());
+        assert_eq!(false, remove("198.51.100.0/24").unwrap());
+        assert_eq!(false, is_blocked("198.51.100.20"));
+
+        let mut file = NamedTempFile::new().unwrap();
+        file.write_all(b"192.0.2.0/24\n2001:db8::/32 1h\n").unwrap();
+        let file_path = file.path().to_string_lossy().to_string();
+        assert_eq!(2, load_file(&file_path).unwrap());
+        assert_eq!(true, is_blocked("192.0.2.1"));
+        assert_eq!(true, is_blocked("2001:db8::1"));
+
+        // reload will replace the entries from file
+        file.as_file().set_len(0).unwrap();
+        assert_eq!(0, load_file(&file_path).unwrap());
+        assert_eq!(false, is_blocked("192.0.2.1"));
+    }
+}
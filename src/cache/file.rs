@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::file_index::{get_file_index, FileIndex, FileIndexEntry};
 use super::http_cache::{CacheObject, HttpCacheStats, HttpCacheStorage};
 use super::{Error, Result};
 #[cfg(feature = "full")]
@@ -19,16 +20,17 @@ use crate::state::{CACHE_READING_TIME, CACHE_WRITING_TIME};
 use crate::util;
 use async_trait::async_trait;
 use bytes::Bytes;
+use pingora::cache::CacheMeta;
 #[cfg(feature = "full")]
 use prometheus::Histogram;
 use scopeguard::defer;
 use std::path::Path;
 use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
 use std::time::SystemTime;
 use tinyufo::TinyUfo;
 use tokio::fs;
 use tracing::{error, info};
-use walkdir::WalkDir;
 
 pub struct FileCache {
     directory: String,
@@ -40,17 +42,39 @@ pub struct FileCache {
     writing_max: u32,
     #[cfg(feature = "full")]
     write_time: Box<Histogram>,
-    cache: TinyUfo<String, CacheObject>,
+    cache: Option<TinyUfo<String, CacheObject>>,
+    index: Arc<FileIndex>,
+    // the keys of evicted or cleared objects
+    evicted: Mutex<Vec<String>>,
 }
 
-/// Create a file cache and use tinyufo for hotspot data caching
-pub fn new_file_cache(dir: &str) -> Result<FileCache> {
+/// Create a file cache and use tinyufo for hotspot data caching,
+/// the max size of files is unlimited if it's zero.
+pub fn new_file_cache(dir: &str, max_size: u64) -> Result<FileCache> {
+    new_cache(dir, max_size, Some(TinyUfo::new(100, 100)))
+}
+
+/// Create a file cache without hotspot data caching,
+/// it's used as the second tier of tiered cache.
+pub fn new_file_cache_without_memory(
+    dir: &str,
+    max_size: u64,
+) -> Result<FileCache> {
+    new_cache(dir, max_size, None)
+}
+
+fn new_cache(
+    dir: &str,
+    max_size: u64,
+    cache: Option<TinyUfo<String, CacheObject>>,
+) -> Result<FileCache> {
     let dir = util::resolve_path(dir);
     let path = Path::new(&dir);
     if !path.exists() {
         std::fs::create_dir_all(path).map_err(|e| Error::Io { source: e })?;
     }
-    info!(dir, "new file cache");
+    let index = get_file_index(&dir, max_size)?;
+    info!(dir, max_size, "new file cache");
 
     Ok(FileCache {
         directory: dir,
@@ -64,10 +88,51 @@ pub fn new_file_cache(dir: &str) -> Result<FileCache> {
         writing_max: 1000,
         #[cfg(feature = "full")]
         write_time: CACHE_WRITING_TIME.clone(),
-        cache: TinyUfo::new(100, 100),
+        cache,
+        index,
+        evicted: Mutex::new(vec![]),
     })
 }
 
+/// Get the expired time(seconds) of cache object from its meta,
+/// the stale time is also included.
+fn get_expired_at(obj: &CacheObject) -> u64 {
+    let Ok(meta) = CacheMeta::deserialize(&obj.meta.0, &obj.meta.1) else {
+        return 0;
+    };
+    let stale = meta
+        .stale_while_revalidate_sec()
+        .max(meta.stale_if_error_sec());
+    meta.fresh_until()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|d| d.as_secs() + stale as u64)
+        .unwrap_or_default()
+}
+
+impl FileCache {
+    fn add_evicted(&self, keys: impl Iterator<Item = String>) {
+        if let Ok(mut evicted) = self.evicted.lock() {
+            evicted.extend(keys);
+        }
+    }
+    /// Remove the files of evicted entries and their hotspot data.
+    async fn remove_evicted(&self, evicted: Vec<(String, FileIndexEntry)>) {
+        self.add_evicted(evicted.iter().map(|(key, _)| key.clone()));
+        for (key, entry) in evicted {
+            if let Some(c) = &self.cache {
+                c.remove(&key);
+            }
+            if let Err(e) = fs::remove_file(&entry.path).await {
+                error!(
+                    err = e.to_string(),
+                    file = entry.path,
+                    "remove cache file fail"
+                );
+            }
+        }
+    }
+}
+
 #[cfg(feature = "full")]
 #[inline]
 fn elapsed(time: SystemTime) -> f64 {
@@ -80,7 +145,10 @@ impl HttpCacheStorage for FileCache {
     /// Get cache object from tinyufo,
     /// if not exists, then get from the file.
     async fn get(&self, key: &str) -> Result<Option<CacheObject>> {
-        if let Some(obj) = self.cache.get(&key.to_string()) {
+        if let Some(obj) =
+            self.cache.as_ref().and_then(|c| c.get(&key.to_string()))
+        {
+            self.index.touch(key);
             return Ok(Some(obj));
         }
         #[cfg(feature = "full")]
@@ -102,6 +170,7 @@ impl HttpCacheStorage for FileCache {
             Ok(buf) => Ok(buf),
             Err(e) => {
                 if e.kind() == std::io::ErrorKind::NotFound {
+                    self.index.remove(key);
                     Ok(vec![])
                 } else {
                     Err(Error::Io { source: e })
@@ -109,10 +178,27 @@ impl HttpCacheStorage for FileCache {
             },
         }?;
         if buf.len() < 8 {
-            Ok(None)
-        } else {
-            Ok(Some(CacheObject::from(Bytes::from(buf))))
+            return Ok(None);
+        }
+        let size = buf.len() as u64;
+        let obj = CacheObject::from(Bytes::from(buf));
+        // the file is written but the index isn't saved before exiting
+        if !self.index.touch(key) {
+            let evicted = self.index.insert(
+                key,
+                FileIndexEntry {
+                    path: Path::new(&self.directory)
+                        .join(key)
+                        .to_string_lossy()
+                        .to_string(),
+                    size,
+                    expired_at: get_expired_at(&obj),
+                    ..Default::default()
+                },
+            );
+            self.remove_evicted(evicted).await;
         }
+        Ok(Some(obj))
     }
     /// Put cache object to tinyufo and file.
     async fn put(
@@ -121,11 +207,15 @@ impl HttpCacheStorage for FileCache {
         data: CacheObject,
         weight: u16,
     ) -> Result<()> {
-        self.cache.put(key.clone(), data.clone(), weight);
+        if let Some(c) = &self.cache {
+            c.put(key.clone(), data.clone(), weight);
+        }
         #[cfg(feature = "full")]
         let start = SystemTime::now();
+        let expired_at = get_expired_at(&data);
         let buf: Bytes = data.into();
-        let file = Path::new(&self.directory).join(key);
+        let size = buf.len() as u64;
+        let file = Path::new(&self.directory).join(&key);
         // add writing count
         let count = self.writing.fetch_add(1, Ordering::Relaxed);
         defer!(self.writing.fetch_sub(1, Ordering::Relaxed););
@@ -135,14 +225,28 @@ impl HttpCacheStorage for FileCache {
                 message: "too many writing".to_string(),
             });
         }
-        let result = fs::write(file, buf).await;
+        let result = fs::write(&file, buf).await;
         #[cfg(feature = "full")]
         self.write_time.observe(elapsed(start));
-        result.map_err(|e| Error::Io { source: e })
+        result.map_err(|e| Error::Io { source: e })?;
+        let evicted = self.index.insert(
+            &key,
+            FileIndexEntry {
+                path: file.to_string_lossy().to_string(),
+                size,
+                expired_at,
+                ..Default::default()
+            },
+        );
+        self.remove_evicted(evicted).await;
+        Ok(())
     }
-    /// Remove cache object from file, tinyufo doesn't support remove now.
+    /// Remove cache object from tinyufo and file.
     async fn remove(&self, key: &str) -> Result<Option<CacheObject>> {
-        // TODO remove from tinyufo
+        if let Some(c) = &self.cache {
+            c.remove(&key.to_string());
+        }
+        self.index.remove(key);
         let file = Path::new(&self.directory).join(key);
         fs::remove_file(file)
             .await
@@ -155,25 +259,36 @@ impl HttpCacheStorage for FileCache {
         Some(HttpCacheStats {
             reading: self.reading.load(Ordering::Relaxed),
             writing: self.writing.load(Ordering::Relaxed),
+            index: Some(self.index.stats()),
+            ..Default::default()
         })
     }
+    /// Save the file index if it's modified.
+    async fn flush(&self) -> Result<()> {
+        self.index.save().await?;
+        Ok(())
+    }
+    fn evicted(&self) -> Vec<String> {
+        self.evicted
+            .lock()
+            .map(|mut evicted| std::mem::take(&mut *evicted))
+            .unwrap_or_default()
+    }
+    /// Check the object by index, the file isn't read.
+    fn contains(&self, key: &str) -> bool {
+        self.index.contains(key)
+    }
+    /// Remove the inactive cache files by index and save the index.
     async fn clear(&self, access_before: SystemTime) -> Result<(i32, i32)> {
         let mut success = 0;
         let mut fail = 0;
-        for entry in WalkDir::new(&self.directory)
-            .into_iter()
-            .filter_map(|e| e.ok())
-        {
-            let Ok(metadata) = entry.metadata() else {
-                continue;
-            };
-            let Ok(accessed) = metadata.accessed() else {
-                continue;
-            };
-            if accessed > access_before {
-                continue;
+        let removed = self.index.remove_inactive(access_before);
+        self.add_evicted(removed.iter().map(|(key, _)| key.clone()));
+        for (key, entry) in removed {
+            if let Some(c) = &self.cache {
+                c.remove(&key);
             }
-            match std::fs::remove_file(entry.path()) {
+            match fs::remove_file(&entry.path).await {
                 Ok(()) => {
                     success += 1;
                 },
@@ -181,7 +296,7 @@ impl HttpCacheStorage for FileCache {
                     fail += 1;
                     error!(
                         err = e.to_string(),
-                        entry = entry.path().to_string_lossy().to_string(),
+                        entry = entry.path,
                         "remove cache file fail"
                     );
                 },
@@ -203,7 +318,7 @@ mod tests {
     async fn test_file_cache() {
         let dir = TempDir::new().unwrap();
         let dir = dir.into_path().to_string_lossy().to_string();
-        let cache = new_file_cache(&dir).unwrap();
+        let cache = new_file_cache(&dir, 0).unwrap();
         let key = "key".to_string();
         let obj = CacheObject {
             meta: (b"Hello".to_vec(), b"World".to_vec()),
@@ -216,7 +331,7 @@ mod tests {
         assert_eq!(obj, result);
 
         // empty tinyufo, get from file
-        let cache = new_file_cache(&dir).unwrap();
+        let cache = new_file_cache(&dir, 0).unwrap();
         let result = cache.get(&key).await.unwrap().unwrap();
         assert_eq!(obj, result);
 
@@ -225,11 +340,27 @@ mod tests {
         assert_eq!(true, result.is_none());
     }
 
+    #[tokio::test]
+    async fn test_file_cache_eviction() {
+        let dir = TempDir::new().unwrap();
+        let dir = dir.into_path().to_string_lossy().to_string();
+        let cache = new_file_cache(&dir, 50).unwrap();
+        let obj = CacheObject {
+            meta: (b"Hello".to_vec(), b"World".to_vec()),
+            body: Bytes::from_static(b"Hello World!"),
+        };
+        cache.put("a".to_string(), obj.clone(), 1).await.unwrap();
+        cache.put("b".to_string(), obj.clone(), 1).await.unwrap();
+        assert_eq!(Some((1, 30)), cache.stats().unwrap().index);
+        assert_eq!(false, std::path::Path::new(&dir).join("a").exists());
+        assert_eq!(obj, cache.get("b").await.unwrap().unwrap());
+    }
+
     #[test]
     fn test_stats() {
         let dir = TempDir::new().unwrap();
         let dir = dir.into_path().to_string_lossy().to_string();
-        let cache = new_file_cache(&dir).unwrap();
+        let cache = new_file_cache(&dir, 0).unwrap();
         assert_eq!(0, cache.stats().unwrap().reading);
         assert_eq!(0, cache.stats().unwrap().writing);
     }
@@ -0,0 +1,533 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{Error, Result};
+use crate::util;
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap, HashSet};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+use tokio::fs;
+use tracing::{error, info};
+use walkdir::WalkDir;
+
+/// The file name of index, it's stored in the cache directory.
+pub const INDEX_FILE_NAME: &str = ".pingap_index.json";
+
+/// The journal of keys which are added or removed after the index is saved,
+/// only these files are checked when loading the index.
+pub const INDEX_JOURNAL_FILE_NAME: &str = ".pingap_index.journal";
+
+// the access time is only updated if it's older than the interval,
+// so the hit of hot object doesn't modify the index every time
+const TOUCH_INTERVAL_MS: u64 = 60 * 1000;
+
+/// The index entry of cache file.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FileIndexEntry {
+    pub path: String,
+    pub size: u64,
+    // the expired time(seconds) of cache object,
+    // it's zero if unknown
+    pub expired_at: u64,
+    // the last access time(ms) of cache object
+    pub accessed_at: u64,
+}
+
+#[derive(Default)]
+struct IndexInner {
+    entries: HashMap<String, FileIndexEntry>,
+    // the entries sorted by last access time
+    lru: BTreeSet<(u64, String)>,
+    size: u64,
+    // the appending journal file, it's truncated after the index is saved
+    journal: Option<std::fs::File>,
+    // the keys written to journal after the index is saved
+    journal_keys: Vec<String>,
+}
+
+impl IndexInner {
+    fn insert(&mut self, key: String, entry: FileIndexEntry) {
+        self.remove(&key);
+        self.size += entry.size;
+        self.lru.insert((entry.accessed_at, key.clone()));
+        self.entries.insert(key, entry);
+    }
+    fn remove(&mut self, key: &str) -> Option<FileIndexEntry> {
+        let entry = self.entries.remove(key)?;
+        self.size -= entry.size;
+        self.lru.remove(&(entry.accessed_at, key.to_string()));
+        Some(entry)
+    }
+    fn append_journal(&mut self, key: &str) {
+        if let Some(file) = self.journal.as_mut() {
+            if let Err(e) = writeln!(file, "{key}") {
+                error!(err = e.to_string(), "write file cache journal fail");
+            }
+        }
+        self.journal_keys.push(key.to_string());
+    }
+}
+
+/// The on-disk index of file cache, it's loaded at startup,
+/// so the cache keeps accurate and bounded without a full directory scan.
+pub struct FileIndex {
+    file: PathBuf,
+    max_size: u64,
+    inner: Mutex<IndexInner>,
+    dirty: AtomicBool,
+}
+
+static FILE_INDEXES: Lazy<Mutex<HashMap<String, Arc<FileIndex>>>> =
+    Lazy::new(|| Mutex::new(HashMap::new()));
+
+/// Get the shared index of cache directory, it will be loaded
+/// from the index file or the directory if not exists.
+pub fn get_file_index(dir: &str, max_size: u64) -> Result<Arc<FileIndex>> {
+    let mut indexes = FILE_INDEXES.lock().map_err(|e| Error::Invalid {
+        message: e.to_string(),
+    })?;
+    if let Some(index) = indexes.get(dir) {
+        return Ok(index.clone());
+    }
+    let index = Arc::new(FileIndex::load(dir, max_size)?);
+    indexes.insert(dir.to_string(), index.clone());
+    Ok(index)
+}
+
+#[inline]
+fn now_ms() -> u64 {
+    util::now().as_millis() as u64
+}
+
+fn new_entry_from_metadata(
+    path: &Path,
+    metadata: &std::fs::Metadata,
+) -> FileIndexEntry {
+    let accessed_at = metadata
+        .accessed()
+        .ok()
+        .and_then(|t| t.duration_since(SystemTime::UNIX_EPOCH).ok())
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default();
+    FileIndexEntry {
+        path: path.to_string_lossy().to_string(),
+        size: metadata.len(),
+        expired_at: 0,
+        accessed_at,
+    }
+}
+
+/// Add all the cache files of directory to index,
+/// it's only used if the index file doesn't exist or is broken.
+fn add_dir_files(inner: &mut IndexInner, dir: &str) {
+    for entry in WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+    {
+        let key = entry.file_name().to_string_lossy().to_string();
+        // the index, journal and temporary files
+        if key.starts_with('.') {
+            continue;
+        }
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        inner.insert(key, new_entry_from_metadata(entry.path(), &metadata));
+    }
+}
+
+/// Check the files of keys in journal, the missing files are removed
+/// from index and the others are updated.
+/// Returns the count of changed entries.
+fn reconcile_journal(
+    inner: &mut IndexInner,
+    dir: &str,
+    journal: &Path,
+) -> usize {
+    let Ok(data) = std::fs::read_to_string(journal) else {
+        return 0;
+    };
+    let keys: HashSet<&str> = data
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('.'))
+        .collect();
+    let mut count = 0;
+    for key in keys {
+        let path = Path::new(dir).join(key);
+        match std::fs::metadata(&path) {
+            Ok(metadata) if metadata.is_file() => {
+                let size = inner.entries.get(key).map(|entry| entry.size);
+                if size != Some(metadata.len()) {
+                    inner.insert(
+                        key.to_string(),
+                        new_entry_from_metadata(&path, &metadata),
+                    );
+                    count += 1;
+                }
+            },
+            _ => {
+                if inner.remove(key).is_some() {
+                    count += 1;
+                }
+            },
+        }
+    }
+    count
+}
+
+impl FileIndex {
+    fn load(dir: &str, max_size: u64) -> Result<Self> {
+        let file = Path::new(dir).join(INDEX_FILE_NAME);
+        let journal = Path::new(dir).join(INDEX_JOURNAL_FILE_NAME);
+        let mut inner = IndexInner::default();
+        let entries = match std::fs::read(&file) {
+            Ok(buf) => {
+                serde_json::from_slice::<HashMap<String, FileIndexEntry>>(&buf)
+                    .map_err(|e| {
+                        error!(
+                            err = e.to_string(),
+                            file = file.to_string_lossy().to_string(),
+                            "parse file cache index fail, rebuild it"
+                        );
+                    })
+                    .ok()
+            },
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(Error::Io { source: e }),
+        };
+        let dirty = if let Some(entries) = entries {
+            for (key, entry) in entries {
+                inner.insert(key, entry);
+            }
+            // only the files changed after the index is saved are checked
+            reconcile_journal(&mut inner, dir, &journal) > 0
+        } else {
+            // build the index from cache files at the first time
+            add_dir_files(&mut inner, dir);
+            true
+        };
+        inner.journal = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&journal)
+            .map_err(|e| {
+                error!(
+                    err = e.to_string(),
+                    file = journal.to_string_lossy().to_string(),
+                    "open file cache journal fail"
+                );
+            })
+            .ok();
+        info!(
+            dir,
+            count = inner.entries.len(),
+            size = inner.size,
+            "load file cache index"
+        );
+        Ok(Self {
+            file,
+            max_size,
+            inner: Mutex::new(inner),
+            dirty: AtomicBool::new(dirty),
+        })
+    }
+    /// Update the last access time of entry if it's older than
+    /// the touch interval, returns false if the entry is not found.
+    pub fn touch(&self, key: &str) -> bool {
+        let Ok(mut inner) = self.inner.lock() else {
+            return false;
+        };
+        let now = now_ms();
+        match inner.entries.get(key) {
+            None => return false,
+            Some(entry) if now < entry.accessed_at + TOUCH_INTERVAL_MS => {
+                return true;
+            },
+            _ => {},
+        };
+        let Some(mut entry) = inner.remove(key) else {
+            return false;
+        };
+        entry.accessed_at = now;
+        inner.insert(key.to_string(), entry);
+        self.dirty.store(true, Ordering::Relaxed);
+        true
+    }
+    /// Insert the entry and evict the least recently used entries
+    /// if the total size is over limit, returns the evicted entries.
+    pub fn insert(
+        &self,
+        key: &str,
+        mut entry: FileIndexEntry,
+    ) -> Vec<(String, FileIndexEntry)> {
+        let mut evicted = vec![];
+        let Ok(mut inner) = self.inner.lock() else {
+            return evicted;
+        };
+        entry.accessed_at = now_ms();
+        inner.insert(key.to_string(), entry);
+        inner.append_journal(key);
+        while self.max_size > 0 && inner.size > self.max_size {
+            // the new entry is always kept
+            let Some((_, lru_key)) =
+                inner.lru.iter().find(|(_, item)| item != key).cloned()
+            else {
+                break;
+            };
+            if let Some(item) = inner.remove(&lru_key) {
+                inner.append_journal(&lru_key);
+                evicted.push((lru_key, item));
+            }
+        }
+        self.dirty.store(true, Ordering::Relaxed);
+        evicted
+    }
+    /// Remove the entry from index.
+    pub fn remove(&self, key: &str) -> Option<FileIndexEntry> {
+        let mut inner = self.inner.lock().ok()?;
+        let entry = inner.remove(key);
+        if entry.is_some() {
+            inner.append_journal(key);
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        entry
+    }
+    /// Remove the entries which are accessed or expired before the time,
+    /// returns the removed entries.
+    pub fn remove_inactive(
+        &self,
+        access_before: SystemTime,
+    ) -> Vec<(String, FileIndexEntry)> {
+        let Ok(before) = access_before.duration_since(SystemTime::UNIX_EPOCH)
+        else {
+            return vec![];
+        };
+        let Ok(mut inner) = self.inner.lock() else {
+            return vec![];
+        };
+        let before_ms = before.as_millis() as u64;
+        let keys = inner
+            .entries
+            .iter()
+            .filter(|(_, entry)| {
+                entry.accessed_at < before_ms
+                    || (entry.expired_at > 0
+                        && entry.expired_at < before.as_secs())
+            })
+            .map(|(key, _)| key.clone())
+            .collect::<Vec<_>>();
+        let removed = keys
+            .into_iter()
+            .filter_map(|key| inner.remove(&key).map(|entry| (key, entry)))
+            .collect::<Vec<_>>();
+        for (key, _) in removed.iter() {
+            inner.append_journal(key);
+        }
+        if !removed.is_empty() {
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+        removed
+    }
+    /// Returns true if the entry is in index.
+    pub fn contains(&self, key: &str) -> bool {
+        self.inner
+            .lock()
+            .map(|inner| inner.entries.contains_key(key))
+            .unwrap_or_default()
+    }
+    /// Get the count and total size of entries.
+    pub fn stats(&self) -> (usize, u64) {
+        self.inner
+            .lock()
+            .map(|inner| (inner.entries.len(), inner.size))
+            .unwrap_or_default()
+    }
+    /// Save the index to file if it's modified,
+    /// returns true if the index is written.
+    /// The entries are cloned first, so the lock isn't held when serializing.
+    /// The journal is truncated with the snapshot, and its keys are
+    /// written back if the index fails to save.
+    pub async fn save(&self) -> Result<bool> {
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(false);
+        }
+        let (entries, journal_keys) = {
+            let mut inner = self.inner.lock().map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?;
+            if let Some(Err(e)) =
+                inner.journal.as_ref().map(|file| file.set_len(0))
+            {
+                error!(err = e.to_string(), "truncate file cache journal fail");
+            }
+            (
+                inner.entries.clone(),
+                std::mem::take(&mut inner.journal_keys),
+            )
+        };
+        let tmp = self.file.with_extension("tmp");
+        let result = async {
+            let buf = serde_json::to_vec(&entries)?;
+            fs::write(&tmp, buf).await?;
+            fs::rename(&tmp, &self.file).await
+        }
+        .await;
+        if let Err(e) = result {
+            if let Ok(mut inner) = self.inner.lock() {
+                for key in journal_keys.iter() {
+                    inner.append_journal(key);
+                }
+            }
+            self.dirty.store(true, Ordering::Relaxed);
+            error!(
+                err = e.to_string(),
+                file = self.file.to_string_lossy().to_string(),
+                "save file cache index fail"
+            );
+            return Err(Error::Io { source: e });
+        }
+        Ok(true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        FileIndex, FileIndexEntry, INDEX_FILE_NAME, INDEX_JOURNAL_FILE_NAME,
+        TOUCH_INTERVAL_MS,
+    };
+    use pretty_assertions::assert_eq;
+    use std::path::Path;
+    use std::time::{Duration, SystemTime};
+    use tempfile::TempDir;
+
+    fn new_entry(size: u64) -> FileIndexEntry {
+        FileIndexEntry {
+            size,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn test_file_index() {
+        let dir = TempDir::new().unwrap();
+        let dir = dir.into_path().to_string_lossy().to_string();
+        std::fs::write(format!("{dir}/a"), b"Hello").unwrap();
+
+        // build from directory
+        let index = FileIndex::load(&dir, 10).unwrap();
+        assert_eq!((1, 5), index.stats());
+        assert_eq!(true, index.save().await.unwrap());
+        assert_eq!(false, index.save().await.unwrap());
+        assert_eq!(
+            true,
+            std::path::Path::new(&dir).join(INDEX_FILE_NAME).exists()
+        );
+
+        // evict the least recently used entry
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(true, index.insert("b", new_entry(3)).is_empty());
+        // the access time of "a" is old enough to be updated
+        {
+            let mut inner = index.inner.lock().unwrap();
+            let mut entry = inner.remove("a").unwrap();
+            entry.accessed_at -= 2 * TOUCH_INTERVAL_MS;
+            inner.insert("a".to_string(), entry);
+        }
+        std::thread::sleep(Duration::from_millis(2));
+        assert_eq!(true, index.touch("a"));
+        assert_eq!(false, index.touch("c"));
+        let evicted = index.insert("c", new_entry(4));
+        assert_eq!(
+            vec!["b".to_string()],
+            evicted.into_iter().map(|item| item.0).collect::<Vec<_>>()
+        );
+        assert_eq!((2, 9), index.stats());
+        assert_eq!(true, index.remove("c").is_some());
+        assert_eq!((1, 5), index.stats());
+        index.save().await.unwrap();
+
+        // touch within the interval doesn't modify the index
+        assert_eq!(true, index.touch("a"));
+        assert_eq!(false, index.save().await.unwrap());
+
+        // load from index file
+        let index = FileIndex::load(&dir, 10).unwrap();
+        assert_eq!((1, 5), index.stats());
+        assert_eq!(false, index.save().await.unwrap());
+        let removed = index.remove_inactive(
+            SystemTime::now()
+                .checked_add(Duration::from_secs(1))
+                .unwrap(),
+        );
+        assert_eq!(1, removed.len());
+        assert_eq!((0, 0), index.stats());
+    }
+
+    #[tokio::test]
+    async fn test_file_index_reconcile() {
+        let dir = TempDir::new().unwrap();
+        let dir = dir.into_path().to_string_lossy().to_string();
+        std::fs::write(format!("{dir}/a"), b"Hello").unwrap();
+        std::fs::write(format!("{dir}/b"), b"Pingap").unwrap();
+        let index = FileIndex::load(&dir, 0).unwrap();
+        assert_eq!((2, 11), index.stats());
+        assert_eq!(true, index.save().await.unwrap());
+
+        // the files are changed after the index is saved,
+        // e.g. the server exits without saving index
+        std::fs::write(format!("{dir}/c"), b"World!").unwrap();
+        index.insert("c", new_entry(6));
+        std::fs::remove_file(format!("{dir}/a")).unwrap();
+        index.remove("a");
+        // the file isn't in journal, so it's not checked
+        std::fs::write(format!("{dir}/d"), b"Hello").unwrap();
+        drop(index);
+
+        let index = FileIndex::load(&dir, 0).unwrap();
+        assert_eq!(true, index.contains("b"));
+        assert_eq!(true, index.contains("c"));
+        assert_eq!((2, 12), index.stats());
+        assert_eq!(true, index.save().await.unwrap());
+        assert_eq!(
+            0,
+            std::fs::metadata(Path::new(&dir).join(INDEX_JOURNAL_FILE_NAME))
+                .unwrap()
+                .len()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_file_index_rebuild() {
+        let dir = TempDir::new().unwrap();
+        let dir = dir.into_path().to_string_lossy().to_string();
+        std::fs::write(format!("{dir}/a"), b"Hello").unwrap();
+        std::fs::write(Path::new(&dir).join(INDEX_FILE_NAME), b"{broken")
+            .unwrap();
+
+        // the broken index is rebuilt from cache files
+        let index = FileIndex::load(&dir, 0).unwrap();
+        assert_eq!((1, 5), index.stats());
+        assert_eq!(true, index.save().await.unwrap());
+        let index = FileIndex::load(&dir, 0).unwrap();
+        assert_eq!((1, 5), index.stats());
+    }
+}
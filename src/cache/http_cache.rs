@@ -12,7 +12,6 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::file;
 use super::{Error, Result};
 use crate::service::CommonServiceTask;
 use crate::service::ServiceTask;
@@ -28,10 +27,14 @@ use pingora::cache::trace::SpanHandle;
 use pingora::cache::{
     CacheKey, CacheMeta, HitHandler, MissHandler, PurgeType, Storage,
 };
+use serde::{Deserialize, Serialize};
 use std::any::Any;
-use std::sync::Arc;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Arc, RwLock};
 use std::time::{Duration, SystemTime};
-use tracing::info;
+use tracing::{error, info};
 
 type BinaryMeta = (Vec<u8>, Vec<u8>);
 
@@ -83,10 +86,22 @@ impl From<CacheObject> for Bytes {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct HttpCacheTierStats {
+    pub hit: u64,
+    pub miss: u64,
+}
+
+#[derive(Debug, Default)]
 pub struct HttpCacheStats {
     pub reading: u32,
     pub writing: u32,
+    // hit and miss stats of memory tier
+    pub memory: Option<HttpCacheTierStats>,
+    // hit and miss stats of file tier
+    pub file: Option<HttpCacheTierStats>,
+    // the count and total size of files in index
+    pub index: Option<(usize, u64)>,
 }
 
 #[async_trait]
@@ -110,6 +125,20 @@ pub trait HttpCacheStorage: Sync + Send {
     ) -> Result<(i32, i32)> {
         Ok((-1, -1))
     }
+    // flush the pending data(e.g. index) of storage to disk
+    async fn flush(&self) -> Result<()> {
+        Ok(())
+    }
+    // take the keys of objects evicted or cleared by storage itself,
+    // they are removed from the purge index
+    fn evicted(&self) -> Vec<String> {
+        vec![]
+    }
+    // returns false if the object is not in storage,
+    // it's true if the storage can't check it without reading
+    fn contains(&self, _key: &str) -> bool {
+        true
+    }
     // get reading and writing stats of storage
     fn stats(&self) -> Option<HttpCacheStats> {
         None
@@ -117,32 +146,46 @@ pub trait HttpCacheStorage: Sync + Send {
 }
 
 struct CacheStorageClearTask {
-    storage: Box<dyn HttpCacheStorage>,
+    cache: &'static HttpCache,
+    // the count of task running
+    count: AtomicU32,
 }
 
-pub fn new_file_storage_clear_service(dir: &str) -> Option<CommonServiceTask> {
-    let Ok(c) = file::new_file_cache(dir) else {
-        return None;
-    };
-    Some(CommonServiceTask::new(
-        Duration::from_secs(3600),
+// the clear task runs every minute, and clears the inactive objects per hour
+const CACHE_STORAGE_CLEAR_INTERVAL: u32 = 60;
+
+/// Create the background service of file storage, it saves the index
+/// every minute and clears the inactive files per hour.
+/// The storage of http cache is used, so they share the same state.
+pub fn new_file_storage_clear_service(
+    cache: &'static HttpCache,
+) -> CommonServiceTask {
+    CommonServiceTask::new(
+        Duration::from_secs(60),
         CacheStorageClearTask {
-            storage: Box::new(c),
+            cache,
+            count: AtomicU32::new(0),
         },
-    ))
+    )
 }
 
 #[async_trait]
 impl ServiceTask for CacheStorageClearTask {
     async fn run(&self) -> Option<bool> {
+        if let Err(e) = self.cache.flush().await {
+            error!(error = e.to_string(), "flush cache storage fail");
+        }
+        let count = self.count.fetch_add(1, Ordering::Relaxed);
+        if count % CACHE_STORAGE_CLEAR_INTERVAL != 0 {
+            return Some(false);
+        }
         let Some(access_before) =
             SystemTime::now().checked_sub(Duration::from_secs(24 * 3600))
         else {
             return Some(false);
         };
 
-        let Ok((success, fail)) = self.storage.clear(access_before).await
-        else {
+        let Ok((success, fail)) = self.cache.clear(access_before).await else {
             return Some(false);
         };
         if success < 0 {
@@ -152,20 +195,294 @@ impl ServiceTask for CacheStorageClearTask {
 
         Some(false)
     }
+    async fn shutdown(&self) {
+        // save the index before the server exits
+        if let Err(e) = self.cache.flush().await {
+            error!(error = e.to_string(), "flush cache storage fail");
+        }
+    }
     fn description(&self) -> String {
         "CacheStorageClear".to_string()
     }
 }
 
+/// The file name of purge index, it's stored in the cache directory.
+const PURGE_INDEX_FILE_NAME: &str = ".pingap_purge_index.json";
+
+/// The index entry of cache object, it's used for bulk purge.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+struct CacheIndexEntry {
+    // the cache prefix(namespace and headers) without method
+    prefix: String,
+    uri: String,
+    tags: Vec<String>,
+    // the key of primary object, it's different from the key of variant
+    primary: String,
+}
+
+/// The params of bulk purge, all the specified conditions should be matched.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct CachePurgeParams {
+    // the path prefix, `*` is supported as wildcard, e.g. `/static/*.js`
+    pub prefix: Option<String>,
+    // the tag of `Cache-Tag` or `Surrogate-Key` response header
+    pub tag: Option<String>,
+    // the namespace of cache plugin
+    pub namespace: Option<String>,
+}
+
+/// Match the value with wildcard pattern, `*` matches any characters.
+fn is_wildcard_matched(pattern: &str, value: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        return rest.is_empty();
+    };
+    for part in middle {
+        let Some(index) = rest.find(part) else {
+            return false;
+        };
+        rest = &rest[index + part.len()..];
+    }
+    rest.ends_with(last)
+}
+
+impl CachePurgeParams {
+    fn is_empty(&self) -> bool {
+        self.prefix.is_none() && self.tag.is_none() && self.namespace.is_none()
+    }
+    fn matched(&self, entry: &CacheIndexEntry) -> bool {
+        if let Some(prefix) = &self.prefix {
+            let path = entry
+                .uri
+                .parse::<http::Uri>()
+                .map(|uri| uri.path().to_string())
+                .unwrap_or_else(|_| entry.uri.clone());
+            let matched = if prefix.contains('*') {
+                is_wildcard_matched(prefix, &path)
+            } else {
+                path.starts_with(prefix)
+            };
+            if !matched {
+                return false;
+            }
+        }
+        if let Some(tag) = &self.tag {
+            if !entry.tags.contains(tag) {
+                return false;
+            }
+        }
+        if let Some(namespace) = &self.namespace {
+            if entry.prefix != *namespace
+                && !entry.prefix.starts_with(&format!("{namespace}:"))
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Get the tags from `Cache-Tag`(comma separated)
+/// and `Surrogate-Key`(space separated) headers.
+fn get_cache_tags(headers: &http::HeaderMap) -> Vec<String> {
+    let mut tags = vec![];
+    for value in headers.get_all("Cache-Tag").iter() {
+        let value = value.to_str().unwrap_or_default();
+        tags.extend(value.split(',').map(|item| item.trim().to_string()));
+    }
+    for value in headers.get_all("Surrogate-Key").iter() {
+        let value = value.to_str().unwrap_or_default();
+        tags.extend(value.split_whitespace().map(|item| item.to_string()));
+    }
+    tags.retain(|item| !item.is_empty());
+    tags.sort_unstable();
+    tags.dedup();
+    tags
+}
+
+#[derive(Default)]
+struct CacheIndex {
+    entries: RwLock<HashMap<String, CacheIndexEntry>>,
+    // the file of persisted index, it's none for memory cache
+    file: Option<PathBuf>,
+    dirty: AtomicBool,
+}
+
+impl CacheIndex {
+    /// Load the index from file, the entries whose object
+    /// is not in storage are ignored.
+    fn load(file: PathBuf, cached: &dyn HttpCacheStorage) -> Self {
+        let mut entries: HashMap<String, CacheIndexEntry> =
+            match std::fs::read(&file) {
+                Ok(buf) => serde_json::from_slice(&buf).unwrap_or_else(|e| {
+                    error!(
+                        error = e.to_string(),
+                        file = file.to_string_lossy().to_string(),
+                        "parse cache purge index fail"
+                    );
+                    HashMap::new()
+                }),
+                Err(_) => HashMap::new(),
+            };
+        let count = entries.len();
+        entries.retain(|key, _| cached.contains(key));
+        info!(
+            count = entries.len(),
+            removed = count - entries.len(),
+            "load cache purge index"
+        );
+        Self {
+            entries: RwLock::new(entries),
+            file: Some(file),
+            dirty: AtomicBool::new(false),
+        }
+    }
+    fn insert(&self, key: String, entry: CacheIndexEntry) {
+        if let Ok(mut entries) = self.entries.write() {
+            entries.insert(key, entry);
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+    /// Remove the entries of keys, the write lock is only acquired
+    /// if any of them is in index.
+    fn remove(&self, keys: &[String]) {
+        let exists = self
+            .entries
+            .read()
+            .map(|entries| keys.iter().any(|key| entries.contains_key(key)))
+            .unwrap_or_default();
+        if !exists {
+            return;
+        }
+        if let Ok(mut entries) = self.entries.write() {
+            for key in keys.iter() {
+                entries.remove(key);
+            }
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+    /// Get the keys of entries which match the filter.
+    fn find(&self, filter: impl Fn(&CacheIndexEntry) -> bool) -> Vec<String> {
+        self.entries
+            .read()
+            .map(|entries| {
+                entries
+                    .iter()
+                    .filter(|(_, entry)| filter(entry))
+                    .map(|(key, _)| key.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+    /// Save the index to file if it's modified,
+    /// the entries are cloned first to release the lock quickly.
+    async fn save(&self) -> Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let entries = self
+            .entries
+            .read()
+            .map(|entries| entries.clone())
+            .unwrap_or_default();
+        let buf = serde_json::to_vec(&entries).map_err(|e| Error::Invalid {
+            message: e.to_string(),
+        })?;
+        let tmp = file.with_extension("tmp");
+        let result = async {
+            tokio::fs::write(&tmp, buf).await?;
+            tokio::fs::rename(&tmp, file).await
+        }
+        .await;
+        if let Err(e) = result {
+            self.dirty.store(true, Ordering::Relaxed);
+            return Err(Error::Io { source: e });
+        }
+        Ok(())
+    }
+}
+
 pub struct HttpCache {
     pub(crate) cached: Arc<dyn HttpCacheStorage>,
+    // the index of cache objects for bulk purge
+    index: Arc<CacheIndex>,
 }
 
 impl HttpCache {
+    pub fn new(cached: Arc<dyn HttpCacheStorage>) -> Self {
+        Self {
+            cached,
+            index: Default::default(),
+        }
+    }
+    /// Persist the purge index to the directory,
+    /// it's loaded now and saved when the cache is flushed.
+    pub fn with_index_directory(mut self, dir: &str) -> Self {
+        let file = PathBuf::from(crate::util::resolve_path(dir))
+            .join(PURGE_INDEX_FILE_NAME);
+        self.index = Arc::new(CacheIndex::load(file, self.cached.as_ref()));
+        self
+    }
     #[inline]
     pub fn stats(&self) -> Option<HttpCacheStats> {
         self.cached.stats()
     }
+    /// Remove the index of objects evicted by storage.
+    fn remove_evicted(&self) {
+        let evicted = self.cached.evicted();
+        if !evicted.is_empty() {
+            self.index.remove(&evicted);
+        }
+    }
+    /// Flush the pending data of storage and save the purge index.
+    pub async fn flush(&self) -> Result<()> {
+        self.remove_evicted();
+        let result = self.cached.flush().await;
+        self.index.save().await?;
+        result
+    }
+    /// Clear the inactive objects of storage and remove their index.
+    pub async fn clear(&self, access_before: SystemTime) -> Result<(i32, i32)> {
+        let result = self.cached.clear(access_before).await;
+        self.remove_evicted();
+        result
+    }
+    /// Remove the cache object and its index,
+    /// the variants of the object are also removed.
+    pub async fn remove(&self, key: &str) -> Result<Option<CacheObject>> {
+        let variants = self.index.find(|entry| entry.primary == key);
+        let mut keys = variants.clone();
+        keys.push(key.to_string());
+        self.index.remove(&keys);
+        for key in variants.iter() {
+            let _ = self.cached.remove(key).await;
+        }
+        self.cached.remove(key).await
+    }
+    /// Purge the cache objects which match the params,
+    /// returns the count of purged objects.
+    pub async fn purge(&self, params: &CachePurgeParams) -> Result<usize> {
+        if params.is_empty() {
+            return Err(Error::Invalid {
+                message: "prefix, tag or namespace is required".to_string(),
+            });
+        }
+        let keys = self.index.find(|entry| params.matched(entry));
+        self.index.remove(&keys);
+        for key in keys.iter() {
+            // the object may be evicted, so ignore the error
+            let _ = self.cached.remove(key).await;
+        }
+        Ok(keys.len())
+    }
 }
 
 pub struct CompleteHit {
@@ -243,6 +560,8 @@ pub struct ObjectMissHandler {
     // these are used only in finish() to data from temp to cache
     key: String,
     cache: Arc<dyn HttpCacheStorage>,
+    index_entry: CacheIndexEntry,
+    index: Arc<CacheIndex>,
 }
 
 #[async_trait]
@@ -261,7 +580,7 @@ impl HandleMiss for ObjectMissHandler {
         let _ = self
             .cache
             .put(
-                self.key,
+                self.key.clone(),
                 CacheObject {
                     meta: self.meta,
                     body: self.body.into(),
@@ -269,12 +588,21 @@ impl HandleMiss for ObjectMissHandler {
                 get_wegiht(size),
             )
             .await?;
+        let evicted = self.cache.evicted();
+        // the object may not be admitted by storage
+        let admitted = !evicted.contains(&self.key);
+        if !evicted.is_empty() {
+            self.index.remove(&evicted);
+        }
+        if admitted {
+            self.index.insert(self.key, self.index_entry);
+        }
 
         Ok(size)
     }
 }
 
-fn get_wegiht(size: usize) -> u16 {
+pub(super) fn get_wegiht(size: usize) -> u16 {
     if size < 50 * 1024 {
         return 4;
     }
@@ -292,19 +620,20 @@ impl Storage for HttpCache {
         _trace: &SpanHandle,
     ) -> pingora::Result<Option<(CacheMeta, HitHandler)>> {
         let hash = key.combined();
-        if let Some(obj) = self.cached.get(&hash).await? {
-            let meta = CacheMeta::deserialize(&obj.meta.0, &obj.meta.1)?;
-            let size = obj.body.len();
-            let hit_handler = CompleteHit {
-                body: obj.body,
-                done: false,
-                range_start: 0,
-                range_end: size,
-            };
-            Ok(Some((meta, Box::new(hit_handler))))
-        } else {
-            Ok(None)
-        }
+        let Some(obj) = self.cached.get(&hash).await? else {
+            // the object is evicted, remove its index
+            self.index.remove(&[hash]);
+            return Ok(None);
+        };
+        let meta = CacheMeta::deserialize(&obj.meta.0, &obj.meta.1)?;
+        let size = obj.body.len();
+        let hit_handler = CompleteHit {
+            body: obj.body,
+            done: false,
+            range_start: 0,
+            range_end: size,
+        };
+        Ok(Some((meta, Box::new(hit_handler))))
     }
 
     async fn get_miss_handler(
@@ -327,12 +656,24 @@ impl Storage for HttpCache {
             capacity
         };
         let hash = key.combined();
+        let index_entry = CacheIndexEntry {
+            prefix: key
+                .namespace()
+                .split_once(':')
+                .map(|(_, prefix)| prefix.to_string())
+                .unwrap_or_default(),
+            uri: key.primary_key().to_string(),
+            tags: get_cache_tags(meta.headers()),
+            primary: key.primary(),
+        };
         let meta = meta.serialize()?;
         let miss_handler = ObjectMissHandler {
             meta,
             key: hash,
             cache: self.cached.clone(),
             body: BytesMut::with_capacity(size),
+            index_entry,
+            index: self.index.clone(),
         };
         Ok(Box::new(miss_handler))
     }
@@ -346,8 +687,7 @@ impl Storage for HttpCache {
         // This usually purges the primary key because, without a lookup,
         // the variance key is usually empty
         let hash = key.combined();
-        let cache_removed = if let Ok(result) = self.cached.remove(&hash).await
-        {
+        let cache_removed = if let Ok(result) = self.remove(&hash).await {
             result.is_some()
         } else {
             false
@@ -386,12 +726,42 @@ impl Storage for HttpCache {
 
 #[cfg(test)]
 mod tests {
-    use super::{CompleteHit, HttpCacheStorage, ObjectMissHandler};
+    use super::{
+        get_cache_tags, is_wildcard_matched, CacheIndexEntry, CachePurgeParams,
+        CompleteHit, HttpCache, HttpCacheStorage, ObjectMissHandler,
+    };
+    use crate::cache::file::new_file_cache;
     use crate::cache::tiny::new_tiny_ufo_cache;
     use bytes::{Bytes, BytesMut};
     use pingora::cache::storage::{HitHandler, MissHandler};
     use pretty_assertions::assert_eq;
     use std::sync::Arc;
+    use tempfile::TempDir;
+
+    async fn put_object(
+        cache: &HttpCache,
+        key: &str,
+        prefix: &str,
+        uri: &str,
+        tags: &[&str],
+        primary: &str,
+    ) {
+        let obj = ObjectMissHandler {
+            meta: (b"Hello".to_vec(), b"World".to_vec()),
+            body: BytesMut::from(&b"Hello World!"[..]),
+            key: key.to_string(),
+            cache: cache.cached.clone(),
+            index_entry: CacheIndexEntry {
+                prefix: prefix.to_string(),
+                uri: uri.to_string(),
+                tags: tags.iter().map(|tag| tag.to_string()).collect(),
+                primary: primary.to_string(),
+            },
+            index: cache.index.clone(),
+        };
+        let handle: MissHandler = Box::new(obj);
+        handle.finish().await.unwrap();
+    }
 
     #[tokio::test]
     async fn test_complete_hit() {
@@ -424,6 +794,8 @@ mod tests {
             body: BytesMut::new(),
             key: key.to_string(),
             cache: cache.clone(),
+            index_entry: CacheIndexEntry::default(),
+            index: Default::default(),
         };
         let mut handle: MissHandler = Box::new(obj);
 
@@ -436,4 +808,132 @@ mod tests {
         let data = cache.get(key).await.unwrap().unwrap();
         assert_eq!("Hello World!", std::str::from_utf8(&data.body).unwrap());
     }
+
+    #[test]
+    fn test_get_cache_tags() {
+        let mut headers = http::HeaderMap::new();
+        headers.insert("Cache-Tag", "product, list,".parse().unwrap());
+        headers.append("Cache-Tag", "home".parse().unwrap());
+        headers.insert("Surrogate-Key", "user-1 home user-2".parse().unwrap());
+        assert_eq!(
+            vec!["home", "list", "product", "user-1", "user-2"],
+            get_cache_tags(&headers)
+        );
+    }
+
+    #[test]
+    fn test_is_wildcard_matched() {
+        assert_eq!(true, is_wildcard_matched("/static/*.js", "/static/a.js"));
+        assert_eq!(
+            true,
+            is_wildcard_matched("/static/*.js", "/static/js/app.js")
+        );
+        assert_eq!(false, is_wildcard_matched("/static/*.js", "/static/a.css"));
+        assert_eq!(true, is_wildcard_matched("/*/users/*", "/v1/users/1"));
+        assert_eq!(false, is_wildcard_matched("/*/users/*", "/v1/user/1"));
+        assert_eq!(true, is_wildcard_matched("/api", "/api"));
+        assert_eq!(false, is_wildcard_matched("/api", "/api/1"));
+    }
+
+    #[tokio::test]
+    async fn test_http_cache_purge() {
+        let cache = HttpCache::new(Arc::new(new_tiny_ufo_cache(100, 100)));
+        let items = [
+            ("1", "ns:", "/static/app.js", "static"),
+            ("2", "ns:", "/static/app.css?v=1", "static"),
+            ("3", "", "/api/users", "user"),
+            ("4", "other:", "/api/products", "product"),
+        ];
+        for (key, prefix, uri, tag) in items {
+            put_object(&cache, key, prefix, uri, &[tag], key).await;
+        }
+
+        assert_eq!(
+            "prefix, tag or namespace is required",
+            cache
+                .purge(&CachePurgeParams::default())
+                .await
+                .unwrap_err()
+                .to_string()
+        );
+        let count = cache
+            .purge(&CachePurgeParams {
+                prefix: Some("/static/*.css".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(1, count);
+        assert_eq!(true, cache.cached.get("2").await.unwrap().is_none());
+
+        let count = cache
+            .purge(&CachePurgeParams {
+                tag: Some("static".to_string()),
+                namespace: Some("ns".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(1, count);
+        assert_eq!(true, cache.cached.get("1").await.unwrap().is_none());
+
+        let count = cache
+            .purge(&CachePurgeParams {
+                prefix: Some("/api".to_string()),
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(2, count);
+        assert_eq!(true, cache.index.entries.read().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_http_cache_index_evicted() {
+        let cache = HttpCache::new(Arc::new(new_tiny_ufo_cache(10, 10)));
+        let keys: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        for key in keys.iter() {
+            put_object(&cache, key, "", &format!("/{key}"), &[], key).await;
+        }
+        let mut cached = 0;
+        for key in keys.iter() {
+            if cache.cached.get(key).await.unwrap().is_some() {
+                cached += 1;
+            }
+        }
+        assert_eq!(true, cached < keys.len());
+        assert_eq!(cached, cache.index.entries.read().unwrap().len());
+    }
+
+    #[tokio::test]
+    async fn test_http_cache_index_file() {
+        let dir = TempDir::new().unwrap();
+        let dir = dir.into_path().to_string_lossy().to_string();
+        let cache = HttpCache::new(Arc::new(new_file_cache(&dir, 0).unwrap()))
+            .with_index_directory(&dir);
+        put_object(&cache, "a", "", "/a", &[], "a").await;
+        put_object(&cache, "b", "", "/b", &[], "b").await;
+        cache.flush().await.unwrap();
+
+        // the object removed from storage is ignored when loading
+        cache.cached.remove("b").await.unwrap();
+        let cache = HttpCache::new(Arc::new(new_file_cache(&dir, 0).unwrap()))
+            .with_index_directory(&dir);
+        let entries = cache.index.entries.read().unwrap().clone();
+        assert_eq!(1, entries.len());
+        assert_eq!("/a", entries.get("a").unwrap().uri);
+    }
+
+    #[tokio::test]
+    async fn test_http_cache_remove_variants() {
+        let cache = HttpCache::new(Arc::new(new_tiny_ufo_cache(100, 100)));
+        for (key, primary) in [("1", "1"), ("2", "1"), ("3", "3")] {
+            put_object(&cache, key, "", "/", &[], primary).await;
+        }
+        cache.remove("1").await.unwrap();
+        assert_eq!(true, cache.cached.get("1").await.unwrap().is_none());
+        assert_eq!(true, cache.cached.get("2").await.unwrap().is_none());
+        assert_eq!(true, cache.cached.get("3").await.unwrap().is_some());
+        assert_eq!(1, cache.index.entries.read().unwrap().len());
+    }
 }
@@ -17,8 +17,12 @@ use snafu::Snafu;
 use std::sync::Arc;
 
 mod file;
+mod file_index;
 mod http_cache;
+mod shard;
+mod tiered;
 mod tiny;
+mod vary;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -38,21 +42,36 @@ impl From<Error> for pingora::BError {
 }
 
 pub fn new_tiny_ufo_cache(size: usize) -> HttpCache {
-    HttpCache {
-        cached: Arc::new(tiny::new_tiny_ufo_cache(size / 1024, size / 1024)),
-    }
+    HttpCache::new(Arc::new(tiny::new_tiny_ufo_cache(size / 1024, size / 1024)))
+}
+/// Create a file cache, the objects are sharded to the directories
+/// by consistent hashing if more than one directory.
+pub fn new_file_cache(directories: &[(String, u64)]) -> Result<HttpCache> {
+    let cache =
+        HttpCache::new(Arc::from(shard::new_file_storage(directories, true)?));
+    Ok(cache.with_index_directory(&directories[0].0))
 }
-pub fn new_file_cache(dir: &str) -> Result<HttpCache> {
-    Ok(HttpCache {
-        cached: Arc::new(file::new_file_cache(dir)?),
-    })
+
+/// Create a tiered cache, hot objects are served from memory
+/// and all objects are stored in file.
+pub fn new_tiered_cache(
+    directories: &[(String, u64)],
+    memory_size: usize,
+) -> Result<HttpCache> {
+    let file = shard::new_file_storage(directories, false)?;
+    let cache =
+        HttpCache::new(Arc::new(tiered::new_tiered_cache(file, memory_size)));
+    Ok(cache.with_index_directory(&directories[0].0))
 }
 
-pub use http_cache::{new_file_storage_clear_service, HttpCache};
+pub use http_cache::{
+    new_file_storage_clear_service, CachePurgeParams, HttpCache,
+};
+pub use vary::VaryRules;
 
 #[cfg(test)]
 mod tests {
-    use super::{new_file_cache, new_tiny_ufo_cache, Error};
+    use super::{new_file_cache, new_tiered_cache, new_tiny_ufo_cache, Error};
     use pretty_assertions::assert_eq;
     use tempfile::TempDir;
 
@@ -75,7 +94,10 @@ mod tests {
         let _ = new_tiny_ufo_cache(1024);
 
         let dir = TempDir::new().unwrap();
-        let result = new_file_cache(&dir.into_path().to_string_lossy());
+        let dir = dir.into_path().to_string_lossy().to_string();
+        let result = new_file_cache(&[(dir.clone(), 0)]);
+        assert_eq!(true, result.is_ok());
+        let result = new_tiered_cache(&[(dir.clone(), 0)], 1024 * 1024);
         assert_eq!(true, result.is_ok());
     }
 }
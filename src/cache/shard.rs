@@ -0,0 +1,498 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::file::{new_file_cache, new_file_cache_without_memory, FileCache};
+use super::http_cache::{CacheObject, HttpCacheStats, HttpCacheStorage};
+use super::{Error, Result};
+use crate::{util, webhook};
+use async_trait::async_trait;
+use std::path::Path;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::SystemTime;
+use tracing::{error, info};
+
+// the failed directory is probed again after the interval(seconds)
+const SHARD_RETRY_INTERVAL: u32 = 60;
+
+struct Shard {
+    directory: String,
+    // the weight of consistent hashing
+    weight: f64,
+    cache: FileCache,
+    // the time(super timestamp) of failure, 0 means available
+    failed_at: AtomicU32,
+}
+
+impl Shard {
+    fn is_failed(&self) -> bool {
+        self.failed_at.load(Ordering::Relaxed) != 0
+    }
+}
+
+/// Check whether the io error means the disk is unavailable,
+/// the transient errors(e.g. too many open files, disk full)
+/// don't fail the directory.
+fn is_disk_failure(err: &Error) -> bool {
+    let Error::Io { source } = err else {
+        return false;
+    };
+    // ENFILE, EMFILE, ENOSPC
+    if matches!(source.raw_os_error(), Some(23 | 24 | 28)) {
+        return false;
+    }
+    !matches!(
+        source.kind(),
+        std::io::ErrorKind::WouldBlock
+            | std::io::ErrorKind::TimedOut
+            | std::io::ErrorKind::Interrupted
+    )
+}
+
+/// Check the directory exists and is writable.
+async fn probe_directory(directory: &str) -> std::io::Result<()> {
+    let file = Path::new(directory).join(".pingap_probe");
+    tokio::fs::write(&file, b"pingap").await?;
+    tokio::fs::remove_file(&file).await
+}
+
+/// The file cache sharded to multiple directories(disks),
+/// the key is placed by weighted rendezvous hashing,
+/// so only the keys of failed directory are moved.
+pub struct ShardedFileCache {
+    shards: Vec<Shard>,
+}
+
+/// Create the file storage of directories, it's a sharded file cache
+/// if more than one directory.
+pub fn new_file_storage(
+    directories: &[(String, u64)],
+    with_memory: bool,
+) -> Result<Box<dyn HttpCacheStorage>> {
+    let new_cache = |dir: &str, max_size: u64| {
+        if with_memory {
+            new_file_cache(dir, max_size)
+        } else {
+            new_file_cache_without_memory(dir, max_size)
+        }
+    };
+    match directories {
+        [] => Err(Error::Invalid {
+            message: "cache directory is required".to_string(),
+        }),
+        [(dir, max_size)] => Ok(Box::new(new_cache(dir, *max_size)?)),
+        _ => {
+            // use the max size as weight only if all directories are limited
+            let limited = directories.iter().all(|(_, size)| *size > 0);
+            let mut shards = vec![];
+            for (dir, max_size) in directories {
+                shards.push(Shard {
+                    directory: dir.clone(),
+                    weight: if limited { *max_size as f64 } else { 1.0 },
+                    cache: new_cache(dir, *max_size)?,
+                    failed_at: AtomicU32::new(0),
+                });
+            }
+            info!(count = shards.len(), "new sharded file cache");
+            Ok(Box::new(ShardedFileCache { shards }))
+        },
+    }
+}
+
+impl ShardedFileCache {
+    /// Select the available shard of key,
+    /// the shard with highest score is selected.
+    fn select(&self, key: &str) -> Option<&Shard> {
+        let mut selected = None;
+        let mut max_score = f64::MIN;
+        for shard in self.shards.iter() {
+            if shard.is_failed() {
+                continue;
+            }
+            let mut hasher = crc32fast::Hasher::new();
+            hasher.update(shard.directory.as_bytes());
+            hasher.update(key.as_bytes());
+            // map the hash to (0, 1)
+            let value =
+                (hasher.finalize() as f64 + 1.0) / (u32::MAX as f64 + 2.0);
+            let score = shard.weight / -value.ln();
+            if score > max_score {
+                max_score = score;
+                selected = Some(shard);
+            }
+        }
+        selected
+    }
+    /// Remove the shard if it's failed with disk error,
+    /// returns true if the shard is removed.
+    fn check_failed(&self, shard: &Shard, err: &Error) -> bool {
+        if !is_disk_failure(err) {
+            return false;
+        }
+        // the failed time should not be 0
+        let now = util::get_super_ts().max(1);
+        if shard.failed_at.swap(now, Ordering::Relaxed) == 0 {
+            error!(
+                directory = shard.directory,
+                error = err.to_string(),
+                "cache directory is removed"
+            );
+            webhook::send(webhook::SendNotificationParams {
+                category: webhook::NotificationCategory::CacheDiskFail,
+                level: webhook::NotificationLevel::Error,
+                msg: format!(
+                    "cache directory({}) is removed, {err}",
+                    shard.directory
+                ),
+                ..Default::default()
+            });
+        }
+        true
+    }
+    /// Probe the failed shards whose retry interval is reached,
+    /// the shard is available again if its directory is writable.
+    async fn recover_failed(&self) {
+        let now = util::get_super_ts();
+        for shard in self.shards.iter() {
+            let failed_at = shard.failed_at.load(Ordering::Relaxed);
+            if failed_at == 0 || now < failed_at + SHARD_RETRY_INTERVAL {
+                continue;
+            }
+            if let Err(e) = probe_directory(&shard.directory).await {
+                shard.failed_at.store(now.max(1), Ordering::Relaxed);
+                error!(
+                    directory = shard.directory,
+                    error = e.to_string(),
+                    "cache directory is still unavailable"
+                );
+                continue;
+            }
+            shard.failed_at.store(0, Ordering::Relaxed);
+            info!(directory = shard.directory, "cache directory is recovered");
+        }
+    }
+    fn available_shards(&self) -> impl Iterator<Item = &Shard> {
+        self.shards.iter().filter(|shard| !shard.is_failed())
+    }
+}
+
+fn no_available_error() -> Error {
+    Error::Invalid {
+        message: "no available cache directory".to_string(),
+    }
+}
+
+#[async_trait]
+impl HttpCacheStorage for ShardedFileCache {
+    /// Get cache object from the shard of key,
+    /// it's regarded as miss if the shard is failed.
+    async fn get(&self, key: &str) -> Result<Option<CacheObject>> {
+        let Some(shard) = self.select(key) else {
+            return Ok(None);
+        };
+        match shard.cache.get(key).await {
+            Err(e) if self.check_failed(shard, &e) => Ok(None),
+            result => result,
+        }
+    }
+    /// Put cache object to the shard of key,
+    /// it will be put to the next shard if the shard is failed.
+    async fn put(
+        &self,
+        key: String,
+        data: CacheObject,
+        weight: u16,
+    ) -> Result<()> {
+        loop {
+            let shard = self.select(&key).ok_or_else(no_available_error)?;
+            match shard.cache.put(key.clone(), data.clone(), weight).await {
+                Err(e) if self.check_failed(shard, &e) => continue,
+                result => return result,
+            }
+        }
+    }
+    async fn remove(&self, key: &str) -> Result<Option<CacheObject>> {
+        let Some(shard) = self.select(key) else {
+            return Ok(None);
+        };
+        shard.cache.remove(key).await
+    }
+    /// Clear the available shards, the error of one shard
+    /// doesn't stop others, and the first error is returned.
+    async fn clear(&self, access_before: SystemTime) -> Result<(i32, i32)> {
+        let mut success = 0;
+        let mut fail = 0;
+        let mut first_err = None;
+        for shard in self.available_shards() {
+            match shard.cache.clear(access_before).await {
+                Ok((s, f)) => {
+                    success += s;
+                    fail += f;
+                },
+                Err(e) => {
+                    error!(
+                        directory = shard.directory,
+                        error = e.to_string(),
+                        "clear cache directory fail"
+                    );
+                    first_err.get_or_insert(e);
+                },
+            }
+        }
+        if let Some(e) = first_err {
+            return Err(e);
+        }
+        Ok((success, fail))
+    }
+    /// Flush the available shards and probe the failed shards,
+    /// the error of one shard doesn't stop others,
+    /// and the first error is returned.
+    async fn flush(&self) -> Result<()> {
+        self.recover_failed().await;
+        let mut first_err = None;
+        for shard in self.available_shards() {
+            if let Err(e) = shard.cache.flush().await {
+                error!(
+                    directory = shard.directory,
+                    error = e.to_string(),
+                    "flush cache directory fail"
+                );
+                first_err.get_or_insert(e);
+            }
+        }
+        if let Some(e) = first_err {
+            return Err(e);
+        }
+        Ok(())
+    }
+    fn evicted(&self) -> Vec<String> {
+        self.shards
+            .iter()
+            .flat_map(|shard| shard.cache.evicted())
+            .collect()
+    }
+    fn contains(&self, key: &str) -> bool {
+        self.select(key)
+            .map(|shard| shard.cache.contains(key))
+            .unwrap_or_default()
+    }
+    /// Get the sum stats of available shards.
+    fn stats(&self) -> Option<HttpCacheStats> {
+        let mut stats = HttpCacheStats::default();
+        let mut index = (0, 0);
+        for shard in self.available_shards() {
+            let Some(item) = shard.cache.stats() else {
+                continue;
+            };
+            stats.reading += item.reading;
+            stats.writing += item.writing;
+            if let Some((count, size)) = item.index {
+                index.0 += count;
+                index.1 += size;
+            }
+        }
+        stats.index = Some(index);
+        Some(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_disk_failure, new_file_storage, Shard, ShardedFileCache,
+        SHARD_RETRY_INTERVAL,
+    };
+    use crate::cache::file::new_file_cache_without_memory;
+    use crate::cache::http_cache::{CacheObject, HttpCacheStorage};
+    use crate::cache::Error;
+    use crate::util;
+    use bytes::Bytes;
+    use pretty_assertions::assert_eq;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_is_disk_failure() {
+        let new_io_error = |source| Error::Io { source };
+        assert_eq!(
+            true,
+            is_disk_failure(&new_io_error(std::io::Error::from(
+                std::io::ErrorKind::PermissionDenied
+            )))
+        );
+        // too many open files
+        assert_eq!(
+            false,
+            is_disk_failure(&new_io_error(std::io::Error::from_raw_os_error(
+                24
+            )))
+        );
+        // no space left on device
+        assert_eq!(
+            false,
+            is_disk_failure(&new_io_error(std::io::Error::from_raw_os_error(
+                28
+            )))
+        );
+        assert_eq!(
+            false,
+            is_disk_failure(&new_io_error(std::io::Error::from(
+                std::io::ErrorKind::TimedOut
+            )))
+        );
+        assert_eq!(
+            false,
+            is_disk_failure(&Error::OverQuota {
+                max: 10,
+                message: "too many reading".to_string(),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_sharded_file_cache_recover() {
+        let dirs: Vec<String> = (0..2)
+            .map(|_| {
+                TempDir::new()
+                    .unwrap()
+                    .into_path()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        let cache = ShardedFileCache {
+            shards: dirs
+                .iter()
+                .map(|dir| Shard {
+                    directory: dir.clone(),
+                    weight: 1.0,
+                    cache: new_file_cache_without_memory(dir, 0).unwrap(),
+                    failed_at: AtomicU32::new(0),
+                })
+                .collect(),
+        };
+        let shard = &cache.shards[0];
+
+        // the transient error doesn't fail the directory
+        let err = Error::Io {
+            source: std::io::Error::from_raw_os_error(24),
+        };
+        assert_eq!(false, cache.check_failed(shard, &err));
+        assert_eq!(false, shard.is_failed());
+
+        let err = Error::Io {
+            source: std::io::Error::from(std::io::ErrorKind::NotFound),
+        };
+        assert_eq!(true, cache.check_failed(shard, &err));
+        assert_eq!(true, shard.is_failed());
+
+        // the directory is probed after the retry interval
+        std::fs::remove_dir_all(&dirs[0]).unwrap();
+        let failed_at = util::get_super_ts() - 2 * SHARD_RETRY_INTERVAL;
+        shard.failed_at.store(failed_at, Ordering::Relaxed);
+        cache.flush().await.unwrap();
+        assert_eq!(true, shard.is_failed());
+        assert_eq!(true, shard.failed_at.load(Ordering::Relaxed) > failed_at);
+
+        // the directory is available again
+        std::fs::create_dir_all(&dirs[0]).unwrap();
+        shard.failed_at.store(failed_at, Ordering::Relaxed);
+        cache.flush().await.unwrap();
+        assert_eq!(false, shard.is_failed());
+        let obj = CacheObject {
+            meta: (b"Hello".to_vec(), b"World".to_vec()),
+            body: Bytes::from_static(b"Hello World!"),
+        };
+        for i in 0..20 {
+            cache.put(format!("key{i}"), obj.clone(), 1).await.unwrap();
+        }
+        assert_eq!(true, std::fs::read_dir(&dirs[0]).unwrap().count() > 0);
+    }
+
+    #[tokio::test]
+    async fn test_sharded_file_cache() {
+        let dirs: Vec<String> = (0..2)
+            .map(|_| {
+                TempDir::new()
+                    .unwrap()
+                    .into_path()
+                    .to_string_lossy()
+                    .to_string()
+            })
+            .collect();
+        let cache = new_file_storage(
+            &dirs.iter().map(|dir| (dir.clone(), 0)).collect::<Vec<_>>(),
+            false,
+        )
+        .unwrap();
+        let obj = CacheObject {
+            meta: (b"Hello".to_vec(), b"World".to_vec()),
+            body: Bytes::from_static(b"Hello World!"),
+        };
+        for i in 0..20 {
+            cache.put(format!("key{i}"), obj.clone(), 1).await.unwrap();
+        }
+        for i in 0..20 {
+            assert_eq!(
+                obj,
+                cache.get(&format!("key{i}")).await.unwrap().unwrap()
+            );
+        }
+        // the keys are placed in both directories
+        let counts: Vec<usize> = dirs
+            .iter()
+            .map(|dir| {
+                std::fs::read_dir(dir)
+                    .unwrap()
+                    .filter(|entry| {
+                        !entry
+                            .as_ref()
+                            .unwrap()
+                            .file_name()
+                            .to_string_lossy()
+                            .starts_with('.')
+                    })
+                    .count()
+            })
+            .collect();
+        assert_eq!(20, counts.iter().sum::<usize>());
+        assert_eq!(true, counts.iter().all(|count| *count > 0));
+        assert_eq!(20, cache.stats().unwrap().index.unwrap().0);
+
+        // the failed directory is removed, and the key is put to other one
+        std::fs::remove_dir_all(&dirs[0]).unwrap();
+        for i in 0..20 {
+            cache.put(format!("key{i}"), obj.clone(), 1).await.unwrap();
+        }
+        for i in 0..20 {
+            assert_eq!(
+                obj,
+                cache.get(&format!("key{i}")).await.unwrap().unwrap()
+            );
+        }
+        assert_eq!(
+            20,
+            std::fs::read_dir(&dirs[1])
+                .unwrap()
+                .filter(|entry| {
+                    !entry
+                        .as_ref()
+                        .unwrap()
+                        .file_name()
+                        .to_string_lossy()
+                        .starts_with('.')
+                })
+                .count()
+        );
+    }
+}
@@ -0,0 +1,207 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::http_cache::{
+    get_wegiht, CacheObject, HttpCacheStats, HttpCacheStorage,
+    HttpCacheTierStats,
+};
+use super::Result;
+#[cfg(feature = "full")]
+use crate::state::CACHE_TIER_LOOKUP;
+use async_trait::async_trait;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::SystemTime;
+use tinyufo::TinyUfo;
+use tracing::info;
+
+// the object larger than it will only be stored in file
+const MEMORY_OBJECT_MAX_SIZE: usize = 500 * 1024;
+
+#[derive(Default)]
+struct TierCounter {
+    hit: AtomicU64,
+    miss: AtomicU64,
+}
+
+impl TierCounter {
+    #[inline]
+    fn observe(&self, _tier: &str, hit: bool) {
+        if hit {
+            self.hit.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.miss.fetch_add(1, Ordering::Relaxed);
+        }
+        #[cfg(feature = "full")]
+        CACHE_TIER_LOOKUP
+            .with_label_values(&[_tier, if hit { "hit" } else { "miss" }])
+            .inc();
+    }
+    fn stats(&self) -> HttpCacheTierStats {
+        HttpCacheTierStats {
+            hit: self.hit.load(Ordering::Relaxed),
+            miss: self.miss.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The two-tier cache, the hot objects are served from tinyufo
+/// and all objects are stored in file(or sharded files).
+pub struct TieredCache {
+    memory: TinyUfo<String, CacheObject>,
+    file: Box<dyn HttpCacheStorage>,
+    memory_object_max_size: usize,
+    memory_counter: TierCounter,
+    file_counter: TierCounter,
+}
+
+/// Create a tiered cache, the memory size is the weight limit of tinyufo.
+pub fn new_tiered_cache(
+    file: Box<dyn HttpCacheStorage>,
+    memory_size: usize,
+) -> TieredCache {
+    info!(memory_size, "new tiered cache");
+    TieredCache {
+        memory: TinyUfo::new(memory_size / 1024, memory_size / 1024),
+        file,
+        memory_object_max_size: MEMORY_OBJECT_MAX_SIZE,
+        memory_counter: TierCounter::default(),
+        file_counter: TierCounter::default(),
+    }
+}
+
+#[async_trait]
+impl HttpCacheStorage for TieredCache {
+    /// Get cache object from memory, if not exists, then get from file
+    /// and promote it to memory.
+    async fn get(&self, key: &str) -> Result<Option<CacheObject>> {
+        let key = key.to_string();
+        if let Some(obj) = self.memory.get(&key) {
+            self.memory_counter.observe("memory", true);
+            return Ok(Some(obj));
+        }
+        self.memory_counter.observe("memory", false);
+        let result = self.file.get(&key).await?;
+        self.file_counter.observe("file", result.is_some());
+        if let Some(obj) = &result {
+            let size = obj.body.len();
+            if size <= self.memory_object_max_size {
+                self.memory.put(key, obj.clone(), get_wegiht(size));
+            }
+        }
+        Ok(result)
+    }
+    /// Put cache object to file, and also to memory if it's small enough.
+    /// The object evicted from memory is demoted to file only.
+    async fn put(
+        &self,
+        key: String,
+        data: CacheObject,
+        weight: u16,
+    ) -> Result<()> {
+        if data.body.len() <= self.memory_object_max_size {
+            self.memory.put(key.clone(), data.clone(), weight);
+        }
+        self.file.put(key, data, weight).await
+    }
+    /// Remove cache object from memory and file,
+    /// the result of file is returned.
+    async fn remove(&self, key: &str) -> Result<Option<CacheObject>> {
+        let obj = self.memory.remove(&key.to_string());
+        let result = self.file.remove(key).await?;
+        Ok(result.or(obj))
+    }
+    async fn clear(&self, access_before: SystemTime) -> Result<(i32, i32)> {
+        self.file.clear(access_before).await
+    }
+    async fn flush(&self) -> Result<()> {
+        self.file.flush().await
+    }
+    /// The object evicted from memory is still in file,
+    /// so only the evicted of file is returned.
+    fn evicted(&self) -> Vec<String> {
+        self.file.evicted()
+    }
+    fn contains(&self, key: &str) -> bool {
+        self.file.contains(key)
+    }
+    /// Get the stats of file cache and the hit stats of each tier.
+    fn stats(&self) -> Option<HttpCacheStats> {
+        let mut stats = self.file.stats().unwrap_or_default();
+        stats.memory = Some(self.memory_counter.stats());
+        stats.file = Some(self.file_counter.stats());
+        Some(stats)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::new_tiered_cache;
+    use crate::cache::file::new_file_cache_without_memory;
+    use crate::cache::http_cache::{
+        CacheObject, HttpCacheStorage, HttpCacheTierStats,
+    };
+    use bytes::Bytes;
+    use pretty_assertions::assert_eq;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn test_tiered_cache() {
+        let dir = TempDir::new().unwrap();
+        let dir = dir.into_path().to_string_lossy().to_string();
+        let cache = new_tiered_cache(
+            Box::new(new_file_cache_without_memory(&dir, 0).unwrap()),
+            100 * 1024,
+        );
+        let key = "key".to_string();
+        let obj = CacheObject {
+            meta: (b"Hello".to_vec(), b"World".to_vec()),
+            body: Bytes::from_static(b"Hello World!"),
+        };
+        assert_eq!(true, cache.get(&key).await.unwrap().is_none());
+        cache.put(key.clone(), obj.clone(), 1).await.unwrap();
+        assert_eq!(obj, cache.get(&key).await.unwrap().unwrap());
+        let stats = cache.stats().unwrap();
+        assert_eq!(Some(HttpCacheTierStats { hit: 1, miss: 1 }), stats.memory);
+        assert_eq!(Some(HttpCacheTierStats { hit: 0, miss: 1 }), stats.file);
+
+        // the new cache has empty memory, get from file and promote it
+        let cache = new_tiered_cache(
+            Box::new(new_file_cache_without_memory(&dir, 0).unwrap()),
+            100 * 1024,
+        );
+        assert_eq!(obj, cache.get(&key).await.unwrap().unwrap());
+        assert_eq!(obj, cache.get(&key).await.unwrap().unwrap());
+        let stats = cache.stats().unwrap();
+        assert_eq!(Some(HttpCacheTierStats { hit: 1, miss: 1 }), stats.memory);
+        assert_eq!(Some(HttpCacheTierStats { hit: 1, miss: 0 }), stats.file);
+
+        // large object is only stored in file
+        let large_key = "large".to_string();
+        let large = CacheObject {
+            meta: (b"Hello".to_vec(), b"World".to_vec()),
+            body: Bytes::from(vec![0; 600 * 1024]),
+        };
+        cache
+            .put(large_key.clone(), large.clone(), 1)
+            .await
+            .unwrap();
+        assert_eq!(true, cache.memory.get(&large_key).is_none());
+        assert_eq!(large, cache.get(&large_key).await.unwrap().unwrap());
+
+        cache.remove(&key).await.unwrap();
+        assert_eq!(true, cache.get(&key).await.unwrap().is_none());
+        // the error of file is returned
+        assert_eq!(true, cache.remove(&key).await.is_err());
+    }
+}
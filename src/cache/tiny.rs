@@ -15,16 +15,22 @@
 use super::http_cache::{CacheObject, HttpCacheStorage};
 use super::Result;
 use async_trait::async_trait;
+use std::sync::{Arc, Mutex};
 use tinyufo::TinyUfo;
 
 pub struct TinyUfoCache {
-    cache: TinyUfo<String, CacheObject>,
+    // the key is stored with object, tinyufo only returns
+    // the hash of evicted key
+    cache: TinyUfo<String, (Arc<str>, CacheObject)>,
+    // the keys of evicted objects
+    evicted: Mutex<Vec<String>>,
 }
 
 impl TinyUfoCache {
     fn new(total_weight_limit: usize, estimated_size: usize) -> Self {
         Self {
             cache: TinyUfo::new(total_weight_limit, estimated_size),
+            evicted: Mutex::new(vec![]),
         }
     }
 }
@@ -41,7 +47,7 @@ pub fn new_tiny_ufo_cache(
 impl HttpCacheStorage for TinyUfoCache {
     /// Get cache object from tiny ufo storage
     async fn get(&self, key: &str) -> Result<Option<CacheObject>> {
-        Ok(self.cache.get(&key.to_string()))
+        Ok(self.cache.get(&key.to_string()).map(|(_, obj)| obj))
     }
     /// Put an object to tiny ufo storage
     async fn put(
@@ -50,10 +56,27 @@ impl HttpCacheStorage for TinyUfoCache {
         data: CacheObject,
         weight: u16,
     ) -> Result<()> {
-        self.cache.put(key, data, weight);
+        let value = (Arc::from(key.as_str()), data);
+        let evicted = self.cache.put(key, value, weight);
+        if !evicted.is_empty() {
+            if let Ok(mut keys) = self.evicted.lock() {
+                keys.extend(
+                    evicted.into_iter().map(|item| item.data.0.to_string()),
+                );
+            }
+        }
         Ok(())
     }
-    // TODO remove
+    /// Remove the object from tiny ufo storage
+    async fn remove(&self, key: &str) -> Result<Option<CacheObject>> {
+        Ok(self.cache.remove(&key.to_string()).map(|(_, obj)| obj))
+    }
+    fn evicted(&self) -> Vec<String> {
+        self.evicted
+            .lock()
+            .map(|mut evicted| std::mem::take(&mut *evicted))
+            .unwrap_or_default()
+    }
 }
 
 #[cfg(test)]
@@ -75,5 +98,9 @@ mod tests {
         cache.put(key.clone(), obj.clone(), 1).await.unwrap();
         let result = cache.get(&key).await.unwrap().unwrap();
         assert_eq!(obj, result);
+        let result = cache.remove(&key).await.unwrap().unwrap();
+        assert_eq!(obj, result);
+        let result = cache.get(&key).await.unwrap();
+        assert_eq!(true, result.is_none());
     }
 }
@@ -0,0 +1,342 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use http::header;
+use pingora::cache::key::HashBinary;
+use pingora::cache::VarianceBuilder;
+use pingora::http::{RequestHeader, ResponseHeader};
+
+/// The normalisation rule of request header for cache variant.
+#[derive(Debug, Clone, PartialEq)]
+enum VaryRule {
+    // normalise accept-encoding to br, gzip or identity
+    AcceptEncoding,
+    // only the specified cookies are used for variant
+    Cookie(Vec<String>),
+    // the lowercase value of header
+    Header(String),
+}
+
+impl VaryRule {
+    fn name(&self) -> &str {
+        match self {
+            VaryRule::AcceptEncoding => "accept-encoding",
+            VaryRule::Cookie(_) => "cookie",
+            VaryRule::Header(name) => name,
+        }
+    }
+    fn normalize(&self, req: &RequestHeader) -> Vec<u8> {
+        match self {
+            VaryRule::AcceptEncoding => {
+                normalize_accept_encoding(req).as_bytes().to_vec()
+            },
+            VaryRule::Cookie(names) => {
+                let mut values = vec![];
+                for value in req.headers.get_all(header::COOKIE).iter() {
+                    let value = value.to_str().unwrap_or_default();
+                    for item in value.split(';') {
+                        if let Some((name, value)) = item.trim().split_once('=')
+                        {
+                            if names.iter().any(|item| item == name) {
+                                values.push(format!("{name}={value}"));
+                            }
+                        }
+                    }
+                }
+                values.sort();
+                values.join(";").into_bytes()
+            },
+            VaryRule::Header(name) => get_header_values(req, name)
+                .trim()
+                .to_lowercase()
+                .into_bytes(),
+        }
+    }
+}
+
+/// Normalise the accept-encoding of request to `br`, `gzip` or `identity`.
+fn normalize_accept_encoding(req: &RequestHeader) -> &'static str {
+    let value = get_header_values(req, header::ACCEPT_ENCODING.as_str());
+    let mut br = false;
+    let mut gzip = false;
+    for item in value.split(',') {
+        let mut parts = item.split(';');
+        let encoding = parts.next().unwrap_or_default().trim();
+        let disabled = parts.any(|item| {
+            item.trim()
+                .strip_prefix("q=")
+                .and_then(|q| q.trim().parse::<f32>().ok())
+                .map(|q| q == 0.0)
+                .unwrap_or_default()
+        });
+        if disabled {
+            continue;
+        }
+        match encoding {
+            "br" => br = true,
+            "gzip" => gzip = true,
+            _ => {},
+        }
+    }
+    if br {
+        "br"
+    } else if gzip {
+        "gzip"
+    } else {
+        "identity"
+    }
+}
+
+fn get_header_values(req: &RequestHeader, name: &str) -> String {
+    req.headers
+        .get_all(name)
+        .iter()
+        .map(|value| value.to_str().unwrap_or_default())
+        .collect::<Vec<_>>()
+        .join(",")
+}
+
+/// Get the lowercase header names of `Vary` response header.
+fn get_vary_names(resp: &ResponseHeader) -> Vec<String> {
+    let mut names = vec![];
+    for value in resp.headers.get_all(header::VARY).iter() {
+        let value = value.to_str().unwrap_or_default();
+        names.extend(
+            value
+                .split(',')
+                .map(|item| item.trim().to_lowercase())
+                .filter(|item| !item.is_empty()),
+        );
+    }
+    names.sort();
+    names.dedup();
+    names
+}
+
+/// The normalisation rules of cache variants, e.g.
+/// `["Accept-Encoding", "Cookie:lang,theme", "Accept-Language"]`.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct VaryRules {
+    rules: Vec<VaryRule>,
+}
+
+impl VaryRules {
+    pub fn new(values: &[String]) -> Self {
+        let rules = values
+            .iter()
+            .filter_map(|value| {
+                let (name, args) = value
+                    .split_once(':')
+                    .map(|(name, args)| (name, Some(args)))
+                    .unwrap_or((value, None));
+                let name = name.trim().to_lowercase();
+                match name.as_str() {
+                    "" => None,
+                    "accept-encoding" => Some(VaryRule::AcceptEncoding),
+                    "cookie" => {
+                        let names = args
+                            .unwrap_or_default()
+                            .split(',')
+                            .map(|item| item.trim().to_string())
+                            .filter(|item| !item.is_empty())
+                            .collect::<Vec<_>>();
+                        if names.is_empty() {
+                            None
+                        } else {
+                            Some(VaryRule::Cookie(names))
+                        }
+                    },
+                    _ => Some(VaryRule::Header(name)),
+                }
+            })
+            .collect();
+        Self { rules }
+    }
+    fn get(&self, name: &str) -> Option<&VaryRule> {
+        self.rules.iter().find(|rule| rule.name() == name)
+    }
+    /// Returns false if the response varies by `*` or `Cookie`
+    /// without normalisation rule.
+    pub fn is_cacheable(&self, resp: &ResponseHeader) -> bool {
+        get_vary_names(resp).iter().all(|name| match name.as_str() {
+            "*" => !self.rules.is_empty(),
+            "cookie" => self.get(name).is_some(),
+            _ => true,
+        })
+    }
+    /// Get the variance of request from the `Vary` header of cached response,
+    /// the configured rules are used for `Vary: *`.
+    pub fn get_variance(
+        &self,
+        resp: &ResponseHeader,
+        req: &RequestHeader,
+    ) -> Option<HashBinary> {
+        let mut names = get_vary_names(resp);
+        if names.iter().any(|name| name == "*") {
+            names = self
+                .rules
+                .iter()
+                .map(|rule| rule.name().to_string())
+                .collect();
+        }
+        let mut variance = VarianceBuilder::new();
+        for name in names.iter() {
+            let value = if let Some(rule) = self.get(name) {
+                rule.normalize(req)
+            } else {
+                get_header_values(req, name).into_bytes()
+            };
+            variance.add_owned_value(name, value);
+        }
+        variance.finalize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_accept_encoding, VaryRule, VaryRules};
+    use pingora::http::{RequestHeader, ResponseHeader};
+    use pretty_assertions::{assert_eq, assert_ne};
+
+    fn new_request(headers: &[(&str, &str)]) -> RequestHeader {
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        for (name, value) in headers {
+            req.append_header(name.to_string(), *value).unwrap();
+        }
+        req
+    }
+    fn new_response(vary: &str) -> ResponseHeader {
+        let mut resp = ResponseHeader::build(200, None).unwrap();
+        resp.insert_header("Vary", vary).unwrap();
+        resp
+    }
+
+    #[test]
+    fn test_vary_rules() {
+        let rules = VaryRules::new(&[
+            "Accept-Encoding".to_string(),
+            "Cookie: lang, theme".to_string(),
+            "Accept-Language".to_string(),
+            "Cookie".to_string(),
+        ]);
+        assert_eq!(
+            vec![
+                VaryRule::AcceptEncoding,
+                VaryRule::Cookie(vec!["lang".to_string(), "theme".to_string()]),
+                VaryRule::Header("accept-language".to_string()),
+            ],
+            rules.rules
+        );
+
+        assert_eq!(
+            "br",
+            normalize_accept_encoding(&new_request(&[(
+                "Accept-Encoding",
+                "gzip, deflate, br"
+            )]))
+        );
+        assert_eq!(
+            "gzip",
+            normalize_accept_encoding(&new_request(&[(
+                "Accept-Encoding",
+                "gzip, br;q=0"
+            )]))
+        );
+        assert_eq!(
+            "identity",
+            normalize_accept_encoding(&new_request(&[(
+                "Accept-Encoding",
+                "deflate"
+            )]))
+        );
+        assert_eq!("identity", normalize_accept_encoding(&new_request(&[])));
+    }
+
+    #[test]
+    fn test_vary_cacheable() {
+        let rules = VaryRules::default();
+        assert_eq!(true, rules.is_cacheable(&new_response("Accept-Encoding")));
+        assert_eq!(false, rules.is_cacheable(&new_response("*")));
+        assert_eq!(
+            false,
+            rules.is_cacheable(&new_response("Accept-Encoding, Cookie"))
+        );
+
+        let rules = VaryRules::new(&["Cookie:lang".to_string()]);
+        assert_eq!(true, rules.is_cacheable(&new_response("*")));
+        assert_eq!(
+            true,
+            rules.is_cacheable(&new_response("Accept-Encoding, Cookie"))
+        );
+    }
+
+    #[test]
+    fn test_vary_variance() {
+        let rules = VaryRules::default();
+        assert_eq!(
+            None,
+            rules.get_variance(
+                &ResponseHeader::build(200, None).unwrap(),
+                &new_request(&[("Accept-Encoding", "gzip")])
+            )
+        );
+        let resp = new_response("Accept-Encoding");
+        assert_ne!(
+            rules.get_variance(
+                &resp,
+                &new_request(&[("Accept-Encoding", "gzip")])
+            ),
+            rules.get_variance(
+                &resp,
+                &new_request(&[("Accept-Encoding", "gzip, br")])
+            ),
+        );
+
+        let rules = VaryRules::new(&[
+            "Accept-Encoding".to_string(),
+            "Cookie:lang".to_string(),
+        ]);
+        assert_eq!(
+            rules.get_variance(
+                &resp,
+                &new_request(&[("Accept-Encoding", "gzip, deflate, br")])
+            ),
+            rules.get_variance(
+                &resp,
+                &new_request(&[("Accept-Encoding", "br")])
+            ),
+        );
+        let resp = new_response("Cookie");
+        assert_eq!(
+            rules.get_variance(
+                &resp,
+                &new_request(&[("Cookie", "lang=en; uid=1")])
+            ),
+            rules.get_variance(
+                &resp,
+                &new_request(&[("Cookie", "uid=2; lang=en")])
+            ),
+        );
+        assert_ne!(
+            rules.get_variance(&resp, &new_request(&[("Cookie", "lang=en")])),
+            rules.get_variance(&resp, &new_request(&[("Cookie", "lang=zh")])),
+        );
+        // vary by all configured rules
+        let resp = new_response("*");
+        assert_ne!(
+            None,
+            rules.get_variance(&resp, &new_request(&[("Cookie", "lang=en")]))
+        );
+    }
+}
@@ -12,27 +12,33 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::interpolation::interpolate_value;
 use super::{Error, Result};
 use crate::discovery::is_static_discovery;
-use crate::plugin::parse_plugins;
+use crate::plugin::{parse_plugins, validate_plugin_conf};
 use crate::proxy::Parser;
 use crate::util::{self, aes_decrypt, base64_decode};
 use arc_swap::ArcSwap;
 use bytesize::ByteSize;
 use http::{HeaderName, HeaderValue};
+use ipnet::IpNet;
 use once_cell::sync::Lazy;
 use once_cell::sync::OnceCell;
 use regex::Regex;
 use serde::{Deserialize, Serialize, Serializer};
 use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io::Cursor;
-use std::net::ToSocketAddrs;
+use std::net::{IpAddr, ToSocketAddrs};
 use std::sync::Arc;
 use std::time::Duration;
-use std::{collections::HashMap, str::FromStr};
-use strum::EnumString;
+use std::{
+    collections::{BTreeMap, HashMap},
+    str::FromStr,
+};
+use strum::{EnumIter, EnumString};
 use toml::Table;
 use toml::{map::Map, Value};
+use tracing::warn;
 use url::Url;
 
 pub const CATEGORY_CERTIFICATE: &str = "certificate";
@@ -55,7 +61,9 @@ pub fn list_category() -> Vec<String> {
     ]
 }
 
-#[derive(PartialEq, Debug, Default, Clone, EnumString, strum::Display)]
+#[derive(
+    PartialEq, Debug, Default, Clone, EnumString, EnumIter, strum::Display,
+)]
 #[strum(serialize_all = "snake_case")]
 pub enum PluginCategory {
     #[default]
@@ -80,6 +88,10 @@ pub enum PluginCategory {
     Csrf,
     Cors,
     AcceptEncoding,
+    Waf,
+    Geoip,
+    Challenge,
+    Coalesce,
 }
 
 impl Serialize for PluginCategory {
@@ -157,6 +169,11 @@ pub struct CertificateConf {
     pub certificate_file: Option<String>,
     pub is_default: Option<bool>,
     pub acme: Option<String>,
+    pub ca_cert_storage: Option<String>,
+    pub ca_key_storage: Option<String>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub validity: Option<Duration>,
     pub remark: Option<String>,
 }
 
@@ -188,8 +205,26 @@ impl CertificateConf {
         self.hash(&mut hasher);
         format!("{:x}", hasher.finish())
     }
+    /// Whether the certificate is issued by internal ca.
+    pub fn is_internal_ca(&self) -> bool {
+        self.ca_cert_storage.is_some() || self.ca_key_storage.is_some()
+    }
     /// Validate the options of certificate config.
     pub fn validate(&self) -> Result<()> {
+        if self.is_internal_ca() {
+            if self.ca_cert_storage.is_none() || self.ca_key_storage.is_none() {
+                return Err(Error::Invalid {
+                    message: "ca cert and key storage should be set together"
+                        .to_string(),
+                });
+            }
+            if self.domains.clone().unwrap_or_default().is_empty() {
+                return Err(Error::Invalid {
+                    message: "domains of internal ca certificate is empty"
+                        .to_string(),
+                });
+            }
+        }
         // convert private key
         if let Some(value) = &self.tls_key {
             let buf = convert_pem(value)?;
@@ -250,6 +285,7 @@ pub struct UpstreamConf {
     pub tcp_probe_count: Option<usize>,
     pub tcp_recv_buf: Option<ByteSize>,
     pub tcp_fast_open: Option<bool>,
+    pub proxy_protocol: Option<String>,
     pub includes: Option<Vec<String>>,
     pub remark: Option<String>,
 }
@@ -299,6 +335,15 @@ impl UpstreamConf {
                 ),
             });
         }
+        if let Some(version) = &self.proxy_protocol {
+            if !["v1", "v2"].contains(&version.as_str()) {
+                return Err(Error::Invalid {
+                    message: format!(
+                        "proxy protocol({version}) should be v1 or v2"
+                    ),
+                });
+            }
+        }
 
         Ok(())
     }
@@ -315,10 +360,33 @@ pub struct LocationConf {
     pub weight: Option<u16>,
     pub plugins: Option<Vec<String>>,
     pub client_max_body_size: Option<ByteSize>,
+    pub error_template: Option<String>,
+    pub error_templates: Option<BTreeMap<String, String>>,
     pub includes: Option<Vec<String>>,
     pub remark: Option<String>,
 }
 
+// The key of error templates should be status code or class, e.g. `503`, `4xx`.
+fn validate_error_templates(
+    templates: &Option<BTreeMap<String, String>>,
+) -> Result<()> {
+    for key in templates.iter().flat_map(|item| item.keys()) {
+        let valid = match key.as_str() {
+            "4xx" | "5xx" => true,
+            _ => key
+                .parse::<u16>()
+                .map(|code| (400..600).contains(&code))
+                .unwrap_or_default(),
+        };
+        if !valid {
+            return Err(Error::Invalid {
+                message: format!("error template({key}) is invalid"),
+            });
+        }
+    }
+    Ok(())
+}
+
 impl LocationConf {
     /// Get hash key of location config
     pub fn hash_key(&self) -> String {
@@ -372,6 +440,7 @@ impl LocationConf {
             let _ =
                 Regex::new(arr[0]).map_err(|e| Error::Regex { source: e })?;
         }
+        validate_error_templates(&self.error_templates)?;
 
         Ok(())
     }
@@ -414,8 +483,13 @@ pub struct ServerConf {
     pub tls_ciphersuites: Option<String>,
     pub tls_min_version: Option<String>,
     pub tls_max_version: Option<String>,
+    pub tls_ticket_key_storage: Option<String>,
+    #[serde(default)]
+    #[serde(with = "humantime_serde")]
+    pub tls_ticket_key_rotation: Option<Duration>,
     pub global_certificates: Option<bool>,
     pub enabled_h2: Option<bool>,
+    pub trusted_proxies: Option<Vec<String>>,
     #[serde(default)]
     #[serde(with = "humantime_serde")]
     pub tcp_idle: Option<Duration>,
@@ -424,17 +498,38 @@ pub struct ServerConf {
     pub tcp_interval: Option<Duration>,
     pub tcp_probe_count: Option<usize>,
     pub tcp_fastopen: Option<usize>,
+    pub client_header_max_size: Option<ByteSize>,
+    pub client_header_max_count: Option<usize>,
+    pub client_uri_max_length: Option<usize>,
     pub prometheus_metrics: Option<String>,
     pub otlp_exporter: Option<String>,
+    pub error_template: Option<String>,
+    pub error_templates: Option<BTreeMap<String, String>>,
     pub includes: Option<Vec<String>>,
     pub remark: Option<String>,
 }
 
+// The trusted proxies should be ip or cidr.
+fn validate_trusted_proxies(
+    trusted_proxies: &Option<Vec<String>>,
+) -> Result<()> {
+    for item in trusted_proxies.iter().flatten() {
+        if item.parse::<IpAddr>().is_err() && IpNet::from_str(item).is_err() {
+            return Err(Error::Invalid {
+                message: format!("trusted proxy({item}) is invalid"),
+            });
+        }
+    }
+    Ok(())
+}
+
 impl ServerConf {
     /// Validate the options of server config.
     /// 1. Parse listen addr to socket addr.
     /// 2. Check the locations are exists.
-    /// 3. Parse access log layout success.
+    /// 3. Check the trusted proxies are ip or cidr.
+    /// 4. Parse access log layout success.
+    /// 5. Check the keys of error templates.
     fn validate(&self, name: &str, location_names: &[String]) -> Result<()> {
         for addr in self.addr.split(',') {
             let _ = addr.to_socket_addrs().map_err(|e| Error::Io {
@@ -454,6 +549,9 @@ impl ServerConf {
             }
         }
 
+        validate_trusted_proxies(&self.trusted_proxies)?;
+        validate_error_templates(&self.error_templates)?;
+
         if let Some(access_log) = &self.access_log {
             let logger = Parser::from(access_log.as_str());
             if logger.tags.is_empty() {
@@ -470,6 +568,7 @@ impl ServerConf {
 pub struct BasicConf {
     pub name: Option<String>,
     pub error_template: Option<String>,
+    pub error_templates: Option<BTreeMap<String, String>>,
     pub pid_file: Option<String>,
     pub upgrade_sock: Option<String>,
     pub user: Option<String>,
@@ -496,6 +595,13 @@ pub struct BasicConf {
     pub auto_restart_check_interval: Option<Duration>,
     pub cache_directory: Option<String>,
     pub cache_max_size: Option<ByteSize>,
+    // the memory size of tiered cache, it's enabled with cache directory
+    pub cache_memory_size: Option<ByteSize>,
+    // the cache directories with size limit, e.g. `/mnt/nvme0/cache 100GB`,
+    // the objects are sharded to them by consistent hashing
+    pub cache_directories: Option<Vec<String>>,
+    pub trusted_proxies: Option<Vec<String>>,
+    pub blocklist_file: Option<String>,
 }
 
 impl BasicConf {
@@ -506,6 +612,58 @@ impl BasicConf {
             format!("/tmp/{}.pid", util::get_pkg_name())
         }
     }
+    /// Get the cache directories and their max size(zero means unlimited),
+    /// the cache directory with cache max size is used if no directories.
+    pub fn get_cache_directories(&self) -> Vec<(String, u64)> {
+        let directories = self.cache_directories.clone().unwrap_or_default();
+        if directories.is_empty() {
+            return self
+                .cache_directory
+                .iter()
+                .filter(|dir| !dir.is_empty())
+                .map(|dir| {
+                    let max_size = self
+                        .cache_max_size
+                        .map(|item| item.as_u64())
+                        .unwrap_or_default();
+                    (dir.clone(), max_size)
+                })
+                .collect();
+        }
+        directories
+            .iter()
+            .filter_map(|item| {
+                let mut arr = item.split_whitespace();
+                let dir = arr.next()?.to_string();
+                let max_size = arr
+                    .next()
+                    .and_then(|size| ByteSize::from_str(size).ok())
+                    .map(|size| size.as_u64())
+                    .unwrap_or_default();
+                Some((dir, max_size))
+            })
+            .collect()
+    }
+}
+
+// The cache directory should be `path` or `path size`.
+fn validate_cache_directories(
+    cache_directories: &Option<Vec<String>>,
+) -> Result<()> {
+    for item in cache_directories.iter().flatten() {
+        let arr: Vec<&str> = item.split_whitespace().collect();
+        if arr.is_empty() || arr.len() > 2 {
+            return Err(Error::Invalid {
+                message: format!("cache directory({item}) is invalid"),
+            });
+        }
+        if let Some(size) = arr.get(1) {
+            ByteSize::from_str(size).map_err(|e| Error::Invalid {
+                message: format!("cache directory({item}) is invalid, {e}"),
+            })?;
+        }
+    }
+    Ok(())
 }
 
 #[derive(Debug, Default, Deserialize, Clone, Serialize)]
@@ -516,8 +674,21 @@ pub struct StorageConf {
     pub remark: Option<String>,
 }
 
+impl StorageConf {
+    /// Get the value of storage, it will be decrypted if secret is set.
+    pub fn get_value(&self) -> Result<String> {
+        if let Some(key) = &self.secret {
+            return aes_decrypt(key, &self.value).map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            });
+        }
+        Ok(self.value.clone())
+    }
+}
+
 #[derive(Deserialize, Debug, Serialize)]
 struct TomlConfig {
+    include: Option<Vec<String>>,
     basic: Option<BasicConf>,
     servers: Option<Map<String, Value>>,
     upstreams: Option<Map<String, Value>>,
@@ -539,6 +710,8 @@ pub type PluginConf = Map<String, Value>;
 
 #[derive(Debug, Default, Clone, Deserialize, Serialize)]
 pub struct PingapConf {
+    // the include patterns of config files(or etcd keys)
+    pub include: Option<Vec<String>>,
     pub basic: BasicConf,
     pub upstreams: HashMap<String, UpstreamConf>,
     pub locations: HashMap<String, LocationConf>,
@@ -630,19 +803,8 @@ impl PingapConf {
         Ok(result)
     }
     pub fn get_storage_value(&self, name: &str) -> Result<String> {
-        for (key, item) in self.storages.iter() {
-            if key != name {
-                continue;
-            }
-
-            if let Some(key) = &item.secret {
-                return aes_decrypt(key, &item.value).map_err(|e| {
-                    Error::Invalid {
-                        message: e.to_string(),
-                    }
-                });
-            }
-            return Ok(item.value.clone());
+        if let Some(item) = self.storages.get(name) {
+            return item.get_value();
         }
         Ok("".to_string())
     }
@@ -690,18 +852,57 @@ fn get_include_toml(
     Some(arr.join("\n"))
 }
 
+/// Interpolate the string values of config, the storages only support
+/// environment variable, and the others can reference storage value.
+fn interpolate_config(mut table: Table) -> Result<Table> {
+    let no_storage = |name: &str| -> Result<String> {
+        Err(Error::Invalid {
+            message: format!("storage({name}) can not be used in storages"),
+        })
+    };
+    let mut storages = HashMap::new();
+    if let Some(value) = table.get_mut("storages") {
+        interpolate_value(value, &no_storage).map_err(with_path("storages"))?;
+        for (name, value) in value.as_table().cloned().unwrap_or_default() {
+            let storage: StorageConf =
+                value.try_into().map_err(|e| Error::De { source: e })?;
+            storages.insert(name, storage);
+        }
+    }
+    let get_storage_value = |name: &str| -> Result<String> {
+        let Some(storage) = storages.get(name) else {
+            return Err(Error::Invalid {
+                message: format!("storage({name}) is not found"),
+            });
+        };
+        storage.get_value()
+    };
+    for (key, value) in table.iter_mut() {
+        if key == "storages" {
+            continue;
+        }
+        interpolate_value(value, &get_storage_value).map_err(with_path(key))?;
+    }
+    Ok(table)
+}
+
 fn convert_pingap_config(
     data: &[u8],
     replace_includes: bool,
 ) -> Result<PingapConf, Error> {
-    let data: TomlConfig = toml::from_str(
-        std::string::String::from_utf8_lossy(data)
-            .to_string()
-            .as_str(),
-    )
-    .map_err(|e| Error::De { source: e })?;
+    let data = std::string::String::from_utf8_lossy(data).to_string();
+    let data: TomlConfig = if replace_includes {
+        let table: Table =
+            toml::from_str(&data).map_err(|e| Error::De { source: e })?;
+        interpolate_config(table)?
+            .try_into()
+            .map_err(|e| Error::De { source: e })?
+    } else {
+        toml::from_str(&data).map_err(|e| Error::De { source: e })?
+    };
 
     let mut conf = PingapConf {
+        include: data.include,
         basic: data.basic.unwrap_or_default(),
         ..Default::default()
     };
@@ -758,20 +959,47 @@ struct Description {
     data: String,
 }
 
+/// Add the config path to the message of invalid error,
+/// e.g. `upstreams.charts: upstream addrs is empty`.
+fn with_path(path: &str) -> impl FnOnce(Error) -> Error + '_ {
+    move |err| match err {
+        Error::Invalid { message } => Error::Invalid {
+            message: format!("{path}: {message}"),
+        },
+        _ => err,
+    }
+}
+
 impl PingapConf {
     pub fn new(data: &[u8], replace_includes: bool) -> Result<Self> {
         convert_pingap_config(data, replace_includes)
     }
-    /// Validate the options of pinggap config.
+    /// Validate the options of pinggap config,
+    /// the values are interpolated before validation.
     pub fn validate(&self) -> Result<()> {
+        let ping_conf = toml::to_string_pretty(self)
+            .map_err(|e| Error::Ser { source: e })?;
+        convert_pingap_config(ping_conf.as_bytes(), true)?.validate_options()
+    }
+    fn validate_options(&self) -> Result<()> {
+        validate_trusted_proxies(&self.basic.trusted_proxies)
+            .map_err(with_path("basic.trusted_proxies"))?;
+        validate_error_templates(&self.basic.error_templates)
+            .map_err(with_path("basic.error_templates"))?;
+        validate_cache_directories(&self.basic.cache_directories)
+            .map_err(with_path("basic.cache_directories"))?;
         let mut upstream_names = vec![];
         for (name, upstream) in self.upstreams.iter() {
-            upstream.validate(name)?;
+            upstream
+                .validate(name)
+                .map_err(with_path(&format!("upstreams.{name}")))?;
             upstream_names.push(name.to_string());
         }
         let mut location_names = vec![];
         for (name, location) in self.locations.iter() {
-            location.validate(name, &upstream_names)?;
+            location
+                .validate(name, &upstream_names)
+                .map_err(with_path(&format!("locations.{name}")))?;
             location_names.push(name.to_string());
         }
         let mut listen_addr_list = vec![];
@@ -779,26 +1007,73 @@ impl PingapConf {
             for addr in server.addr.split(',') {
                 if listen_addr_list.contains(&addr.to_string()) {
                     return Err(Error::Invalid {
-                        message: format!("{addr} is inused by other server"),
+                        message: format!(
+                            "servers.{name}.addr: {addr} is inused by other server"
+                        ),
                     });
                 }
                 listen_addr_list.push(addr.to_string());
             }
-            server.validate(name, &location_names)?;
+            server
+                .validate(name, &location_names)
+                .map_err(with_path(&format!("servers.{name}")))?;
+            if let Some(storage) = &server.tls_ticket_key_storage {
+                if !self.storages.contains_key(storage) {
+                    return Err(Error::Invalid {
+                        message: format!(
+                            "servers.{name}.tls_ticket_key_storage: storage({storage}) is not found"
+                        ),
+                    });
+                }
+                // empty secret would disable the shared ticket keys silently
+                let value =
+                    self.get_storage_value(storage).map_err(with_path(
+                        &format!("servers.{name}.tls_ticket_key_storage"),
+                    ))?;
+                if value.is_empty() {
+                    return Err(Error::Invalid {
+                        message: format!(
+                            "servers.{name}.tls_ticket_key_storage: storage({storage}) value is empty"
+                        ),
+                    });
+                }
+            }
         }
         for (name, plugin) in self.plugins.iter() {
+            // the mistyped fields are reported with path,
+            // the unknown fields are only warned for compatibility
+            let unknown_fields =
+                validate_plugin_conf(name, plugin).map_err(|e| {
+                    Error::Invalid {
+                        message: e.to_string(),
+                    }
+                })?;
+            for message in unknown_fields {
+                warn!(message, "plugin config has unknown field");
+            }
             parse_plugins(vec![(name.to_string(), plugin.clone())]).map_err(
                 |e| Error::Invalid {
-                    message: e.to_string(),
+                    message: format!("plugins.{name}: {e}"),
                 },
             )?;
         }
-        for (_, certificate) in self.certificates.iter() {
-            certificate.validate()?;
+        for (certificate_name, certificate) in self.certificates.iter() {
+            let path = format!("certificates.{certificate_name}");
+            certificate.validate().map_err(with_path(&path))?;
+            for name in
+                [&certificate.ca_cert_storage, &certificate.ca_key_storage]
+                    .into_iter()
+                    .flatten()
+            {
+                if !self.storages.contains_key(name) {
+                    return Err(Error::Invalid {
+                        message: format!(
+                            "{path}: storage({name}) is not found"
+                        ),
+                    });
+                }
+            }
         }
-        let ping_conf = toml::to_string_pretty(self)
-            .map_err(|e| Error::Ser { source: e })?;
-        convert_pingap_config(ping_conf.as_bytes(), true)?;
         Ok(())
     }
     /// Generate the content hash of config.
@@ -1047,14 +1322,16 @@ pub fn get_config_hash() -> String {
 mod tests {
     use super::{
         get_app_name, get_config_hash, set_app_name, set_current_config,
-        BasicConf,
+        validate_cache_directories, BasicConf,
     };
     use super::{
-        LocationConf, PingapConf, PluginCategory, ServerConf, UpstreamConf,
-        CATEGORY_LOCATION, CATEGORY_PLUGIN, CATEGORY_SERVER, CATEGORY_UPSTREAM,
+        LocationConf, PingapConf, PluginCategory, ServerConf, StorageConf,
+        UpstreamConf, CATEGORY_LOCATION, CATEGORY_PLUGIN, CATEGORY_SERVER,
+        CATEGORY_UPSTREAM,
     };
     use pretty_assertions::assert_eq;
     use serde::{Deserialize, Serialize};
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_app_name() {
@@ -1063,6 +1340,53 @@ mod tests {
         assert_eq!("Pingap-X", get_app_name());
     }
 
+    #[test]
+    fn test_get_cache_directories() {
+        let conf = BasicConf {
+            cache_directory: Some("/tmp/cache".to_string()),
+            cache_max_size: Some(bytesize::ByteSize::mb(100)),
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![("/tmp/cache".to_string(), 100 * 1000 * 1000)],
+            conf.get_cache_directories()
+        );
+
+        let conf = BasicConf {
+            cache_directory: Some("/tmp/cache".to_string()),
+            cache_directories: Some(vec![
+                "/mnt/nvme0/cache 1GB".to_string(),
+                "/mnt/nvme1/cache".to_string(),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(
+            vec![
+                ("/mnt/nvme0/cache".to_string(), 1000 * 1000 * 1000),
+                ("/mnt/nvme1/cache".to_string(), 0)
+            ],
+            conf.get_cache_directories()
+        );
+        assert_eq!(
+            true,
+            BasicConf {
+                cache_directory: Some("".to_string()),
+                ..Default::default()
+            }
+            .get_cache_directories()
+            .is_empty()
+        );
+
+        assert_eq!(
+            "Invalid error cache directory(/mnt/nvme0/cache 1GB 2) is invalid",
+            validate_cache_directories(&Some(vec![
+                "/mnt/nvme0/cache 1GB 2".to_string()
+            ]))
+            .unwrap_err()
+            .to_string()
+        );
+    }
+
     #[test]
     fn test_current_config() {
         let conf = PingapConf {
@@ -1230,6 +1554,21 @@ mod tests {
         conf.locations = Some(vec!["lo".to_string()]);
         let result = conf.validate("test", &location_names);
         assert_eq!(true, result.is_ok());
+
+        conf.error_templates = Some(BTreeMap::from([
+            ("5xx".to_string(), "<p>5xx</p>".to_string()),
+            ("503".to_string(), "<p>maintenance</p>".to_string()),
+        ]));
+        let result = conf.validate("test", &location_names);
+        assert_eq!(true, result.is_ok());
+
+        conf.error_templates =
+            Some(BTreeMap::from([("3xx".to_string(), "".to_string())]));
+        let result = conf.validate("test", &location_names);
+        assert_eq!(
+            "Invalid error error template(3xx) is invalid",
+            result.expect_err("").to_string()
+        );
     }
 
     #[test]
@@ -1414,4 +1753,69 @@ basic
         let result = conf.remove("plugin", "stats");
         assert_eq!(true, result.is_ok());
     }
+
+    #[test]
+    fn test_tls_ticket_key_storage() {
+        let toml_data = include_bytes!("../../conf/pingap.toml");
+        let mut conf =
+            PingapConf::new(toml_data.to_vec().as_slice(), false).unwrap();
+        conf.servers.get_mut("test").unwrap().tls_ticket_key_storage =
+            Some("ticket".to_string());
+        assert_eq!(
+            "Invalid error servers.test.tls_ticket_key_storage: storage(ticket) is not found",
+            conf.validate().err().unwrap().to_string()
+        );
+
+        conf.storages.insert(
+            "ticket".to_string(),
+            StorageConf {
+                category: "config".to_string(),
+                ..Default::default()
+            },
+        );
+        assert_eq!(
+            "Invalid error servers.test.tls_ticket_key_storage: storage(ticket) value is empty",
+            conf.validate().err().unwrap().to_string()
+        );
+
+        conf.storages.get_mut("ticket").unwrap().value =
+            "pingap-secret".to_string();
+        assert_eq!(true, conf.validate().is_ok());
+    }
+
+    #[test]
+    fn test_interpolate_pingap_conf() {
+        let data = r###"
+[upstreams.charts]
+addrs = ["${PINGAP_TEST_NOT_EXISTS:-127.0.0.1:5000}"]
+
+[locations.lo]
+upstream = "charts"
+rewrite = "^/api/(?P<rest>.*) /${rest}"
+
+[plugins.mock]
+category = "mock"
+data = "{\"name\": \"${name}\"}"
+"###;
+        let conf = PingapConf::new(data.as_bytes(), true).unwrap();
+        assert_eq!(
+            vec!["127.0.0.1:5000".to_string()],
+            conf.upstreams.get("charts").unwrap().addrs
+        );
+        // the named capture of rewrite is kept
+        assert_eq!(
+            "^/api/(?P<rest>.*) /${rest}",
+            conf.locations.get("lo").unwrap().rewrite.clone().unwrap()
+        );
+        assert_eq!(
+            r#"{"name": "${name}"}"#,
+            conf.plugins
+                .get("mock")
+                .unwrap()
+                .get("data")
+                .unwrap()
+                .as_str()
+                .unwrap()
+        );
+    }
 }
@@ -12,11 +12,16 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{ConfigStorage, Error, Result};
+use super::{
+    sort_versions, take_includes, ConfigStorage, ConfigVersion, Error, Result,
+    MAX_CONFIG_VERSIONS,
+};
 use super::{Observer, PingapConf};
 use async_trait::async_trait;
 use etcd_client::{Client, ConnectOptions, GetOptions, WatchOptions};
+use glob::Pattern;
 use humantime::parse_duration;
+use std::collections::{HashSet, VecDeque};
 use substring::Substring;
 
 pub struct EtcdStorage {
@@ -79,23 +84,44 @@ impl EtcdStorage {
             path,
         })
     }
+    /// Get the key prefix of config versions, e.g. `/.history/pingap/`,
+    /// it's not under the config path, so it won't be loaded as config.
+    fn get_history_prefix(&self) -> String {
+        format!("/.history{}/", self.path)
+    }
     /// Connect to etcd server.
     async fn connect(&self) -> Result<Client> {
         Client::connect(&self.addrs, Some(self.options.clone()))
             .await
             .map_err(|e| Error::Etcd { source: e })
     }
-}
-
-#[async_trait]
-impl ConfigStorage for EtcdStorage {
-    /// Load config from etcd.
-    async fn load_config(
+    /// Get the matcher and key prefix(without wildcard) of include pattern,
+    /// the pattern is relative to the config path if it isn't absolute.
+    fn get_include_matcher(&self, pattern: &str) -> Result<(Pattern, String)> {
+        let pattern = if pattern.starts_with('/') {
+            pattern.to_string()
+        } else {
+            format!("{}/{pattern}", self.path)
+        };
+        let matcher = Pattern::new(&pattern).map_err(|e| Error::Pattern {
+            source: e,
+            path: pattern.clone(),
+        })?;
+        let prefix = pattern
+            .split(['*', '?', '['])
+            .next()
+            .unwrap_or_default()
+            .to_string();
+        Ok((matcher, prefix))
+    }
+    /// Load the config data and the include patterns of config path,
+    /// the key prefixes of included keys outside the path are also
+    /// returned, so they can be watched.
+    async fn load_data(
         &self,
+        c: &mut Client,
         replace_include: bool,
-        _admin: bool,
-    ) -> Result<PingapConf> {
-        let mut c = self.connect().await?;
+    ) -> Result<(Vec<u8>, Vec<String>, Vec<String>)> {
         let mut opts = GetOptions::new();
         opts = opts.with_prefix();
         let arr = c
@@ -103,12 +129,75 @@ impl ConfigStorage for EtcdStorage {
             .await
             .map_err(|e| Error::Etcd { source: e })?
             .take_kvs();
-        let mut buffer = vec![];
+        let mut loaded = HashSet::new();
+        let mut queue = VecDeque::new();
         for item in arr {
-            buffer.extend(item.value());
+            loaded.insert(item.key().to_vec());
+            queue.push_back((item.value().to_vec(), true));
+        }
+        let mut includes = vec![];
+        let mut prefixes: Vec<String> = vec![];
+        let mut buffer = vec![];
+        while let Some((value, root)) = queue.pop_front() {
+            let (value, patterns) = take_includes(value);
+            buffer.extend(value);
             buffer.push(0x0a);
+            if root {
+                includes.extend(patterns.clone());
+            }
+            // the included keys are only loaded for running config
+            if !replace_include {
+                continue;
+            }
+            for pattern in patterns.iter() {
+                let (matcher, prefix) = self.get_include_matcher(pattern)?;
+                // the keys of config path are loaded already,
+                // and the same prefix is only queried once
+                if prefix.starts_with(&self.path) || prefixes.contains(&prefix)
+                {
+                    continue;
+                }
+                let arr = c
+                    .get(
+                        prefix.as_bytes(),
+                        Some(GetOptions::new().with_prefix()),
+                    )
+                    .await
+                    .map_err(|e| Error::Etcd { source: e })?
+                    .take_kvs();
+                prefixes.push(prefix);
+                for item in arr {
+                    let key = item.key_str().unwrap_or_default();
+                    if matcher.matches(key)
+                        && loaded.insert(item.key().to_vec())
+                    {
+                        queue.push_back((item.value().to_vec(), false));
+                    }
+                }
+            }
         }
-        PingapConf::new(buffer.as_slice(), replace_include)
+        Ok((buffer, includes, prefixes))
+    }
+}
+
+#[async_trait]
+impl ConfigStorage for EtcdStorage {
+    /// Load config from etcd.
+    async fn load_config(
+        &self,
+        replace_include: bool,
+        _admin: bool,
+    ) -> Result<PingapConf> {
+        let mut c = self.connect().await?;
+        let (buffer, mut includes, _) =
+            self.load_data(&mut c, replace_include).await?;
+        let mut conf = PingapConf::new(buffer.as_slice(), replace_include)?;
+        if !includes.is_empty() {
+            let mut exists = HashSet::new();
+            includes.retain(|item| exists.insert(item.clone()));
+            conf.include = Some(includes);
+        }
+        Ok(conf)
     }
     /// Save config to etcd by category.
     async fn save_config(
@@ -129,17 +218,73 @@ impl ConfigStorage for EtcdStorage {
     fn support_observer(&self) -> bool {
         true
     }
+    /// Save the version to the history key, it's outside of config path.
+    async fn save_version(&self, version: &ConfigVersion) -> Result<()> {
+        let buf = serde_json::to_vec(version).map_err(|e| Error::Invalid {
+            message: e.to_string(),
+        })?;
+        let mut c = self.connect().await?;
+        c.put(
+            format!("{}{}", self.get_history_prefix(), version.id),
+            buf,
+            None,
+        )
+        .await
+        .map_err(|e| Error::Etcd { source: e })?;
+        let versions = self.list_versions().await?;
+        if versions.len() > MAX_CONFIG_VERSIONS {
+            for item in &versions[..versions.len() - MAX_CONFIG_VERSIONS] {
+                c.delete(
+                    format!("{}{}", self.get_history_prefix(), item.id),
+                    None,
+                )
+                .await
+                .map_err(|e| Error::Etcd { source: e })?;
+            }
+        }
+        Ok(())
+    }
+    /// List the versions from the history keys.
+    async fn list_versions(&self) -> Result<Vec<ConfigVersion>> {
+        let mut c = self.connect().await?;
+        let arr = c
+            .get(
+                self.get_history_prefix(),
+                Some(GetOptions::new().with_prefix()),
+            )
+            .await
+            .map_err(|e| Error::Etcd { source: e })?
+            .take_kvs();
+        let mut versions = vec![];
+        for item in arr {
+            let version: ConfigVersion = serde_json::from_slice(item.value())
+                .map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?;
+            versions.push(version);
+        }
+        sort_versions(&mut versions);
+        Ok(versions)
+    }
     async fn observe(&self) -> Result<Observer> {
         // 逻辑并不完善，有可能因为变更处理中途又发生其它变更导致缺失
         // 因此还需配合fetch的形式比对
         let mut c = self.connect().await?;
-        let (_, stream) = c
+        // the included keys outside the config path are also watched
+        let (_, _, prefixes) = self.load_data(&mut c, true).await?;
+        let (mut watcher, stream) = c
             .watch(
                 self.path.as_bytes(),
                 Some(WatchOptions::default().with_prefix()),
             )
             .await
             .map_err(|e| Error::Etcd { source: e })?;
+        for prefix in prefixes {
+            watcher
+                .watch(prefix, Some(WatchOptions::default().with_prefix()))
+                .await
+                .map_err(|e| Error::Etcd { source: e })?;
+        }
         Ok(Observer {
             etcd_watch_stream: Some(stream),
         })
@@ -156,6 +301,21 @@ mod tests {
     use nanoid::nanoid;
     use pretty_assertions::assert_eq;
 
+    #[test]
+    fn test_get_include_matcher() {
+        let storage = EtcdStorage::new("etcd://127.0.0.1:2379/pingap").unwrap();
+        let (matcher, prefix) =
+            storage.get_include_matcher("conf.d/*.toml").unwrap();
+        assert_eq!("/pingap/conf.d/", prefix);
+        assert_eq!(true, matcher.matches("/pingap/conf.d/upstreams.toml"));
+
+        let (matcher, prefix) =
+            storage.get_include_matcher("/shared/upstream-?").unwrap();
+        assert_eq!("/shared/upstream-", prefix);
+        assert_eq!(true, matcher.matches("/shared/upstream-1"));
+        assert_eq!(false, matcher.matches("/pingap/upstream-1"));
+    }
+
     #[tokio::test]
     async fn test_etcd_storage() {
         let url = format!(
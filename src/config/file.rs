@@ -12,15 +12,26 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use super::{ConfigStorage, Error, PingapConf, Result};
+use super::{
+    sort_versions, take_includes, ConfigStorage, ConfigVersion, Error,
+    PingapConf, Result, MAX_CONFIG_VERSIONS,
+};
 use crate::util;
 use async_trait::async_trait;
 use futures_util::TryFutureExt;
 use glob::glob;
-use std::path::Path;
+use std::collections::{HashSet, VecDeque};
+use std::path::{Path, PathBuf};
 use tokio::fs;
 use tracing::debug;
 
+// the versions are saved as json, so they are not loaded as config
+const HISTORY_DIR: &str = ".history";
+
+fn canonicalize(file: &Path) -> PathBuf {
+    std::fs::canonicalize(file).unwrap_or(file.to_path_buf())
+}
+
 pub struct FileStorage {
     path: String,
 }
@@ -36,6 +47,15 @@ impl FileStorage {
 
         Ok(Self { path: filepath })
     }
+    /// Get the directory of config versions, it's under the config dir.
+    fn get_history_dir(&self) -> PathBuf {
+        let path = Path::new(&self.path);
+        if path.is_file() {
+            path.parent().unwrap_or(Path::new("")).join(HISTORY_DIR)
+        } else {
+            path.join(HISTORY_DIR)
+        }
+    }
 }
 
 #[async_trait]
@@ -61,7 +81,7 @@ impl ConfigStorage for FileStorage {
                 .await?;
         }
 
-        let mut data = vec![];
+        let mut files = vec![];
         if dir.is_dir() {
             for entry in
                 glob(&format!("{filepath}/**/*.toml")).map_err(|e| {
@@ -71,23 +91,61 @@ impl ConfigStorage for FileStorage {
                     }
                 })?
             {
-                let f = entry.map_err(|e| Error::Glob { source: e })?;
-                let mut buf = fs::read(&f).await.map_err(|e| Error::Io {
-                    source: e,
-                    file: f.to_string_lossy().to_string(),
-                })?;
-                debug!(filename = format!("{f:?}"), "load config");
-                data.append(&mut buf);
-                data.push(0x0a);
+                files.push(entry.map_err(|e| Error::Glob { source: e })?);
             }
         } else {
-            let mut buf = fs::read(&filepath).await.map_err(|e| Error::Io {
+            files.push(PathBuf::from(filepath));
+        }
+        let mut loaded: HashSet<PathBuf> =
+            files.iter().map(|f| canonicalize(f)).collect();
+        let mut queue: VecDeque<(PathBuf, bool)> =
+            files.into_iter().map(|f| (f, true)).collect();
+        let mut includes = vec![];
+        let mut data = vec![];
+        while let Some((f, root)) = queue.pop_front() {
+            let buf = fs::read(&f).await.map_err(|e| Error::Io {
                 source: e,
-                file: filepath,
+                file: f.to_string_lossy().to_string(),
             })?;
+            debug!(filename = format!("{f:?}"), "load config");
+            let (mut buf, patterns) = take_includes(buf);
             data.append(&mut buf);
+            data.push(0x0a);
+            if root {
+                includes.extend(patterns.clone());
+            }
+            // the included files are only loaded for running config,
+            // so they are not saved to the config files
+            if !replace_include {
+                continue;
+            }
+            let base = f.parent().unwrap_or(Path::new(""));
+            for pattern in patterns.iter() {
+                // relative to the directory of current file
+                let pattern = if pattern.starts_with('~') {
+                    util::resolve_path(pattern)
+                } else {
+                    pattern.clone()
+                };
+                let pattern = base.join(pattern).to_string_lossy().to_string();
+                for entry in glob(&pattern).map_err(|e| Error::Pattern {
+                    source: e,
+                    path: pattern.clone(),
+                })? {
+                    let file = entry.map_err(|e| Error::Glob { source: e })?;
+                    if loaded.insert(canonicalize(&file)) {
+                        queue.push_back((file, false));
+                    }
+                }
+            }
         }
-        PingapConf::new(data.as_slice(), replace_include)
+        let mut conf = PingapConf::new(data.as_slice(), replace_include)?;
+        if !includes.is_empty() {
+            let mut exists = HashSet::new();
+            includes.retain(|item| exists.insert(item.clone()));
+            conf.include = Some(includes);
+        }
+        Ok(conf)
     }
     /// Save config to file by category.
     async fn save_config(
@@ -117,14 +175,79 @@ impl ConfigStorage for FileStorage {
                 file: filepath,
             })
     }
+    /// Save the version to json file of history directory.
+    async fn save_version(&self, version: &ConfigVersion) -> Result<()> {
+        let dir = self.get_history_dir();
+        let dir_name = dir.to_string_lossy().to_string();
+        fs::create_dir_all(&dir).await.map_err(|e| Error::Io {
+            source: e,
+            file: dir_name.clone(),
+        })?;
+        let buf =
+            serde_json::to_vec_pretty(version).map_err(|e| Error::Invalid {
+                message: e.to_string(),
+            })?;
+        let file = dir.join(format!("{}.json", version.id));
+        fs::write(&file, buf).await.map_err(|e| Error::Io {
+            source: e,
+            file: file.to_string_lossy().to_string(),
+        })?;
+        let versions = self.list_versions().await?;
+        if versions.len() > MAX_CONFIG_VERSIONS {
+            for item in &versions[..versions.len() - MAX_CONFIG_VERSIONS] {
+                let file = dir.join(format!("{}.json", item.id));
+                fs::remove_file(&file).await.map_err(|e| Error::Io {
+                    source: e,
+                    file: file.to_string_lossy().to_string(),
+                })?;
+            }
+        }
+        Ok(())
+    }
+    /// List the versions from json files of history directory.
+    async fn list_versions(&self) -> Result<Vec<ConfigVersion>> {
+        let dir = self.get_history_dir();
+        if !dir.exists() {
+            return Ok(vec![]);
+        }
+        let dir_name = dir.to_string_lossy().to_string();
+        let mut entries = fs::read_dir(&dir).await.map_err(|e| Error::Io {
+            source: e,
+            file: dir_name.clone(),
+        })?;
+        let mut versions = vec![];
+        while let Some(entry) =
+            entries.next_entry().await.map_err(|e| Error::Io {
+                source: e,
+                file: dir_name.clone(),
+            })?
+        {
+            let file = entry.path();
+            if file.extension().unwrap_or_default() != "json" {
+                continue;
+            }
+            let buf = fs::read(&file).await.map_err(|e| Error::Io {
+                source: e,
+                file: file.to_string_lossy().to_string(),
+            })?;
+            let version: ConfigVersion =
+                serde_json::from_slice(&buf).map_err(|e| Error::Invalid {
+                    message: e.to_string(),
+                })?;
+            versions.push(version);
+        }
+        sort_versions(&mut versions);
+        Ok(versions)
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::FileStorage;
     use crate::config::{
-        ConfigStorage, PingapConf, CATEGORY_BASIC, CATEGORY_LOCATION,
-        CATEGORY_PLUGIN, CATEGORY_SERVER, CATEGORY_UPSTREAM,
+        list_category, rollback, save_version, ConfigStorage, PingapConf,
+        CATEGORY_BASIC, CATEGORY_LOCATION, CATEGORY_PLUGIN, CATEGORY_SERVER,
+        CATEGORY_UPSTREAM,
     };
     use nanoid::nanoid;
     use pretty_assertions::assert_eq;
@@ -156,4 +279,123 @@ mod tests {
         let current_conf = storage.load_config(false, false).await.unwrap();
         assert_eq!(current_conf.hash().unwrap(), conf.hash().unwrap());
     }
+
+    #[tokio::test]
+    async fn test_file_storage_include() {
+        let path = format!("/tmp/{}", nanoid!(16));
+        tokio::fs::create_dir_all(format!("{path}/conf.d"))
+            .await
+            .unwrap();
+        let file = format!("{path}/pingap.toml");
+        tokio::fs::write(
+            &file,
+            r#"include = ["conf.d/*.toml"]
+
+[storages.token]
+category = "secret"
+value = "pingap"
+"#,
+        )
+        .await
+        .unwrap();
+        tokio::fs::write(
+            format!("{path}/conf.d/upstreams.toml"),
+            r#"[upstreams.charts]
+addrs = ["${PINGAP_TEST_CHARTS_ADDR:-127.0.0.1:5000}"]
+"#,
+        )
+        .await
+        .unwrap();
+        tokio::fs::write(
+            format!("{path}/conf.d/plugins.toml"),
+            r#"[plugins.auth]
+category = "key_auth"
+keys = ["${storage:token}"]
+"#,
+        )
+        .await
+        .unwrap();
+        let storage = FileStorage::new(&file).unwrap();
+
+        let conf = storage.load_config(true, false).await.unwrap();
+        assert_eq!(Some(vec!["conf.d/*.toml".to_string()]), conf.include);
+        assert_eq!(
+            vec!["127.0.0.1:5000".to_string()],
+            conf.upstreams.get("charts").unwrap().addrs
+        );
+        assert_eq!(
+            r#"["pingap"]"#,
+            conf.plugins
+                .get("auth")
+                .unwrap()
+                .get("keys")
+                .unwrap()
+                .to_string()
+        );
+
+        // the included files are not loaded for editing,
+        // and the include directive is kept after saving
+        let conf = storage.load_config(false, false).await.unwrap();
+        assert_eq!(true, conf.upstreams.is_empty());
+        storage.save_config(&conf, CATEGORY_BASIC).await.unwrap();
+        let conf = storage.load_config(true, false).await.unwrap();
+        assert_eq!(Some(vec!["conf.d/*.toml".to_string()]), conf.include);
+        assert_eq!(1, conf.upstreams.len());
+    }
+
+    #[tokio::test]
+    async fn test_file_storage_versions() {
+        let path = format!("/tmp/{}", nanoid!(16));
+        tokio::fs::create_dir(&path).await.unwrap();
+        let storage = FileStorage::new(&path).unwrap();
+        assert_eq!(true, storage.list_versions().await.unwrap().is_empty());
+
+        let current_conf = storage.load_config(false, false).await.unwrap();
+        let toml_data = include_bytes!("../../conf/pingap.toml");
+        let conf =
+            PingapConf::new(toml_data.to_vec().as_slice(), false).unwrap();
+        for category in list_category() {
+            storage.save_config(&conf, &category).await.unwrap();
+        }
+        save_version(&storage, &current_conf, &conf, "tree", "", None)
+            .await
+            .unwrap();
+
+        let mut new_conf = conf.clone();
+        new_conf.basic.name = Some("pingap-test".to_string());
+        storage
+            .save_config(&new_conf, CATEGORY_BASIC)
+            .await
+            .unwrap();
+        save_version(&storage, &conf, &new_conf, "tree", CATEGORY_BASIC, None)
+            .await
+            .unwrap();
+        // no version for same config
+        save_version(&storage, &new_conf, &new_conf, "tree", "", None)
+            .await
+            .unwrap();
+
+        // the initial config is saved as the first version
+        let versions = storage.list_versions().await.unwrap();
+        assert_eq!(3, versions.len());
+        assert_eq!(Some("initial config".to_string()), versions[0].remark);
+        assert_eq!("tree", versions[2].author);
+        assert_eq!(CATEGORY_BASIC, versions[2].category);
+        assert_eq!(false, versions[2].diff.is_empty());
+
+        // the history files are not loaded as config
+        let current_conf = storage.load_config(false, false).await.unwrap();
+        assert_eq!(new_conf.hash().unwrap(), current_conf.hash().unwrap());
+
+        rollback(&storage, &versions[1].id, "admin").await.unwrap();
+        let current_conf = storage.load_config(false, false).await.unwrap();
+        assert_eq!(conf.hash().unwrap(), current_conf.hash().unwrap());
+        let versions = storage.list_versions().await.unwrap();
+        assert_eq!(4, versions.len());
+        assert_eq!("admin", versions[3].author);
+        assert_eq!(
+            Some(format!("rollback to {}", versions[1].id)),
+            versions[3].remark
+        );
+    }
 }
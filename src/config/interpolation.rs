@@ -0,0 +1,200 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::Result;
+use toml::Value;
+
+const STORAGE_PREFIX: &str = "storage:";
+
+/// Check the name is a valid environment variable name,
+/// e.g. `PINGAP_ADDR`, `_HOME`.
+fn is_env_name(name: &str) -> bool {
+    let mut chars = name.chars();
+    chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+/// Interpolate the string, `${ENV_VAR}` and `${ENV_VAR:-default}` are
+/// replaced by environment variable, `${storage:name}` is replaced by
+/// the value of storage, and `$${` is escaped as `${`.
+/// The unknown or unclosed expression is kept as literal text,
+/// e.g. `${jndi:ldap://127.0.0.1}`, and so is the unset variable without
+/// default, e.g. the named capture `${rest}` of rewrite.
+pub fn interpolate(
+    value: &str,
+    get_storage_value: &dyn Fn(&str) -> Result<String>,
+) -> Result<String> {
+    let mut result = String::with_capacity(value.len());
+    let mut rest = value;
+    while let Some(index) = rest.find("${") {
+        if rest[..index].ends_with('$') {
+            result.push_str(&rest[..index - 1]);
+            result.push_str("${");
+            rest = &rest[index + 2..];
+            continue;
+        }
+        result.push_str(&rest[..index]);
+        let Some(end) = rest[index..].find('}') else {
+            break;
+        };
+        let expr = &rest[index + 2..index + end];
+        let (name, default_value) = expr
+            .split_once(":-")
+            .map(|(name, value)| (name, Some(value)))
+            .unwrap_or((expr, None));
+        let value = if let Some(name) = expr.strip_prefix(STORAGE_PREFIX) {
+            get_storage_value(name.trim())?
+        } else if !is_env_name(name.trim()) {
+            // not an expression, keep it as literal text
+            result.push_str("${");
+            rest = &rest[index + 2..];
+            continue;
+        } else {
+            match (std::env::var(name.trim()), default_value) {
+                (Ok(value), _) => value,
+                (Err(_), Some(value)) => value.to_string(),
+                (Err(_), None) => {
+                    result.push_str("${");
+                    rest = &rest[index + 2..];
+                    continue;
+                },
+            }
+        };
+        result.push_str(&value);
+        rest = &rest[index + end + 1..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Interpolate all string values of toml value.
+pub fn interpolate_value(
+    value: &mut Value,
+    get_storage_value: &dyn Fn(&str) -> Result<String>,
+) -> Result<()> {
+    match value {
+        Value::String(data) if data.contains("${") => {
+            *data = interpolate(data, get_storage_value)?;
+        },
+        Value::Array(items) => {
+            for item in items.iter_mut() {
+                interpolate_value(item, get_storage_value)?;
+            }
+        },
+        Value::Table(table) => {
+            for (_, item) in table.iter_mut() {
+                interpolate_value(item, get_storage_value)?;
+            }
+        },
+        _ => {},
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{interpolate, interpolate_value};
+    use crate::config::{Error, Result};
+    use pretty_assertions::assert_eq;
+
+    fn get_storage_value(name: &str) -> Result<String> {
+        if name == "token" {
+            return Ok("pingap".to_string());
+        }
+        Err(Error::Invalid {
+            message: format!("storage({name}) is not found"),
+        })
+    }
+
+    #[test]
+    fn test_interpolate() {
+        std::env::set_var("PINGAP_TEST_UPSTREAM", "10.0.0.1:3000");
+        assert_eq!(
+            "10.0.0.1:3000",
+            interpolate("${PINGAP_TEST_UPSTREAM}", &get_storage_value).unwrap()
+        );
+        assert_eq!(
+            "addr: 10.0.0.1:3000, port: 80",
+            interpolate(
+                "addr: ${PINGAP_TEST_UPSTREAM:-127.0.0.1}, port: ${PINGAP_TEST_PORT:-80}",
+                &get_storage_value
+            )
+            .unwrap()
+        );
+        assert_eq!(
+            "Bearer pingap",
+            interpolate("Bearer ${storage:token}", &get_storage_value).unwrap()
+        );
+        assert_eq!(
+            "${PINGAP_TEST_UPSTREAM}",
+            interpolate("$${PINGAP_TEST_UPSTREAM}", &get_storage_value)
+                .unwrap()
+        );
+        assert_eq!(
+            "${PINGAP_TEST_NOT_EXISTS}",
+            interpolate("${PINGAP_TEST_NOT_EXISTS}", &get_storage_value)
+                .unwrap()
+        );
+        assert_eq!(
+            "^/api/(?P<rest>.*) /${rest}",
+            interpolate("^/api/(?P<rest>.*) /${rest}", &get_storage_value)
+                .unwrap()
+        );
+        assert_eq!(
+            "Invalid error storage(secret) is not found",
+            interpolate("${storage:secret}", &get_storage_value)
+                .unwrap_err()
+                .to_string()
+        );
+        assert_eq!(
+            "${storage:token",
+            interpolate("${storage:token", &get_storage_value).unwrap()
+        );
+        assert_eq!(
+            "${jndi:ldap://127.0.0.1/a}",
+            interpolate("${jndi:ldap://127.0.0.1/a}", &get_storage_value)
+                .unwrap()
+        );
+        assert_eq!(
+            "{ ${} } 10.0.0.1:3000",
+            interpolate("{ ${} } ${PINGAP_TEST_UPSTREAM}", &get_storage_value)
+                .unwrap()
+        );
+        assert_eq!(
+            "${a-b} 80",
+            interpolate("${a-b} ${PINGAP_TEST_PORT:-80}", &get_storage_value)
+                .unwrap()
+        );
+
+        let mut value: toml::Value = toml::from_str(
+            r#"
+addrs = ["${PINGAP_TEST_UPSTREAM}", "127.0.0.1:3001"]
+[headers]
+authorization = "Bearer ${storage:token}"
+"#,
+        )
+        .unwrap();
+        interpolate_value(&mut value, &get_storage_value).unwrap();
+        assert_eq!(
+            r#"["10.0.0.1:3000", "127.0.0.1:3001"]"#,
+            value["addrs"].to_string()
+        );
+        assert_eq!(
+            "Bearer pingap",
+            value["headers"]["authorization"].as_str().unwrap()
+        );
+    }
+}
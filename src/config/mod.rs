@@ -12,15 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::util;
 use async_trait::async_trait;
 use etcd_client::WatchStream;
 use once_cell::sync::OnceCell;
+use serde::{Deserialize, Serialize};
 use snafu::Snafu;
 use std::time::Duration;
 
 mod common;
 mod etcd;
 mod file;
+mod interpolation;
 
 #[derive(Debug, Snafu)]
 pub enum Error {
@@ -82,6 +85,28 @@ impl Observer {
     }
 }
 
+/// Take the `include` patterns of config data, the directive is removed
+/// from data, so the config data of multiple files can be concatenated.
+fn take_includes(buf: Vec<u8>) -> (Vec<u8>, Vec<String>) {
+    let Ok(mut table) =
+        toml::from_str::<toml::Table>(&String::from_utf8_lossy(&buf))
+    else {
+        return (buf, vec![]);
+    };
+    let Some(value) = table.remove("include") else {
+        return (buf, vec![]);
+    };
+    let includes = match value {
+        toml::Value::String(value) => vec![value],
+        toml::Value::Array(values) => values
+            .iter()
+            .filter_map(|item| item.as_str().map(|item| item.to_string()))
+            .collect(),
+        _ => vec![],
+    };
+    (table.to_string().into_bytes(), includes)
+}
+
 #[async_trait]
 pub trait ConfigStorage {
     async fn load_config(
@@ -102,6 +127,52 @@ pub trait ConfigStorage {
             etcd_watch_stream: None,
         })
     }
+    /// Save the version of config to history.
+    async fn save_version(&self, _version: &ConfigVersion) -> Result<()> {
+        Ok(())
+    }
+    /// List the versions of config, sorted by created time.
+    async fn list_versions(&self) -> Result<Vec<ConfigVersion>> {
+        Ok(vec![])
+    }
+}
+
+/// The max count of config versions, the oldest versions are removed.
+const MAX_CONFIG_VERSIONS: usize = 100;
+
+/// The saved version of config.
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ConfigVersion {
+    pub id: String,
+    // the created time(ms)
+    pub created_at: u64,
+    pub author: String,
+    pub category: String,
+    pub remark: Option<String>,
+    // the diff from previous config
+    pub diff: Vec<String>,
+    // the toml of whole config
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub data: String,
+}
+
+impl ConfigVersion {
+    fn new(conf: &PingapConf, author: &str, category: &str) -> Result<Self> {
+        let data = toml::to_string_pretty(conf)
+            .map_err(|e| Error::Ser { source: e })?;
+        Ok(Self {
+            id: uuid::Uuid::now_v7().to_string(),
+            created_at: util::now().as_millis() as u64,
+            author: author.to_string(),
+            category: category.to_string(),
+            data,
+            ..Default::default()
+        })
+    }
+}
+
+fn sort_versions(versions: &mut [ConfigVersion]) {
+    versions.sort_by(|a, b| (a.created_at, &a.id).cmp(&(b.created_at, &b.id)));
 }
 
 static CONFIG_STORAGE: OnceCell<Box<(dyn ConfigStorage + Sync + Send)>> =
@@ -157,13 +228,126 @@ pub fn get_config_storage() -> Option<&'static (dyn ConfigStorage + Sync + Send)
     }
 }
 
-pub async fn save_config(conf: &PingapConf, category: &str) -> Result<()> {
+/// Save the config of category, and the version is saved to history.
+pub async fn save_config(
+    conf: &PingapConf,
+    category: &str,
+    author: &str,
+) -> Result<()> {
+    let Some(storage) = CONFIG_STORAGE.get() else {
+        return Err(Error::Invalid {
+            message: "storage is not inited".to_string(),
+        });
+    };
+    let current_conf = storage.load_config(false, true).await?;
+    storage.save_config(conf, category).await?;
+    save_version(
+        storage.as_ref(),
+        &current_conf,
+        conf,
+        author,
+        category,
+        None,
+    )
+    .await
+}
+
+/// Save the new version of config if it's modified,
+/// the current config is saved as the first version if history is empty.
+async fn save_version(
+    storage: &(dyn ConfigStorage + Sync + Send),
+    current_conf: &PingapConf,
+    conf: &PingapConf,
+    author: &str,
+    category: &str,
+    remark: Option<String>,
+) -> Result<()> {
+    let (_, diff) = current_conf.diff(conf);
+    if diff.is_empty() {
+        return Ok(());
+    }
+    if storage.list_versions().await?.is_empty() {
+        let mut version = ConfigVersion::new(current_conf, "", "")?;
+        version.remark = Some("initial config".to_string());
+        storage.save_version(&version).await?;
+    }
+    let mut version = ConfigVersion::new(conf, author, category)?;
+    version.diff = diff;
+    version.remark = remark;
+    storage.save_version(&version).await
+}
+
+/// List the versions of config without data, the newest is the first.
+pub async fn list_config_versions() -> Result<Vec<ConfigVersion>> {
     let Some(storage) = CONFIG_STORAGE.get() else {
         return Err(Error::Invalid {
             message: "storage is not inited".to_string(),
         });
     };
-    storage.save_config(conf, category).await
+    let mut versions = storage.list_versions().await?;
+    versions.reverse();
+    for version in versions.iter_mut() {
+        version.data = "".to_string();
+    }
+    Ok(versions)
+}
+
+/// Get the version of config.
+pub async fn get_config_version(id: &str) -> Result<ConfigVersion> {
+    let Some(storage) = CONFIG_STORAGE.get() else {
+        return Err(Error::Invalid {
+            message: "storage is not inited".to_string(),
+        });
+    };
+    get_version(storage.as_ref(), id).await
+}
+
+async fn get_version(
+    storage: &(dyn ConfigStorage + Sync + Send),
+    id: &str,
+) -> Result<ConfigVersion> {
+    storage
+        .list_versions()
+        .await?
+        .into_iter()
+        .find(|item| item.id == id)
+        .ok_or_else(|| Error::Invalid {
+            message: format!("config version({id}) is not found"),
+        })
+}
+
+/// Rollback the config to the version, all categories are saved,
+/// so the config will be reloaded as other updates.
+pub async fn rollback_config(id: &str, author: &str) -> Result<()> {
+    let Some(storage) = CONFIG_STORAGE.get() else {
+        return Err(Error::Invalid {
+            message: "storage is not inited".to_string(),
+        });
+    };
+    rollback(storage.as_ref(), id, author).await
+}
+
+async fn rollback(
+    storage: &(dyn ConfigStorage + Sync + Send),
+    id: &str,
+    author: &str,
+) -> Result<()> {
+    let version = get_version(storage, id).await?;
+    let conf = PingapConf::new(version.data.as_bytes(), false)?;
+    conf.validate()?;
+    let current_conf = storage.load_config(false, true).await?;
+    for category in common::list_category() {
+        storage.save_config(&conf, &category).await?;
+    }
+    save_version(
+        storage,
+        &current_conf,
+        &conf,
+        author,
+        "rollback",
+        Some(format!("rollback to {id}")),
+    )
+    .await
 }
 
 pub async fn sync_config(path: &str) -> Result<()> {
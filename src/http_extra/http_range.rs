@@ -0,0 +1,165 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// too many ranges will be ignored, avoid the abuse of multipart response
+const MAX_RANGES: usize = 32;
+
+/// The byte range of http request, both start and end are inclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// The size of byte range.
+    pub fn size(&self) -> u64 {
+        self.end - self.start + 1
+    }
+    /// The value of `Content-Range` header.
+    pub fn content_range(&self, total: u64) -> String {
+        format!("bytes {}-{}/{total}", self.start, self.end)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub enum RangeResult {
+    // the range is invalid or not supported, response the full content
+    Full,
+    // none of the ranges is satisfiable, response 416
+    Unsatisfiable,
+    Ranges(Vec<ByteRange>),
+}
+
+/// Parse the `Range` header of request, e.g. `bytes=0-99,200-,-100`.
+pub fn parse_range(value: &str, total: u64) -> RangeResult {
+    let Some((unit, value)) = value.split_once('=') else {
+        return RangeResult::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return RangeResult::Full;
+    }
+    let mut ranges = vec![];
+    let mut count = 0;
+    for item in value.split(',') {
+        let item = item.trim();
+        if item.is_empty() {
+            continue;
+        }
+        count += 1;
+        if count > MAX_RANGES {
+            return RangeResult::Full;
+        }
+        let Some((start, end)) = item.split_once('-') else {
+            return RangeResult::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+        if start.is_empty() {
+            // the suffix range: last n bytes
+            let Ok(suffix) = end.parse::<u64>() else {
+                return RangeResult::Full;
+            };
+            if suffix == 0 || total == 0 {
+                continue;
+            }
+            ranges.push(ByteRange {
+                start: total.saturating_sub(suffix),
+                end: total - 1,
+            });
+            continue;
+        }
+        let Ok(start) = start.parse::<u64>() else {
+            return RangeResult::Full;
+        };
+        let end = if end.is_empty() {
+            u64::MAX
+        } else {
+            let Ok(end) = end.parse::<u64>() else {
+                return RangeResult::Full;
+            };
+            if end < start {
+                return RangeResult::Full;
+            }
+            end
+        };
+        if start >= total {
+            continue;
+        }
+        ranges.push(ByteRange {
+            start,
+            end: end.min(total - 1),
+        });
+    }
+    if ranges.is_empty() {
+        return RangeResult::Unsatisfiable;
+    }
+    RangeResult::Ranges(ranges)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_range, ByteRange, RangeResult};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            RangeResult::Ranges(vec![ByteRange { start: 0, end: 99 }]),
+            parse_range("bytes=0-99", 1000)
+        );
+        assert_eq!(
+            RangeResult::Ranges(vec![
+                ByteRange { start: 0, end: 9 },
+                ByteRange {
+                    start: 200,
+                    end: 999
+                },
+                ByteRange {
+                    start: 900,
+                    end: 999
+                },
+            ]),
+            parse_range("bytes=0-9, 200-,-100", 1000)
+        );
+        assert_eq!(
+            RangeResult::Ranges(vec![ByteRange {
+                start: 900,
+                end: 999
+            }]),
+            parse_range("bytes=900-2000", 1000)
+        );
+        assert_eq!(
+            RangeResult::Ranges(vec![ByteRange { start: 0, end: 999 }]),
+            parse_range("bytes=-2000", 1000)
+        );
+        assert_eq!(
+            RangeResult::Unsatisfiable,
+            parse_range("bytes=1000-", 1000)
+        );
+        assert_eq!(RangeResult::Unsatisfiable, parse_range("bytes=-0", 1000));
+        assert_eq!(RangeResult::Full, parse_range("items=0-1", 1000));
+        assert_eq!(RangeResult::Full, parse_range("bytes=10-1", 1000));
+        assert_eq!(RangeResult::Full, parse_range("bytes=a-1", 1000));
+        assert_eq!(RangeResult::Full, parse_range("bytes", 1000));
+        let many = vec!["0-0"; 33].join(",");
+        assert_eq!(
+            RangeResult::Full,
+            parse_range(&format!("bytes={many}"), 10)
+        );
+
+        let range = ByteRange { start: 0, end: 99 };
+        assert_eq!(100, range.size());
+        assert_eq!("bytes 0-99/1000", range.content_range(1000));
+    }
+}
@@ -166,10 +166,14 @@ impl HttpResponse {
             .as_ref()
             .map_or_else(|| fix_size, |headers| headers.len() + fix_size);
         let mut resp = ResponseHeader::build(self.status, Some(size))?;
-        resp.insert_header(
-            header::CONTENT_LENGTH,
-            self.body.len().to_string(),
-        )?;
+        // the content length of not modified response should be the size
+        // of full content, so it's not set
+        if self.status != StatusCode::NOT_MODIFIED {
+            resp.insert_header(
+                header::CONTENT_LENGTH,
+                self.body.len().to_string(),
+            )?;
+        }
 
         // set cache control
         let cache_control = get_cache_control(self.max_age, self.cache_private);
@@ -206,6 +210,8 @@ impl HttpResponse {
 pub struct HttpChunkResponse<'r, R> {
     pub reader: Pin<&'r mut R>,
     pub chunk_size: usize,
+    // http response status
+    pub status: StatusCode,
     // max age of http response
     pub max_age: Option<u32>,
     // private for cache control
@@ -226,6 +232,7 @@ where
         Self {
             reader: Pin::new(r),
             chunk_size: DEFAULT_BUF_SIZE,
+            status: StatusCode::OK,
             max_age: None,
             headers: None,
             cache_private: None,
@@ -233,7 +240,11 @@ where
     }
     /// Get the response header for http chunk response.
     pub fn get_response_header(&self) -> pingora::Result<ResponseHeader> {
-        let mut resp = ResponseHeader::build(StatusCode::OK, Some(4))?;
+        let mut resp = ResponseHeader::build(self.status, Some(4))?;
+        // set cache control, it can be overridden by the headers
+        let cache_control = get_cache_control(self.max_age, self.cache_private);
+        resp.insert_header(cache_control.0, cache_control.1)?;
+
         if let Some(headers) = &self.headers {
             for (name, value) in headers {
                 resp.insert_header(name.to_owned(), value)?;
@@ -242,9 +253,6 @@ where
 
         let chunked = HTTP_HEADER_TRANSFER_CHUNKED.clone();
         resp.insert_header(chunked.0, chunked.1)?;
-
-        let cache_control = get_cache_control(self.max_age, self.cache_private);
-        resp.insert_header(cache_control.0, cache_control.1)?;
         Ok(resp)
     }
     /// Send the chunk data to client until the end of reader, return how many bytes were sent.
@@ -398,8 +406,12 @@ mod tests {
         );
         let header = resp.get_response_header().unwrap();
         assert_eq!(
-            r###"ResponseHeader { base: Parts { status: 200, version: HTTP/1.1, headers: {"contont-type": "text/html", "transfer-encoding": "chunked", "cache-control": "public, max-age=3600"} }, header_name_map: Some({"contont-type": CaseHeaderName(b"contont-type"), "transfer-encoding": CaseHeaderName(b"Transfer-Encoding"), "cache-control": CaseHeaderName(b"Cache-Control")}), reason_phrase: None }"###,
+            r###"ResponseHeader { base: Parts { status: 200, version: HTTP/1.1, headers: {"cache-control": "public, max-age=3600", "contont-type": "text/html", "transfer-encoding": "chunked"} }, header_name_map: Some({"cache-control": CaseHeaderName(b"Cache-Control"), "contont-type": CaseHeaderName(b"contont-type"), "transfer-encoding": CaseHeaderName(b"Transfer-Encoding")}), reason_phrase: None }"###,
             format!("{header:?}")
         );
+
+        resp.status = StatusCode::PARTIAL_CONTENT;
+        let header = resp.get_response_header().unwrap();
+        assert_eq!(StatusCode::PARTIAL_CONTENT, header.status);
     }
 }
@@ -13,7 +13,9 @@
 // limitations under the License.
 
 mod http_header;
+mod http_range;
 mod http_response;
 
 pub use http_header::*;
+pub use http_range::*;
 pub use http_response::*;
@@ -12,7 +12,10 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
-use crate::acme::{new_lets_encrypt_service, new_tls_validity_service};
+use crate::acme::{
+    new_internal_ca_service, new_lets_encrypt_service,
+    new_ocsp_stapling_service, new_tls_validity_service,
+};
 use crate::cache::new_file_storage_clear_service;
 use crate::config::ETCD_PROTOCOL;
 use crate::service::{new_auto_restart_service, new_observer_service};
@@ -34,6 +37,7 @@ use std::time::Duration;
 use tracing::{error, info};
 
 mod acme;
+mod blocklist;
 mod cache;
 mod config;
 mod discovery;
@@ -64,7 +68,7 @@ mod perf;
 #[command(author, version, about, long_about = None)]
 struct Args {
     /// The config file or directory
-    #[arg(short, long)]
+    #[arg(short, long, required_unless_present = "schema", default_value = "")]
     conf: String,
     /// Whether should run this server in the background
     #[arg(short, long)]
@@ -99,6 +103,9 @@ struct Args {
     /// Sync config to other storage
     #[arg(long)]
     sync: Option<String>,
+    /// Print the json schema of plugin config and exit
+    #[arg(long)]
+    schema: bool,
 }
 
 fn new_server_conf(
@@ -283,6 +290,13 @@ fn parse_arguments() -> Args {
 
 fn run() -> Result<(), Box<dyn Error>> {
     let args = parse_arguments();
+    if args.schema {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&plugin::get_plugin_json_schema())?
+        );
+        return Ok(());
+    }
     if let Some(admin) = &args.admin {
         set_admin_addr(admin);
     }
@@ -408,9 +422,18 @@ fn run() -> Result<(), Box<dyn Error>> {
         ));
     }
 
-    if let Some(dir) = &conf.basic.cache_directory {
-        if let Some(task) = new_file_storage_clear_service(dir) {
-            my_server.add_service(background_service("StorageClear", task));
+    let cache_directories = conf.basic.get_cache_directories();
+    if !cache_directories.is_empty() {
+        match plugin::get_cache_backend() {
+            Ok(cache) => {
+                my_server.add_service(background_service(
+                    "StorageClear",
+                    new_file_storage_clear_service(cache),
+                ));
+            },
+            Err(e) => {
+                error!(error = e.to_string(), "init cache backend fail");
+            },
         }
     }
 
@@ -418,6 +441,7 @@ fn run() -> Result<(), Box<dyn Error>> {
         error!(error = e.to_string(), "init plugins fail",);
     }
 
+    let blocklist_file = conf.basic.blocklist_file.clone();
     let mut server_conf_list: Vec<ServerConf> = conf.into();
 
     if let Some(addr) = &get_admin_addr() {
@@ -519,6 +543,22 @@ fn run() -> Result<(), Box<dyn Error>> {
         "TlsValidity",
         new_tls_validity_service(),
     ));
+    my_server.add_service(background_service(
+        "OcspStapling",
+        new_ocsp_stapling_service(),
+    ));
+    my_server.add_service(background_service(
+        "InternalCa",
+        new_internal_ca_service(),
+    ));
+    my_server.add_service(background_service(
+        "GeoipReload",
+        plugin::new_geoip_reload_service(),
+    ));
+    my_server.add_service(background_service(
+        "Blocklist",
+        blocklist::new_blocklist_service(blocklist_file),
+    ));
     my_server.add_service(background_service(
         "UpstreamHc",
         new_upstream_health_check_task(Duration::from_secs(10)),
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{boolean, string, PluginField};
 use super::{get_bool_conf, get_hash_key, get_str_conf, Error, Plugin, Result};
 use crate::config::{PluginConf, PluginStep};
 use crate::http_extra::HttpResponse;
@@ -27,6 +28,10 @@ pub struct AcceptEncoding {
     plugin_step: PluginStep,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![string("encodings"), boolean("only_one_encoding")]
+}
+
 impl TryFrom<&PluginConf> for AcceptEncoding {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
@@ -12,10 +12,13 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{int, string, strings, PluginField};
 use super::{
     get_hash_key, get_int_conf, get_step_conf, get_str_conf,
     get_str_slice_conf, Error, Plugin, Result,
 };
+use crate::blocklist::{self, BlockSource};
+use crate::cache::CachePurgeParams;
 use crate::config::{
     self, get_current_config, save_config, BasicConf, CertificateConf,
     LocationConf, PluginCategory, PluginConf, PluginStep, ServerConf,
@@ -154,6 +157,14 @@ struct TomlJson {
     pub original: String,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        string("path"),
+        strings("authorizations"),
+        int("ip_fail_limit"),
+    ]
+}
+
 impl TryFrom<&PluginConf> for AdminServe {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
@@ -204,6 +215,19 @@ struct AesParmas {
     data: String,
 }
 
+#[derive(Deserialize, Debug)]
+struct BlocklistParams {
+    network: String,
+    #[serde(default, with = "humantime_serde")]
+    ttl: Option<Duration>,
+    reason: Option<String>,
+}
+
+#[derive(Serialize, Debug)]
+struct CachePurgeResp {
+    count: usize,
+}
+
 #[derive(Serialize, Deserialize, Debug)]
 struct AesResp {
     value: String,
@@ -285,13 +309,14 @@ impl AdminServe {
         &self,
         category: &str,
         name: &str,
+        author: &str,
     ) -> pingora::Result<HttpResponse> {
         let mut conf = self.load_config(false).await?;
         conf.remove(category, name).map_err(|e| {
             error!(error = e.to_string(), "validate config fail");
             util::new_internal_error(400, e.to_string())
         })?;
-        save_config(&conf, category).await.map_err(|e| {
+        save_config(&conf, category, author).await.map_err(|e| {
             error!(error = e.to_string(), "save config fail");
             util::new_internal_error(400, e.to_string())
         })?;
@@ -302,6 +327,7 @@ impl AdminServe {
         session: &mut Session,
         category: &str,
         name: &str,
+        author: &str,
     ) -> pingora::Result<HttpResponse> {
         if name.is_empty() {
             return Err(util::new_internal_error(
@@ -388,7 +414,7 @@ impl AdminServe {
                 conf.basic = basic_conf;
             },
         };
-        save_config(&conf, category).await.map_err(|e| {
+        save_config(&conf, category, author).await.map_err(|e| {
             error!(error = e.to_string(), "save config fail");
             util::new_internal_error(400, e.to_string())
         })?;
@@ -396,6 +422,26 @@ impl AdminServe {
     }
 }
 
+/// Get the author of config update,
+/// it's the user of basic auth or the client ip.
+fn get_author(session: &Session, ip: &str) -> String {
+    let value =
+        util::get_req_header_value(session.req_header(), "Authorization")
+            .unwrap_or_default();
+    if let Some(value) = value.strip_prefix("Basic ") {
+        let buf = base64_decode(value.trim()).unwrap_or_default();
+        if let Some((user, _)) = std::str::from_utf8(&buf)
+            .unwrap_or_default()
+            .split_once(':')
+        {
+            if !user.is_empty() {
+                return user.to_string();
+            }
+        }
+    }
+    ip.to_string()
+}
+
 fn get_method_path(session: &Session) -> (Method, String) {
     let req_header = session.req_header();
     let method = req_header.method.clone();
@@ -413,12 +459,15 @@ impl Plugin for AdminServe {
         &self,
         step: PluginStep,
         session: &mut Session,
-        _ctx: &mut State,
+        ctx: &mut State,
     ) -> pingora::Result<Option<HttpResponse>> {
         if self.plugin_step != step {
             return Ok(None);
         }
-        let ip = util::get_client_ip(session);
+        let ip = ctx
+            .client_ip
+            .clone()
+            .unwrap_or_else(|| util::get_client_ip(session));
         if !self.ip_fail_limit.validate(&ip).await {
             return Ok(Some(HttpResponse {
                 status: StatusCode::FORBIDDEN,
@@ -449,6 +498,7 @@ impl Plugin for AdminServe {
             header.set_uri(uri);
         }
 
+        let author = get_author(session, &ip);
         let (method, mut path) = get_method_path(session);
         let api_prefix = "/api";
         if path.starts_with(api_prefix) {
@@ -465,14 +515,17 @@ impl Plugin for AdminServe {
                     if params.len() < 4 {
                         Err(pingora::Error::new_str("Url is invalid(no name)"))
                     } else {
-                        self.update_config(session, category, params[3]).await
+                        self.update_config(
+                            session, category, params[3], &author,
+                        )
+                        .await
                     }
                 },
                 Method::DELETE => {
                     if params.len() < 4 {
                         Err(pingora::Error::new_str("Url is invalid(no name)"))
                     } else {
-                        self.remove_config(category, params[3]).await
+                        self.remove_config(category, params[3], &author).await
                     }
                 },
                 _ => self.get_config(category).await,
@@ -541,6 +594,84 @@ impl Plugin for AdminServe {
             } else {
                 HttpResponse::no_content()
             }
+        } else if path == "/blocklist" {
+            match method {
+                Method::POST => {
+                    let buf = get_request_body(session).await?;
+                    let params: BlocklistParams =
+                        serde_json::from_slice(buf.as_ref()).map_err(|e| {
+                            util::new_internal_error(400, e.to_string())
+                        })?;
+                    blocklist::add(
+                        &params.network,
+                        params.ttl,
+                        BlockSource::Admin,
+                        params.reason,
+                    )
+                    .map_err(|e| {
+                        util::new_internal_error(400, e.to_string())
+                    })?;
+                    HttpResponse::no_content()
+                },
+                Method::DELETE => {
+                    let network =
+                        util::get_query_value(session.req_header(), "network")
+                            .unwrap_or_default();
+                    let network = urlencoding::decode(network)
+                        .map_err(|e| {
+                            util::new_internal_error(400, e.to_string())
+                        })?
+                        .to_string();
+                    blocklist::remove(&network).map_err(|e| {
+                        util::new_internal_error(400, e.to_string())
+                    })?;
+                    HttpResponse::no_content()
+                },
+                _ => HttpResponse::try_from_json(&blocklist::list()).unwrap_or(
+                    HttpResponse::unknown_error("Json serde fail".into()),
+                ),
+            }
+        } else if path == "/cache/purge" && method == Method::POST {
+            let buf = get_request_body(session).await?;
+            let params: CachePurgeParams = serde_json::from_slice(buf.as_ref())
+                .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+            let count = super::cache::purge_cache(&params)
+                .await
+                .map_err(|e| util::new_internal_error(400, e.to_string()))?;
+            HttpResponse::try_from_json(&CachePurgeResp { count }).unwrap_or(
+                HttpResponse::unknown_error("Json serde fail".into()),
+            )
+        } else if path.starts_with("/versions") {
+            // /versions, /versions/{id} and /versions/{id}/rollback
+            let id = category;
+            let result = if method == Method::POST
+                && params.get(3) == Some(&"rollback")
+            {
+                config::rollback_config(id, &author)
+                    .await
+                    .map(|_| HttpResponse::no_content())
+            } else if id.is_empty() {
+                config::list_config_versions().await.map(|versions| {
+                    HttpResponse::try_from_json(&versions).unwrap_or(
+                        HttpResponse::unknown_error("Json serde fail".into()),
+                    )
+                })
+            } else {
+                config::get_config_version(id).await.map(|version| {
+                    HttpResponse::try_from_json(&version).unwrap_or(
+                        HttpResponse::unknown_error("Json serde fail".into()),
+                    )
+                })
+            };
+            result.unwrap_or_else(|e| {
+                error!(error = e.to_string(), "handle config version fail");
+                HttpResponse::bad_request(e.to_string().into())
+            })
+        } else if path == "/schema" {
+            HttpResponse::try_from_json(&super::get_plugin_json_schema())
+                .unwrap_or(HttpResponse::unknown_error(
+                    "Json serde fail".into(),
+                ))
         } else if path == "/aes" {
             let buf = get_request_body(session).await?;
             let params: AesParmas = serde_json::from_slice(buf.as_ref())
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{boolean, duration, strings, PluginField};
 use super::{
     get_bool_conf, get_hash_key, get_step_conf, get_str_conf,
     get_str_slice_conf, Error, Plugin, Result,
@@ -40,6 +41,14 @@ pub struct BasicAuth {
     hash_value: String,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        strings("authorizations"),
+        boolean("hide_credentials"),
+        duration("delay"),
+    ]
+}
+
 impl TryFrom<&PluginConf> for BasicAuth {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
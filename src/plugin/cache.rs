@@ -12,11 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{boolean, duration, size, string, strings, PluginField};
 use super::{
     get_bool_conf, get_hash_key, get_step_conf, get_str_conf,
     get_str_slice_conf, Error, Plugin, Result,
 };
-use crate::cache::{new_file_cache, new_tiny_ufo_cache, HttpCache};
+use crate::cache::{
+    new_file_cache, new_tiered_cache, new_tiny_ufo_cache, CachePurgeParams,
+    HttpCache, VaryRules,
+};
 use crate::config::{
     get_current_config, PluginCategory, PluginConf, PluginStep,
 };
@@ -36,6 +40,7 @@ use pingora::cache::lock::CacheLock;
 use pingora::cache::predictor::{CacheablePredictor, Predictor};
 use pingora::proxy::Session;
 use std::str::FromStr;
+use std::sync::Arc;
 use std::time::Duration;
 use tracing::debug;
 
@@ -54,14 +59,18 @@ pub struct Cache {
     http_cache: &'static HttpCache,
     max_file_size: usize,
     max_ttl: Option<Duration>,
+    stale_while_revalidate: Option<Duration>,
+    stale_if_error: Option<Duration>,
     namespace: Option<String>,
     headers: Option<Vec<String>>,
+    vary_rules: Option<Arc<VaryRules>>,
     check_cache_control: bool,
     purge_ip_rules: util::IpRules,
     hash_value: String,
 }
 
-fn get_cache_backend() -> Result<&'static HttpCache> {
+/// Get the global cache backend, it's initialized at the first time.
+pub fn get_cache_backend() -> Result<&'static HttpCache> {
     // get global cache backend
     CACHE_BACKEND.get_or_try_init(|| {
         let basic_conf = &get_current_config().basic;
@@ -70,9 +79,22 @@ fn get_cache_backend() -> Result<&'static HttpCache> {
         } else {
             MAX_MEMORY_SIZE
         };
-        // file cache
-        let cache = if let Some(dir) = &basic_conf.cache_directory {
-            new_file_cache(dir.as_str()).map_err(|e| Error::Invalid {
+        let memory_size = basic_conf
+            .cache_memory_size
+            .map(|item| item.as_u64() as usize);
+        let cache_directories = basic_conf.get_cache_directories();
+        let cache = if !cache_directories.is_empty() {
+            let result = if let Some(memory_size) = memory_size {
+                // tiered cache: memory and file
+                new_tiered_cache(
+                    &cache_directories,
+                    memory_size.min(ByteSize::gb(1).as_u64() as usize),
+                )
+            } else {
+                // file cache
+                new_file_cache(&cache_directories)
+            };
+            result.map_err(|e| Error::Invalid {
                 category: "cache_backend".to_string(),
                 message: e.to_string(),
             })?
@@ -84,6 +106,18 @@ fn get_cache_backend() -> Result<&'static HttpCache> {
     })
 }
 
+/// Purge the cache objects of global cache backend,
+/// returns the count of purged objects.
+pub async fn purge_cache(params: &CachePurgeParams) -> Result<usize> {
+    let Some(cache) = CACHE_BACKEND.get() else {
+        return Ok(0);
+    };
+    cache.purge(params).await.map_err(|e| Error::Invalid {
+        category: PluginCategory::Cache.to_string(),
+        message: e.to_string(),
+    })
+}
+
 fn get_eviction_manager() -> &'static Manager {
     EVICTION_MANAGER.get_or_init(|| {
         let size = if let Some(cache_max_size) =
@@ -119,6 +153,23 @@ fn get_predictor() -> &'static (dyn CacheablePredictor + Sync) {
     PREDICTOR.get_or_init(|| Predictor::new(128, None))
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        string("namespace"),
+        duration("lock"),
+        size("max_file_size"),
+        duration("max_ttl"),
+        duration("stale_while_revalidate"),
+        duration("stale_if_error"),
+        boolean("eviction"),
+        boolean("predictor"),
+        strings("headers"),
+        strings("vary"),
+        boolean("check_cache_control"),
+        strings("purge_ip_list"),
+    ]
+}
+
 impl TryFrom<&PluginConf> for Cache {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
@@ -153,6 +204,21 @@ impl TryFrom<&PluginConf> for Cache {
             None
         };
 
+        let parse_stale = |key: &str| -> Result<Option<Duration>> {
+            let value = get_str_conf(value, key);
+            if value.is_empty() {
+                return Ok(None);
+            }
+            let d = parse_duration(&value).map_err(|e| Error::Invalid {
+                category: PluginCategory::Cache.to_string(),
+                message: e.to_string(),
+            })?;
+            Ok(Some(d))
+        };
+        // the stale directives of response cache-control take precedence
+        let stale_while_revalidate = parse_stale("stale_while_revalidate")?;
+        let stale_if_error = parse_stale("stale_if_error")?;
+
         let max_file_size = get_str_conf(value, "max_file_size");
         let max_file_size = if !max_file_size.is_empty() {
             ByteSize::from_str(&max_file_size).map_err(|e| Error::Invalid {
@@ -175,6 +241,13 @@ impl TryFrom<&PluginConf> for Cache {
             Some(headers)
         };
 
+        let vary = get_str_slice_conf(value, "vary");
+        let vary_rules = if vary.is_empty() {
+            None
+        } else {
+            Some(Arc::new(VaryRules::new(&vary)))
+        };
+
         let predictor = if value.contains_key("predictor") {
             Some(get_predictor())
         } else {
@@ -184,17 +257,27 @@ impl TryFrom<&PluginConf> for Cache {
         let purge_ip_rules =
             util::IpRules::new(&get_str_slice_conf(value, "purge_ip_list"));
 
+        let mut lock = get_cache_lock(lock);
+        // stale while revalidate only works with cache lock,
+        // the request holding the lock updates the cache in background
+        if stale_while_revalidate.is_some() && lock.is_none() {
+            lock = get_cache_lock(Duration::from_secs(1));
+        }
+
         let params = Self {
             hash_value,
             http_cache: cache,
             plugin_step: step,
             eviction,
             predictor,
-            lock: get_cache_lock(lock),
+            lock,
             max_ttl,
+            stale_while_revalidate,
+            stale_if_error,
             max_file_size: max_file_size.as_u64() as usize,
             namespace,
             headers,
+            vary_rules,
             purge_ip_rules,
             check_cache_control: get_bool_conf(value, "check_cache_control"),
         };
@@ -264,10 +347,11 @@ impl Plugin for Cache {
             ctx.cache_prefix = Some(prefix);
         }
         if method == METHOD_PURGE.to_owned() {
-            let found = match self
-                .purge_ip_rules
-                .matched(&util::get_client_ip(session))
-            {
+            let client_ip = ctx
+                .client_ip
+                .clone()
+                .unwrap_or_else(|| util::get_client_ip(session));
+            let found = match self.purge_ip_rules.matched(&client_ip) {
                 Ok(matched) => matched,
                 Err(e) => {
                     return Ok(Some(HttpResponse::bad_request(
@@ -288,13 +372,18 @@ impl Plugin for Cache {
                 Method::GET.as_ref(),
                 &session.req_header().uri,
             );
-            self.http_cache.cached.remove(&key.combined()).await?;
+            self.http_cache.remove(&key.combined()).await?;
             return Ok(Some(HttpResponse::no_content()));
         }
 
         // max age of cache control
         ctx.cache_max_ttl = self.max_ttl;
+        ctx.cache_stale_while_revalidate =
+            self.stale_while_revalidate.map(|d| d.as_secs() as u32);
+        ctx.cache_stale_if_error =
+            self.stale_if_error.map(|d| d.as_secs() as u32);
         ctx.check_cache_control = self.check_cache_control;
+        ctx.cache_vary_rules = self.vary_rules.clone();
 
         session.cache.enable(
             self.http_cache,
@@ -336,6 +425,9 @@ lock = "2s"
 max_file_size = "100kb"
 predictor = true
 max_ttl = "1m"
+stale_while_revalidate = "10s"
+stale_if_error = "1h"
+vary = ["Accept-Encoding", "Cookie:lang"]
 "###,
             )
             .unwrap(),
@@ -349,7 +441,33 @@ max_ttl = "1m"
         assert_eq!(true, params.lock.is_some());
         assert_eq!(100 * 1000, params.max_file_size);
         assert_eq!(60, params.max_ttl.unwrap().as_secs());
+        assert_eq!(10, params.stale_while_revalidate.unwrap().as_secs());
+        assert_eq!(3600, params.stale_if_error.unwrap().as_secs());
+        assert_eq!(true, params.vary_rules.is_some());
         assert_eq!(true, params.predictor.is_some());
+
+        // the lock is required by stale while revalidate
+        let params = Cache::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+lock = "10s"
+stale_while_revalidate = "10s"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(true, params.lock.is_some());
+        let params = Cache::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+lock = "10s"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(true, params.lock.is_none());
     }
     #[tokio::test]
     async fn test_cache() {
@@ -0,0 +1,686 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::schema::{boolean, duration, int, string, strings, PluginField};
+use super::{
+    get_bool_conf, get_hash_key, get_int_conf, get_step_conf, get_str_conf,
+    get_str_slice_conf, Error, Plugin, Result,
+};
+use crate::config::{PluginCategory, PluginConf, PluginStep};
+use crate::http_extra::{
+    HttpResponse, HTTP_HEADER_CONTENT_HTML, HTTP_HEADER_NO_STORE,
+};
+use crate::state::State;
+use crate::util::{self, generate_token, validate_token};
+use ahash::AHashMap;
+use async_trait::async_trait;
+use bytes::Bytes;
+use cookie::Cookie;
+use http::{header, HeaderValue, StatusCode};
+use humantime::parse_duration;
+use pingora::proxy::Session;
+use pingora_limits::rate::Rate;
+use regex::Regex;
+use sha2::{Digest, Sha256};
+use std::sync::Mutex;
+use std::time::Duration;
+use tracing::debug;
+
+// the challenge should be solved in five minutes
+const CHALLENGE_TTL: u64 = 5 * 60;
+
+const CHALLENGE_HTML: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Checking your browser</title>
+</head>
+<body>
+<p>Checking your browser, please wait...</p>
+<noscript>Please enable JavaScript to continue.</noscript>
+<script>
+(async function () {
+  const challenge = "{{challenge}}";
+  const difficulty = {{difficulty}};
+  const encoder = new TextEncoder();
+  const zeroBits = (buf) => {
+    let count = 0;
+    for (const b of new Uint8Array(buf)) {
+      if (b === 0) {
+        count += 8;
+        continue;
+      }
+      count += Math.clz32(b) - 24;
+      break;
+    }
+    return count;
+  };
+  for (let counter = 0; ; counter++) {
+    const value = challenge + "." + counter;
+    const hash = await crypto.subtle.digest("SHA-256", encoder.encode(value));
+    if (zeroBits(hash) >= difficulty) {
+      document.cookie = "{{name}}=" + value + "; path=/; SameSite=Lax";
+      location.reload();
+      return;
+    }
+  }
+})();
+</script>
+</body>
+</html>
+"#;
+
+pub struct Challenge {
+    plugin_step: PluginStep,
+    key: String,
+    // the cookie name of pass token
+    name: String,
+    // the cookie name of challenge answer
+    answer_name: String,
+    // ttl seconds of pass token
+    ttl: u64,
+    // leading zero bits of the proof of work
+    difficulty: u32,
+    // bind the token to client ip, it's enabled by default
+    bind_ip: bool,
+    // challenge the request without any cookie
+    no_cookie: bool,
+    // challenge the request if user agent is empty or matched
+    ua_list: Vec<Regex>,
+    // challenge the client if its request rate exceeded
+    rate: Option<Rate>,
+    max: isize,
+    // the challenges which have been solved and their expired time,
+    // an answer can only be used once
+    solved: Mutex<AHashMap<String, u64>>,
+    hash_value: String,
+}
+
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        string("name"),
+        string("key"),
+        int("difficulty"),
+        duration("ttl"),
+        int("max"),
+        duration("interval"),
+        boolean("bind_ip"),
+        boolean("no_cookie"),
+        strings("ua_list"),
+    ]
+}
+
+impl TryFrom<&PluginConf> for Challenge {
+    type Error = Error;
+    fn try_from(value: &PluginConf) -> Result<Self> {
+        let hash_value = get_hash_key(value);
+        let step = get_step_conf(value);
+
+        let mut ua_list = vec![];
+        for item in get_str_slice_conf(value, "ua_list").iter() {
+            let reg = Regex::new(item).map_err(|e| Error::Invalid {
+                category: "regex".to_string(),
+                message: e.to_string(),
+            })?;
+            ua_list.push(reg);
+        }
+        let mut name = get_str_conf(value, "name");
+        if name.is_empty() {
+            name = "pingap-challenge".to_string();
+        }
+        let ttl = get_str_conf(value, "ttl");
+        let ttl = if !ttl.is_empty() {
+            parse_duration(&ttl).map_err(|e| Error::Invalid {
+                category: PluginCategory::Challenge.to_string(),
+                message: e.to_string(),
+            })?
+        } else {
+            Duration::from_secs(3600)
+        };
+        let interval = get_str_conf(value, "interval");
+        let interval = if !interval.is_empty() {
+            parse_duration(&interval).map_err(|e| Error::Invalid {
+                category: PluginCategory::Challenge.to_string(),
+                message: e.to_string(),
+            })?
+        } else {
+            Duration::from_secs(10)
+        };
+        let max = get_int_conf(value, "max");
+        let difficulty = match get_int_conf(value, "difficulty") {
+            0 => 16,
+            value => value,
+        };
+
+        let params = Self {
+            hash_value,
+            plugin_step: step,
+            key: get_str_conf(value, "key"),
+            answer_name: format!("{name}-answer"),
+            name,
+            ttl: ttl.as_secs(),
+            difficulty: difficulty as u32,
+            bind_ip: value
+                .get("bind_ip")
+                .and_then(|value| value.as_bool())
+                .unwrap_or(true),
+            no_cookie: get_bool_conf(value, "no_cookie"),
+            ua_list,
+            rate: if max > 0 {
+                Some(Rate::new(interval))
+            } else {
+                None
+            },
+            max: max as isize,
+            solved: Mutex::new(AHashMap::new()),
+        };
+        if params.key.is_empty() {
+            return Err(Error::Invalid {
+                category: PluginCategory::Challenge.to_string(),
+                message: "Key is not allowed empty".to_string(),
+            });
+        }
+        if !(1..=32).contains(&difficulty) {
+            return Err(Error::Invalid {
+                category: PluginCategory::Challenge.to_string(),
+                message: "Difficulty should be between 1 and 32".to_string(),
+            });
+        }
+        if ![PluginStep::Request, PluginStep::ProxyUpstream]
+            .contains(&params.plugin_step)
+        {
+            return Err(Error::Invalid {
+                category: PluginCategory::Challenge.to_string(),
+                message: "Challenge plugin should be executed at request or proxy upstream step".to_string(),
+            });
+        }
+
+        Ok(params)
+    }
+}
+
+impl Challenge {
+    pub fn new(params: &PluginConf) -> Result<Self> {
+        debug!(params = params.to_string(), "new challenge plugin");
+        Self::try_from(params)
+    }
+    /// Get the sign key of challenge and pass token, the client ip
+    /// will be appended if bind ip. The keys are different,
+    /// so a challenge token can't be used as a pass token.
+    fn get_sign_keys(&self, client_ip: &str) -> (String, String) {
+        let key = if self.bind_ip {
+            format!("{}:{client_ip}", self.key)
+        } else {
+            self.key.clone()
+        };
+        (format!("{key}:challenge"), format!("{key}:pass"))
+    }
+    /// Mark the challenge of answer as solved,
+    /// returns false if it has been solved before.
+    fn mark_solved(&self, answer: &str) -> bool {
+        let Some((challenge, _)) = answer.rsplit_once('.') else {
+            return false;
+        };
+        let now = util::now().as_secs();
+        let Ok(mut solved) = self.solved.lock() else {
+            return false;
+        };
+        solved.retain(|_, expired_at| *expired_at > now);
+        if solved.contains_key(challenge) {
+            return false;
+        }
+        solved.insert(challenge.to_string(), now + CHALLENGE_TTL + 1);
+        true
+    }
+    /// Returns true if the request should be challenged,
+    /// all requests will be challenged if no condition is set.
+    fn should_challenge(&self, session: &Session, client_ip: &str) -> bool {
+        let mut has_condition = false;
+        if self.no_cookie {
+            has_condition = true;
+            if session.get_header(header::COOKIE).is_none() {
+                return true;
+            }
+        }
+        if !self.ua_list.is_empty() {
+            has_condition = true;
+            let ua = session
+                .get_header(header::USER_AGENT)
+                .and_then(|value| value.to_str().ok())
+                .unwrap_or_default();
+            if ua.is_empty() || self.ua_list.iter().any(|reg| reg.is_match(ua))
+            {
+                return true;
+            }
+        }
+        if let Some(rate) = &self.rate {
+            has_condition = true;
+            rate.observe(&client_ip, 1);
+            if rate.rate(&client_ip) as isize > self.max {
+                return true;
+            }
+        }
+        !has_condition
+    }
+    fn new_cookie(&self, name: &str, value: &str, max_age: i64) -> String {
+        Cookie::build((name, value))
+            .path("/")
+            .http_only(true)
+            .same_site(cookie::SameSite::Lax)
+            .max_age(cookie::time::Duration::seconds(max_age))
+            .build()
+            .to_string()
+    }
+}
+
+/// Count the leading zero bits of hash.
+fn leading_zero_bits(hash: &[u8]) -> u32 {
+    let mut count = 0;
+    for b in hash {
+        if *b == 0 {
+            count += 8;
+            continue;
+        }
+        count += b.leading_zeros();
+        break;
+    }
+    count
+}
+
+/// Validate the answer of challenge, the format is `{challenge}.{counter}`,
+/// the challenge is a signed token and the sha256 of answer should have
+/// enough leading zero bits.
+fn validate_answer(key: &str, difficulty: u32, answer: &str) -> bool {
+    let Some((challenge, counter)) = answer.rsplit_once('.') else {
+        return false;
+    };
+    if counter.parse::<u64>().is_err()
+        || !validate_token(key, CHALLENGE_TTL, challenge)
+    {
+        return false;
+    }
+    let hash = Sha256::digest(answer.as_bytes());
+    leading_zero_bits(&hash) >= difficulty
+}
+
+#[async_trait]
+impl Plugin for Challenge {
+    #[inline]
+    fn hash_key(&self) -> String {
+        self.hash_value.clone()
+    }
+    #[inline]
+    async fn handle_request(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+    ) -> pingora::Result<Option<HttpResponse>> {
+        if step != self.plugin_step {
+            return Ok(None);
+        }
+        // the forwarded ip can be spoofed, so use the trusted client ip
+        let client_ip = ctx.trusted_client_ip.clone().unwrap_or_default();
+        let (challenge_key, pass_key) = self.get_sign_keys(&client_ip);
+        let req_header = session.req_header();
+        // the client has passed the challenge
+        if let Some(value) = util::get_cookie_value(req_header, &self.name) {
+            if validate_token(&pass_key, self.ttl, value) {
+                return Ok(None);
+            }
+        }
+        // the client solved the challenge, set the pass token
+        // and redirect to the original url
+        if let Some(value) =
+            util::get_cookie_value(req_header, &self.answer_name)
+        {
+            if validate_answer(&challenge_key, self.difficulty, value)
+                && self.mark_solved(value)
+            {
+                let location = req_header
+                    .uri
+                    .path_and_query()
+                    .map(|value| value.to_string())
+                    .unwrap_or("/".to_string());
+                let mut headers = vec![HTTP_HEADER_NO_STORE.clone()];
+                for cookie in [
+                    self.new_cookie(
+                        &self.name,
+                        &generate_token(&pass_key),
+                        self.ttl as i64,
+                    ),
+                    self.new_cookie(&self.answer_name, "", 0),
+                ] {
+                    let value =
+                        HeaderValue::from_str(&cookie).map_err(|e| {
+                            util::new_internal_error(400, e.to_string())
+                        })?;
+                    headers.push((header::SET_COOKIE, value));
+                }
+                let value = HeaderValue::from_str(&location).map_err(|e| {
+                    util::new_internal_error(400, e.to_string())
+                })?;
+                headers.push((header::LOCATION, value));
+                return Ok(Some(HttpResponse {
+                    status: StatusCode::FOUND,
+                    headers: Some(headers),
+                    ..Default::default()
+                }));
+            }
+        }
+
+        if !self.should_challenge(session, &client_ip) {
+            return Ok(None);
+        }
+        let html = CHALLENGE_HTML
+            .replace("{{challenge}}", &generate_token(&challenge_key))
+            .replace("{{difficulty}}", &self.difficulty.to_string())
+            .replace("{{name}}", &self.answer_name);
+        Ok(Some(HttpResponse {
+            status: StatusCode::FORBIDDEN,
+            headers: Some(vec![
+                HTTP_HEADER_CONTENT_HTML.clone(),
+                HTTP_HEADER_NO_STORE.clone(),
+            ]),
+            body: Bytes::from(html),
+            ..Default::default()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{leading_zero_bits, validate_answer, Challenge};
+    use crate::config::{PluginConf, PluginStep};
+    use crate::plugin::Plugin;
+    use crate::state::State;
+    use crate::util::generate_token;
+    use http::StatusCode;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use sha2::{Digest, Sha256};
+    use tokio_test::io::Builder;
+
+    fn solve(challenge: &str, difficulty: u32) -> String {
+        let mut counter = 0;
+        loop {
+            let answer = format!("{challenge}.{counter}");
+            if leading_zero_bits(&Sha256::digest(answer.as_bytes()))
+                >= difficulty
+            {
+                return answer;
+            }
+            counter += 1;
+        }
+    }
+
+    fn new_state(client_ip: &str) -> State {
+        State {
+            trusted_client_ip: Some(client_ip.to_string()),
+            ..Default::default()
+        }
+    }
+
+    async fn new_session(headers: &[&str]) -> Session {
+        let headers = headers.join("\r\n");
+        let input_header =
+            format!("GET /vicanso/pingap?size=1 HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        session
+    }
+
+    #[test]
+    fn test_challenge_params() {
+        let params = Challenge::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+key = "WjrXUG47wu"
+ttl = "2h"
+difficulty = 8
+no_cookie = true
+ua_list = ["curl"]
+max = 10
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("pingap-challenge", params.name);
+        assert_eq!("pingap-challenge-answer", params.answer_name);
+        assert_eq!(7200, params.ttl);
+        assert_eq!(8, params.difficulty);
+        assert_eq!(true, params.no_cookie);
+        assert_eq!(true, params.bind_ip);
+        assert_eq!(1, params.ua_list.len());
+        assert_eq!(true, params.rate.is_some());
+
+        let result = Challenge::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+ttl = "2h"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin challenge invalid, message: Key is not allowed empty",
+            result.err().unwrap().to_string()
+        );
+
+        let result = Challenge::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+key = "WjrXUG47wu"
+difficulty = 33
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin challenge invalid, message: Difficulty should be between 1 and 32",
+            result.err().unwrap().to_string()
+        );
+
+        let result = Challenge::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+step = "response"
+key = "WjrXUG47wu"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin challenge invalid, message: Challenge plugin should be executed at request or proxy upstream step",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_validate_answer() {
+        assert_eq!(8, leading_zero_bits(&[0, 255]));
+        assert_eq!(11, leading_zero_bits(&[0, 16, 0]));
+
+        let key = "123";
+        let answer = solve(&generate_token(key), 8);
+        assert_eq!(true, validate_answer(key, 8, &answer));
+        assert_eq!(false, validate_answer("456", 8, &answer));
+        assert_eq!(false, validate_answer(key, 8, "abc"));
+    }
+
+    #[tokio::test]
+    async fn test_challenge() {
+        let challenge = Challenge::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+key = "WjrXUG47wu"
+difficulty = 8
+ua_list = ["curl"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        // not matched
+        let mut session = new_session(&["User-Agent: pingap/0.1.1"]).await;
+        let result = challenge
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+
+        // challenge page
+        let mut session = new_session(&["User-Agent: curl/8.0"]).await;
+        let result = challenge
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut State::default(),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, result.status);
+        let html = std::str::from_utf8(&result.body).unwrap();
+        assert_eq!(true, html.contains("const difficulty = 8;"));
+        assert_eq!(true, html.contains("pingap-challenge-answer="));
+
+        // the challenge token can't be used as pass token
+        let challenge_token = generate_token("WjrXUG47wu:1.1.1.1:challenge");
+        let mut session = new_session(&[
+            "User-Agent: curl/8.0",
+            &format!("Cookie: pingap-challenge={challenge_token}"),
+        ])
+        .await;
+        let result = challenge
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut new_state("1.1.1.1"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, result.unwrap().status);
+
+        // solved challenge
+        let answer = solve(&challenge_token, 8);
+        let answer_cookie =
+            format!("Cookie: a=1; pingap-challenge-answer={answer}");
+
+        // the answer is bound to client ip
+        let mut session =
+            new_session(&["User-Agent: curl/8.0", &answer_cookie]).await;
+        let result = challenge
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut new_state("2.2.2.2"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, result.unwrap().status);
+
+        let mut session =
+            new_session(&["User-Agent: curl/8.0", &answer_cookie]).await;
+        let result = challenge
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut new_state("1.1.1.1"),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(StatusCode::FOUND, result.status);
+        let headers = result.headers.unwrap();
+        assert_eq!(
+            "/vicanso/pingap?size=1",
+            headers
+                .iter()
+                .find(|(name, _)| name == http::header::LOCATION)
+                .unwrap()
+                .1
+                .to_str()
+                .unwrap()
+        );
+        let pass_cookie = headers
+            .iter()
+            .find(|(name, value)| {
+                name == http::header::SET_COOKIE
+                    && value.to_str().unwrap().starts_with("pingap-challenge=")
+            })
+            .unwrap()
+            .1
+            .to_str()
+            .unwrap()
+            .split(';')
+            .next()
+            .unwrap()
+            .to_string();
+
+        // the answer can only be used once
+        let mut session =
+            new_session(&["User-Agent: curl/8.0", &answer_cookie]).await;
+        let result = challenge
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut new_state("1.1.1.1"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, result.unwrap().status);
+
+        // passed
+        let mut session = new_session(&[
+            "User-Agent: curl/8.0",
+            &format!("Cookie: {pass_cookie}"),
+        ])
+        .await;
+        let result = challenge
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut new_state("1.1.1.1"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+
+        // the token is bound to client ip, the forwarded ip is ignored
+        let mut session = new_session(&[
+            "User-Agent: curl/8.0",
+            "X-Forwarded-For: 1.1.1.1",
+            &format!("Cookie: {pass_cookie}"),
+        ])
+        .await;
+        let result = challenge
+            .handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut new_state("2.2.2.2"),
+            )
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, result.unwrap().status);
+    }
+}
@@ -0,0 +1,655 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::schema::{duration, size, strings, PluginField};
+use super::{
+    get_hash_key, get_step_conf, get_str_conf, get_str_slice_conf, Error,
+    Plugin, Result,
+};
+use crate::config::{PluginCategory, PluginConf, PluginStep};
+use crate::http_extra::{
+    HttpHeader, HttpResponse, HTTP_HEADER_NAME_X_REQUEST_ID,
+};
+use crate::state::{ObserveResponse, State};
+use crate::util;
+use ahash::AHashMap;
+use async_trait::async_trait;
+use bytes::{BufMut, Bytes, BytesMut};
+use bytesize::ByteSize;
+use http::{header, Method, StatusCode};
+use humantime::parse_duration;
+use pingora::cache::key::CacheHashKey;
+use pingora::http::ResponseHeader;
+use pingora::proxy::Session;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tracing::debug;
+
+/// The response of leader request, it's shared to the waiting requests.
+#[derive(Debug)]
+struct CoalescedResponse {
+    status: StatusCode,
+    headers: Vec<HttpHeader>,
+    body: Bytes,
+}
+
+#[derive(Debug, Clone, Default)]
+enum CallState {
+    #[default]
+    Pending,
+    // the waiting requests should be sent to upstream
+    Failed,
+    Done(Arc<CoalescedResponse>),
+}
+
+type CallSender = Arc<watch::Sender<CallState>>;
+
+/// Returns true if all the fields of vary header are
+/// in the key headers, `Vary: *` is never covered.
+fn is_vary_covered(vary: &str, key_headers: &[String]) -> bool {
+    vary.split(',')
+        .map(|item| item.trim())
+        .filter(|item| !item.is_empty())
+        .all(|item| {
+            item != "*"
+                && key_headers
+                    .iter()
+                    .any(|name| name.eq_ignore_ascii_case(item))
+        })
+}
+type Calls = Arc<Mutex<AHashMap<String, (u64, CallSender)>>>;
+
+/// The observer of leader request, it collects the response
+/// and shares it to the waiting requests when the response is done.
+struct CoalesceObserver {
+    id: u64,
+    key: String,
+    calls: Calls,
+    sender: CallSender,
+    max_body_size: usize,
+    // the request headers of coalesce key
+    key_headers: Vec<String>,
+    header: Mutex<Option<(StatusCode, Vec<HttpHeader>)>>,
+    // it's none if the body is larger than max body size
+    body: Mutex<Option<BytesMut>>,
+}
+
+impl CoalesceObserver {
+    fn finish(&self, state: CallState) {
+        if let Ok(mut calls) = self.calls.lock() {
+            if calls.get(&self.key).map(|(id, _)| *id) == Some(self.id) {
+                calls.remove(&self.key);
+            }
+        }
+        self.sender.send_if_modified(|value| {
+            if !matches!(value, CallState::Pending) {
+                return false;
+            }
+            *value = state;
+            true
+        });
+    }
+}
+
+impl ObserveResponse for CoalesceObserver {
+    fn header(&self, header: &ResponseHeader) {
+        // the response varies by the header which is not in the key,
+        // so the waiting requests should be sent to upstream
+        let covered =
+            header.headers.get_all(header::VARY).iter().all(|value| {
+                is_vary_covered(
+                    value.to_str().unwrap_or("*"),
+                    &self.key_headers,
+                )
+            });
+        if !covered {
+            if let Ok(mut value) = self.body.lock() {
+                *value = None;
+            }
+            self.finish(CallState::Failed);
+            return;
+        }
+        // the per client headers are not shared
+        let headers = header
+            .headers
+            .iter()
+            .filter(|(name, _)| {
+                ![
+                    header::CONTENT_LENGTH,
+                    header::TRANSFER_ENCODING,
+                    header::CONNECTION,
+                    header::SET_COOKIE,
+                ]
+                .contains(name)
+                    && *name != *HTTP_HEADER_NAME_X_REQUEST_ID
+            })
+            .map(|(name, value)| (name.to_owned(), value.to_owned()))
+            .collect();
+        if let Ok(mut value) = self.header.lock() {
+            *value = Some((header.status, headers));
+        }
+    }
+    fn body(&self, body: &Option<Bytes>, end_of_stream: bool) {
+        let Ok(mut value) = self.body.lock() else {
+            return;
+        };
+        let Some(buf) = value.as_mut() else {
+            return;
+        };
+        if let Some(data) = body {
+            if buf.len() + data.len() > self.max_body_size {
+                *value = None;
+                self.finish(CallState::Failed);
+                return;
+            }
+            buf.put(data.as_ref());
+        }
+        if !end_of_stream {
+            return;
+        }
+        let body = buf.split().freeze();
+        let header = self.header.lock().ok().and_then(|mut value| value.take());
+        let state = if let Some((status, headers)) = header {
+            CallState::Done(Arc::new(CoalescedResponse {
+                status,
+                headers,
+                body,
+            }))
+        } else {
+            CallState::Failed
+        };
+        self.finish(state);
+    }
+}
+
+impl Drop for CoalesceObserver {
+    // the leader request is done without complete response,
+    // e.g. upstream error or client closed
+    fn drop(&mut self) {
+        self.finish(CallState::Failed);
+    }
+}
+
+pub struct Coalesce {
+    plugin_step: PluginStep,
+    headers: Option<Vec<String>>,
+    max_wait: Duration,
+    max_body_size: usize,
+    calls: Calls,
+    sequence: AtomicU64,
+    hash_value: String,
+}
+
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        strings("headers"),
+        duration("max_wait"),
+        size("max_body_size"),
+    ]
+}
+
+impl TryFrom<&PluginConf> for Coalesce {
+    type Error = Error;
+    fn try_from(value: &PluginConf) -> Result<Self> {
+        let hash_value = get_hash_key(value);
+        let step = get_step_conf(value);
+
+        let max_wait = get_str_conf(value, "max_wait");
+        let max_wait = if !max_wait.is_empty() {
+            parse_duration(&max_wait).map_err(|e| Error::Invalid {
+                category: PluginCategory::Coalesce.to_string(),
+                message: e.to_string(),
+            })?
+        } else {
+            Duration::from_secs(5)
+        };
+        let max_body_size = get_str_conf(value, "max_body_size");
+        let max_body_size = if !max_body_size.is_empty() {
+            ByteSize::from_str(&max_body_size).map_err(|e| Error::Invalid {
+                category: PluginCategory::Coalesce.to_string(),
+                message: e.to_string(),
+            })?
+        } else {
+            ByteSize::mb(1)
+        };
+        let headers = get_str_slice_conf(value, "headers");
+
+        let params = Self {
+            hash_value,
+            plugin_step: step,
+            headers: if headers.is_empty() {
+                None
+            } else {
+                Some(headers)
+            },
+            max_wait,
+            max_body_size: max_body_size.as_u64() as usize,
+            calls: Default::default(),
+            sequence: AtomicU64::new(0),
+        };
+        if params.plugin_step != PluginStep::Request {
+            return Err(Error::Invalid {
+                category: PluginCategory::Coalesce.to_string(),
+                message: "Coalesce plugin should be executed at request step"
+                    .to_string(),
+            });
+        }
+        Ok(params)
+    }
+}
+
+impl Coalesce {
+    pub fn new(params: &PluginConf) -> Result<Self> {
+        debug!(params = params.to_string(), "new coalesce plugin");
+        Self::try_from(params)
+    }
+    /// Get the request headers of key, `Accept-Encoding` is always included
+    /// because the response is compressed by it.
+    fn get_key_headers(&self) -> Vec<String> {
+        let mut key_headers = vec![header::ACCEPT_ENCODING.to_string()];
+        for name in self.headers.iter().flatten() {
+            if !key_headers
+                .iter()
+                .any(|item| item.eq_ignore_ascii_case(name))
+            {
+                key_headers.push(name.to_string());
+            }
+        }
+        key_headers
+    }
+    /// Get the key of request, it's the same as cache key.
+    fn get_key(&self, session: &Session) -> String {
+        let mut prefix = BytesMut::with_capacity(64);
+        for key in self.get_key_headers().iter() {
+            // the empty value keeps its place to avoid key collision
+            prefix.put(session.get_header_bytes(key));
+            prefix.put(&b":"[..]);
+        }
+        let req_header = session.req_header();
+        util::get_cache_key(
+            std::str::from_utf8(&prefix).unwrap_or_default(),
+            req_header.method.as_ref(),
+            &req_header.uri,
+        )
+        .combined()
+    }
+}
+
+#[async_trait]
+impl Plugin for Coalesce {
+    #[inline]
+    fn hash_key(&self) -> String {
+        self.hash_value.clone()
+    }
+    #[inline]
+    async fn handle_request(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+    ) -> pingora::Result<Option<HttpResponse>> {
+        if step != self.plugin_step {
+            return Ok(None);
+        }
+        // only idempotent request can be coalesced
+        if ![Method::GET, Method::HEAD].contains(&session.req_header().method) {
+            return Ok(None);
+        }
+        // the response may be different for each client
+        let req_headers = &session.req_header().headers;
+        if [
+            header::COOKIE,
+            header::AUTHORIZATION,
+            header::RANGE,
+            header::IF_NONE_MATCH,
+            header::IF_MODIFIED_SINCE,
+        ]
+        .iter()
+        .any(|name| req_headers.contains_key(name))
+        {
+            return Ok(None);
+        }
+        let key = self.get_key(session);
+        let mut receiver = {
+            let Ok(mut calls) = self.calls.lock() else {
+                return Ok(None);
+            };
+            if let Some((_, sender)) = calls.get(&key) {
+                sender.subscribe()
+            } else {
+                // the first request is the leader, it's sent to upstream
+                let id = self.sequence.fetch_add(1, Ordering::Relaxed);
+                let (sender, _) = watch::channel(CallState::Pending);
+                let sender = Arc::new(sender);
+                calls.insert(key.clone(), (id, sender.clone()));
+                ctx.response_observer = Some(Box::new(CoalesceObserver {
+                    id,
+                    key,
+                    calls: self.calls.clone(),
+                    sender,
+                    max_body_size: self.max_body_size,
+                    key_headers: self.get_key_headers(),
+                    header: Mutex::new(None),
+                    body: Mutex::new(Some(BytesMut::new())),
+                }));
+                return Ok(None);
+            }
+        };
+        let result = tokio::time::timeout(
+            self.max_wait,
+            receiver.wait_for(|value| !matches!(value, CallState::Pending)),
+        )
+        .await;
+        // the request is sent to upstream if timeout or leader fails
+        let Ok(Ok(value)) = result else {
+            return Ok(None);
+        };
+        let CallState::Done(resp) = value.clone() else {
+            return Ok(None);
+        };
+        Ok(Some(HttpResponse {
+            status: resp.status,
+            headers: Some(resp.headers.clone()),
+            body: resp.body.clone(),
+            ..Default::default()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{is_vary_covered, Coalesce};
+    use crate::config::{PluginConf, PluginStep};
+    use crate::plugin::Plugin;
+    use crate::state::State;
+    use bytes::Bytes;
+    use pingora::http::ResponseHeader;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+    use tokio_test::io::Builder;
+
+    async fn new_session(method: &str) -> Session {
+        new_session_with_headers(method, &[]).await
+    }
+
+    async fn new_session_with_headers(
+        method: &str,
+        extra_headers: &[&str],
+    ) -> Session {
+        let mut headers = vec!["Accept-Encoding: gzip"];
+        headers.extend_from_slice(extra_headers);
+        let headers = headers.join("\r\n");
+        let input_header = format!(
+            "{method} /vicanso/pingap?size=1 HTTP/1.1\r\n{headers}\r\n\r\n"
+        );
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        session
+    }
+
+    #[test]
+    fn test_coalesce_params() {
+        let params = Coalesce::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+headers = ["Accept-Encoding"]
+max_wait = "3s"
+max_body_size = "100kb"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            r#"Some(["Accept-Encoding"])"#,
+            format!("{:?}", params.headers)
+        );
+        assert_eq!(3, params.max_wait.as_secs());
+        assert_eq!(100 * 1000, params.max_body_size);
+
+        let result = Coalesce::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+step = "response"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin coalesce invalid, message: Coalesce plugin should be executed at request step",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_coalesce() {
+        let coalesce = Coalesce::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+headers = ["Accept-Encoding"]
+max_wait = "1s"
+max_body_size = "10b"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+
+        // post request is not coalesced
+        let mut ctx = State::default();
+        let result = coalesce
+            .handle_request(
+                PluginStep::Request,
+                &mut new_session("POST").await,
+                &mut ctx,
+            )
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+        assert_eq!(true, ctx.response_observer.is_none());
+
+        // the request with client specific headers is not coalesced
+        for header in [
+            "Cookie: uid=1",
+            "Authorization: Basic YWRtaW46MTIzMTIz",
+            "Range: bytes=0-10",
+            "If-None-Match: \"123\"",
+            "If-Modified-Since: Tue, 15 Oct 2024 00:00:00 GMT",
+        ] {
+            let mut ctx = State::default();
+            let result = coalesce
+                .handle_request(
+                    PluginStep::Request,
+                    &mut new_session_with_headers("GET", &[header]).await,
+                    &mut ctx,
+                )
+                .await
+                .unwrap();
+            assert_eq!(true, result.is_none());
+            assert_eq!(true, ctx.response_observer.is_none());
+            assert_eq!(true, coalesce.calls.lock().unwrap().is_empty());
+        }
+
+        // the first request is leader
+        let mut leader_ctx = State::default();
+        let result = coalesce
+            .handle_request(
+                PluginStep::Request,
+                &mut new_session("GET").await,
+                &mut leader_ctx,
+            )
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+        let observer = leader_ctx.response_observer.take().unwrap();
+
+        let mut session = new_session("GET").await;
+        let mut ctx = State::default();
+        let wait = coalesce.handle_request(
+            PluginStep::Request,
+            &mut session,
+            &mut ctx,
+        );
+        let finish = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            let mut header = ResponseHeader::build(200, None).unwrap();
+            header.insert_header("Content-Type", "text/plain").unwrap();
+            header.insert_header("Content-Length", "5").unwrap();
+            header.insert_header("Set-Cookie", "uid=1").unwrap();
+            header.insert_header("X-Request-Id", "abc").unwrap();
+            observer.header(&header);
+            observer.body(&Some(Bytes::from_static(b"Hello")), true);
+        };
+        let (result, _) = tokio::join!(wait, finish);
+        let resp = result.unwrap().unwrap();
+        assert_eq!(200, resp.status.as_u16());
+        assert_eq!(b"Hello", resp.body.as_ref());
+        assert_eq!(
+            r#"Some([("content-type", "text/plain")])"#,
+            format!("{:?}", resp.headers)
+        );
+        assert_eq!(true, coalesce.calls.lock().unwrap().is_empty());
+        drop(observer);
+
+        // the body is larger than max body size
+        let mut leader_ctx = State::default();
+        coalesce
+            .handle_request(
+                PluginStep::Request,
+                &mut new_session("GET").await,
+                &mut leader_ctx,
+            )
+            .await
+            .unwrap();
+        let observer = leader_ctx.response_observer.take().unwrap();
+        let mut session = new_session("GET").await;
+        let mut ctx = State::default();
+        let wait = coalesce.handle_request(
+            PluginStep::Request,
+            &mut session,
+            &mut ctx,
+        );
+        let finish = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            observer.header(&ResponseHeader::build(200, None).unwrap());
+            observer.body(&Some(Bytes::from_static(b"Hello World!")), false);
+        };
+        let (result, _) = tokio::join!(wait, finish);
+        assert_eq!(true, result.unwrap().is_none());
+        drop(observer);
+
+        // the leader is dropped without response
+        let mut leader_ctx = State::default();
+        coalesce
+            .handle_request(
+                PluginStep::Request,
+                &mut new_session("GET").await,
+                &mut leader_ctx,
+            )
+            .await
+            .unwrap();
+        let mut session = new_session("GET").await;
+        let mut ctx = State::default();
+        let wait = coalesce.handle_request(
+            PluginStep::Request,
+            &mut session,
+            &mut ctx,
+        );
+        let finish = async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            drop(leader_ctx);
+        };
+        let (result, _) = tokio::join!(wait, finish);
+        assert_eq!(true, result.unwrap().is_none());
+        assert_eq!(true, coalesce.calls.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_coalesce_key_and_vary() {
+        let coalesce = Coalesce::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+headers = ["X-Tenant"]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            vec!["accept-encoding", "X-Tenant"],
+            coalesce.get_key_headers()
+        );
+
+        // accept encoding is in the key by default
+        let gzip_key = coalesce.get_key(&new_session("GET").await);
+        let input_header =
+            "GET /vicanso/pingap?size=1 HTTP/1.1\r\nAccept-Encoding: br\r\n\r\n";
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        assert_eq!(false, gzip_key == coalesce.get_key(&session));
+
+        assert_eq!(
+            true,
+            is_vary_covered(
+                "Accept-Encoding, x-tenant",
+                &coalesce.get_key_headers()
+            )
+        );
+        assert_eq!(
+            false,
+            is_vary_covered(
+                "Accept-Encoding, Origin",
+                &coalesce.get_key_headers()
+            )
+        );
+        assert_eq!(false, is_vary_covered("*", &coalesce.get_key_headers()));
+
+        // the vary header is not covered by key
+        for (vary, shared) in [("Origin", false), ("Accept-Encoding", true)] {
+            let mut leader_ctx = State::default();
+            coalesce
+                .handle_request(
+                    PluginStep::Request,
+                    &mut new_session("GET").await,
+                    &mut leader_ctx,
+                )
+                .await
+                .unwrap();
+            let observer = leader_ctx.response_observer.take().unwrap();
+            let mut session = new_session("GET").await;
+            let mut ctx = State::default();
+            let wait = coalesce.handle_request(
+                PluginStep::Request,
+                &mut session,
+                &mut ctx,
+            );
+            let finish = async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                let mut header = ResponseHeader::build(200, None).unwrap();
+                header.insert_header("Vary", vary).unwrap();
+                observer.header(&header);
+                observer.body(&Some(Bytes::from_static(b"Hello")), true);
+            };
+            let (result, _) = tokio::join!(wait, finish);
+            assert_eq!(shared, result.unwrap().is_some());
+            assert_eq!(true, coalesce.calls.lock().unwrap().is_empty());
+        }
+    }
+}
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{field, int, string, strings, FieldKind, PluginField};
 use super::{
     get_hash_key, get_int_conf, get_step_conf, get_str_conf,
     get_str_slice_conf, Error, Plugin, Result,
@@ -41,6 +42,18 @@ pub struct CombinedAuth {
     auths: AHashMap<String, AuthParam>,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![field(
+        "authorizations",
+        FieldKind::TableArray(vec![
+            string("app_id"),
+            string("secret"),
+            int("deviation"),
+            strings("ip_list"),
+        ]),
+    )]
+}
+
 impl TryFrom<&PluginConf> for CombinedAuth {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
@@ -98,7 +111,7 @@ impl CombinedAuth {
         debug!(params = params.to_string(), "new combined auth plugin");
         Self::try_from(params)
     }
-    fn validate(&self, session: &Session) -> Result<()> {
+    fn validate(&self, session: &Session, ctx: &State) -> Result<()> {
         let category = "combined_auth";
         // validate timestamp
         let req_header = session.req_header();
@@ -120,7 +133,10 @@ impl CombinedAuth {
         }
         // validate ip
         if let Some(ip_rules) = &auth_param.ip_rules {
-            let ip = util::get_client_ip(session);
+            let ip = ctx
+                .client_ip
+                .clone()
+                .unwrap_or_else(|| util::get_client_ip(session));
             if !ip_rules.matched(&ip).unwrap_or_default() {
                 return Err(Error::Invalid {
                     category: category.to_string(),
@@ -179,12 +195,12 @@ impl Plugin for CombinedAuth {
         &self,
         step: PluginStep,
         session: &mut Session,
-        _ctx: &mut State,
+        ctx: &mut State,
     ) -> pingora::Result<Option<HttpResponse>> {
         if step != self.plugin_step {
             return Ok(None);
         }
-        if let Err(e) = self.validate(session) {
+        if let Err(e) = self.validate(session, ctx) {
             return Ok(Some(HttpResponse {
                 status: StatusCode::UNAUTHORIZED,
                 headers: Some(vec![HTTP_HEADER_NO_STORE.clone()]),
@@ -201,6 +217,7 @@ impl Plugin for CombinedAuth {
 mod tests {
     use super::{AuthParam, CombinedAuth};
     use crate::config::PluginStep;
+    use crate::state::State;
     use crate::util;
     use ahash::AHashMap;
     use hex::ToHex;
@@ -237,7 +254,7 @@ mod tests {
         let mock_io = Builder::new().read(input_header.as_bytes()).build();
         let mut session = Session::new_h1(Box::new(mock_io));
         session.read_request().await.unwrap();
-        let result = combined_auth.validate(&session);
+        let result = combined_auth.validate(&session, &State::default());
         assert_eq!(true, result.is_err());
         assert_eq!(
             "Plugin combined_auth invalid, message: app id is empty",
@@ -252,7 +269,7 @@ mod tests {
         let mock_io = Builder::new().read(input_header.as_bytes()).build();
         let mut session = Session::new_h1(Box::new(mock_io));
         session.read_request().await.unwrap();
-        let result = combined_auth.validate(&session);
+        let result = combined_auth.validate(&session, &State::default());
         assert_eq!(true, result.is_err());
         assert_eq!(
             "Plugin combined_auth invalid, message: app id is invalid",
@@ -267,7 +284,7 @@ mod tests {
         let mock_io = Builder::new().read(input_header.as_bytes()).build();
         let mut session = Session::new_h1(Box::new(mock_io));
         session.read_request().await.unwrap();
-        let result = combined_auth.validate(&session);
+        let result = combined_auth.validate(&session, &State::default());
         assert_eq!(true, result.is_err());
         assert_eq!(
             "Plugin combined_auth invalid, message: ip is invalid",
@@ -282,7 +299,7 @@ mod tests {
         let mock_io = Builder::new().read(input_header.as_bytes()).build();
         let mut session = Session::new_h1(Box::new(mock_io));
         session.read_request().await.unwrap();
-        let result = combined_auth.validate(&session);
+        let result = combined_auth.validate(&session, &State::default());
         assert_eq!(true, result.is_err());
         assert_eq!(
             "Plugin combined_auth invalid, message: timestamp is empty",
@@ -297,7 +314,7 @@ mod tests {
         let mock_io = Builder::new().read(input_header.as_bytes()).build();
         let mut session = Session::new_h1(Box::new(mock_io));
         session.read_request().await.unwrap();
-        let result = combined_auth.validate(&session);
+        let result = combined_auth.validate(&session, &State::default());
         assert_eq!(true, result.is_err());
         assert_eq!(
             "Plugin combined_auth invalid, message: timestamp deviation is invalid",
@@ -313,7 +330,7 @@ mod tests {
         let mock_io = Builder::new().read(input_header.as_bytes()).build();
         let mut session = Session::new_h1(Box::new(mock_io));
         session.read_request().await.unwrap();
-        let result = combined_auth.validate(&session);
+        let result = combined_auth.validate(&session, &State::default());
         assert_eq!(true, result.is_err());
         assert_eq!(
             "Plugin combined_auth invalid, message: digest is empty",
@@ -329,7 +346,7 @@ mod tests {
         let mock_io = Builder::new().read(input_header.as_bytes()).build();
         let mut session = Session::new_h1(Box::new(mock_io));
         session.read_request().await.unwrap();
-        let result = combined_auth.validate(&session);
+        let result = combined_auth.validate(&session, &State::default());
         assert_eq!(true, result.is_err());
         assert_eq!(
             "Plugin combined_auth invalid, message: digest is invalid",
@@ -348,7 +365,7 @@ mod tests {
         let mock_io = Builder::new().read(input_header.as_bytes()).build();
         let mut session = Session::new_h1(Box::new(mock_io));
         session.read_request().await.unwrap();
-        let result = combined_auth.validate(&session);
+        let result = combined_auth.validate(&session, &State::default());
         assert_eq!(true, result.is_ok());
     }
 }
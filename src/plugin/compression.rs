@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{boolean, int, PluginField};
 use super::{get_bool_conf, get_hash_key, get_int_conf, Error, Plugin, Result};
 use crate::config::{PluginConf, PluginStep};
 use crate::http_extra::HttpResponse;
@@ -36,6 +37,15 @@ pub struct Compression {
     hash_value: String,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        int("gzip_level"),
+        int("br_level"),
+        int("zstd_level"),
+        boolean("decompression"),
+    ]
+}
+
 impl TryFrom<&PluginConf> for Compression {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{boolean, duration, string, PluginField};
 use super::{
     get_bool_conf, get_hash_key, get_step_conf, get_str_conf, Error, Plugin,
     Result,
@@ -37,6 +38,18 @@ pub struct Cors {
     hash_value: String,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        string("path"),
+        string("allow_origin"),
+        string("allow_methods"),
+        string("allow_headers"),
+        boolean("allow_credentials"),
+        string("expose_headers"),
+        duration("max_age"),
+    ]
+}
+
 impl TryFrom<&PluginConf> for Cors {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
@@ -12,19 +12,18 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{duration, string, PluginField};
 use super::{get_hash_key, get_step_conf, get_str_conf, Error, Plugin, Result};
 use crate::config::{PluginCategory, PluginConf, PluginStep};
 use crate::http_extra::{HttpResponse, HTTP_HEADER_NO_STORE};
 use crate::state::State;
-use crate::util::{self, base64_encode};
+use crate::util::{self, generate_token, validate_token};
 use async_trait::async_trait;
 use bytes::Bytes;
 use cookie::Cookie;
 use http::{header, HeaderValue, Method, StatusCode};
 use humantime::parse_duration;
-use nanoid::nanoid;
 use pingora::proxy::Session;
-use sha2::{Digest, Sha256};
 use tracing::debug;
 
 pub struct Csrf {
@@ -38,6 +37,15 @@ pub struct Csrf {
     hash_value: String,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        string("token_path"),
+        string("name"),
+        string("key"),
+        duration("ttl"),
+    ]
+}
+
 impl TryFrom<&PluginConf> for Csrf {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
@@ -100,39 +108,6 @@ impl Csrf {
     }
 }
 
-fn generate_token(key: &str) -> String {
-    let id = nanoid!(12);
-    let prefix = format!("{id}.{:x}", util::now().as_secs());
-    let mut hasher = Sha256::new();
-    hasher.update(prefix.as_bytes());
-    hasher.update(key.as_bytes());
-    let hash256 = hasher.finalize();
-    format!("{prefix}.{}", base64_encode(hash256))
-}
-
-fn validate_token(key: &str, ttl: u64, value: &str) -> bool {
-    let arr: Vec<&str> = value.split('.').collect();
-    if arr.len() != 3 {
-        return false;
-    }
-
-    if ttl > 0 {
-        let now = util::now().as_secs();
-        if now - u64::from_str_radix(arr[1], 16).unwrap_or_default() > ttl {
-            return false;
-        }
-    }
-
-    let mut hasher = Sha256::new();
-    hasher.update(format!("{}.{}", arr[0], arr[1]).as_bytes());
-    hasher.update(key.as_bytes());
-    let hash256 = hasher.finalize();
-    if arr[2] != base64_encode(hash256) {
-        return false;
-    }
-    true
-}
-
 #[async_trait]
 impl Plugin for Csrf {
     #[inline]
@@ -200,7 +175,7 @@ impl Plugin for Csrf {
 
 #[cfg(test)]
 mod tests {
-    use super::{generate_token, validate_token, Csrf};
+    use super::Csrf;
     use crate::config::{PluginConf, PluginStep};
     use crate::plugin::Plugin;
     use crate::state::State;
@@ -285,14 +260,6 @@ ttl = "1h"
         );
     }
 
-    #[test]
-    fn test_generate_token() {
-        let key = "123";
-        let value = generate_token(key);
-        assert_eq!(true, validate_token(key, 10, &value));
-        assert_eq!(false, validate_token(key, 10, &format!("{value}:1")));
-    }
-
     #[tokio::test]
     async fn test_csrf() {
         let csrf = Csrf::new(
@@ -12,34 +12,43 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{boolean, duration, size, string, strings, PluginField};
 use super::{
     get_bool_conf, get_hash_key, get_step_conf, get_str_conf,
     get_str_slice_conf, Error, Plugin, Result,
 };
 use crate::config::{PluginCategory, PluginConf, PluginStep};
 use crate::http_extra::{
-    convert_headers, HttpChunkResponse, HttpHeader, HttpResponse,
+    convert_headers, parse_range, ByteRange, HttpChunkResponse, HttpHeader,
+    HttpResponse, RangeResult, HTTP_HEADER_NO_STORE,
 };
 use crate::state::State;
 use crate::util;
 use async_trait::async_trait;
+use bytes::Bytes;
 use bytesize::ByteSize;
 use glob::glob;
-use http::{header, HeaderValue, StatusCode};
+use http::{header, HeaderValue, Method, StatusCode};
 use humantime::parse_duration;
+use nanoid::nanoid;
 use once_cell::sync::Lazy;
+use pingora::http::RequestHeader;
+use pingora::modules::http::compression::ResponseCompression;
 use pingora::proxy::Session;
+use regex::Regex;
+use std::collections::HashMap;
 use std::fs::Metadata;
+use std::io::SeekFrom;
 #[cfg(unix)]
 use std::os::unix::fs::MetadataExt;
 #[cfg(windows)]
 use std::os::windows::fs::MetadataExt;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::time::UNIX_EPOCH;
+use std::time::{SystemTime, UNIX_EPOCH};
 use substring::Substring;
 use tokio::fs;
-use tokio::io::AsyncReadExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tracing::{debug, error};
 use urlencoding::decode;
 
@@ -122,9 +131,22 @@ pub struct Directory {
     headers: Option<Vec<HttpHeader>>,
     // support download
     download: bool,
+    // the accepted encoding and extension of precompressed file
+    precompressed: Vec<(&'static str, &'static str)>,
+    // try files in order, e.g. `$uri $uri/ /index.html`
+    try_files: Vec<String>,
+    // custom error page of status code, the file is relative to the path
+    error_pages: HashMap<u16, String>,
+    // max age of hashed assets, e.g. `app.3f2a1b4c.js`
+    hashed_max_age: Option<u32>,
+    hashed_pattern: Option<Regex>,
     hash_value: String,
 }
 
+// the hex content hash of file name, e.g. `main.3f2a1b4c.js`,
+// set `hashed_pattern` for other hash formats
+static DEFAULT_HASHED_PATTERN: &str = r"[.-][0-9a-f]{8,}\.[0-9a-zA-Z]+$";
+
 async fn get_data(
     file: &PathBuf,
 ) -> std::io::Result<(std::fs::Metadata, fs::File)> {
@@ -138,10 +160,36 @@ async fn get_data(
     Ok((meta, f))
 }
 
+/// Get the encodings accepted by client, the encoding with `q=0`
+/// will be ignored.
+fn get_accept_encodings(accept_encoding: &str) -> Vec<String> {
+    accept_encoding
+        .split(',')
+        .filter_map(|item| {
+            let mut arr = item.split(';');
+            let encoding = arr.next()?.trim().to_lowercase();
+            let disabled = arr.any(|param| {
+                param
+                    .trim()
+                    .strip_prefix("q=")
+                    .and_then(|q| q.parse::<f32>().ok())
+                    .map(|q| q <= 0.0)
+                    .unwrap_or_default()
+            });
+            if encoding.is_empty() || disabled {
+                None
+            } else {
+                Some(encoding)
+            }
+        })
+        .collect()
+}
+
 fn get_cacheable_and_headers_from_meta(
     file: &PathBuf,
     meta: &Metadata,
     charset: &Option<String>,
+    encoding: Option<&str>,
 ) -> (bool, usize, Vec<HttpHeader>) {
     let result = mime_guess::from_path(file);
     let binding = result.first_or_octet_stream();
@@ -165,13 +213,42 @@ fn get_cacheable_and_headers_from_meta(
             .unwrap_or_default()
             .as_secs();
         if value > 0 {
-            let etag = format!(r###"W/"{:x}-{:x}""###, size, value);
+            // the precompressed file is another representation
+            let etag = if let Some(encoding) = encoding {
+                format!(r###"W/"{:x}-{:x}-{encoding}""###, size, value)
+            } else {
+                format!(r###"W/"{:x}-{:x}""###, size, value)
+            };
             headers.push((header::ETAG, HeaderValue::from_str(&etag).unwrap()));
         }
     }
+    if let Some(encoding) = encoding {
+        if let Ok(value) = HeaderValue::from_str(encoding) {
+            headers.push((header::CONTENT_ENCODING, value));
+        }
+    }
     (cacheable, size, headers)
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        string("path"),
+        string("index"),
+        boolean("autoindex"),
+        size("chunk_size"),
+        duration("max_age"),
+        boolean("private"),
+        string("charset"),
+        boolean("download"),
+        strings("headers"),
+        strings("precompressed"),
+        strings("try_files"),
+        strings("error_page"),
+        string("hashed_pattern"),
+        duration("hashed_max_age"),
+    ]
+}
+
 impl TryFrom<&PluginConf> for Directory {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
@@ -208,6 +285,81 @@ impl TryFrom<&PluginConf> for Directory {
                 message: e.to_string(),
             })?;
 
+        let mut precompressed = vec![];
+        for item in get_str_slice_conf(value, "precompressed").iter() {
+            let value = match item.to_lowercase().as_str() {
+                "br" => ("br", ".br"),
+                "zstd" => ("zstd", ".zst"),
+                "gzip" => ("gzip", ".gz"),
+                _ => {
+                    return Err(Error::Invalid {
+                        category: PluginCategory::Directory.to_string(),
+                        message: format!(
+                            "Precompressed encoding({item}) is not supported"
+                        ),
+                    })
+                },
+            };
+            precompressed.push(value);
+        }
+
+        let try_files = get_str_slice_conf(value, "try_files");
+        for item in try_files.iter() {
+            if let Some(code) = item.strip_prefix('=') {
+                if StatusCode::from_str(code).is_err() {
+                    return Err(Error::Invalid {
+                        category: PluginCategory::Directory.to_string(),
+                        message: format!("Try files({item}) is invalid"),
+                    });
+                }
+            }
+        }
+
+        // e.g. `404 /404.html` or `500 502 /50x.html`
+        let mut error_pages = HashMap::new();
+        for item in get_str_slice_conf(value, "error_page").iter() {
+            let mut arr: Vec<&str> = item.split_whitespace().collect();
+            let file = arr.pop().unwrap_or_default();
+            if arr.is_empty() {
+                return Err(Error::Invalid {
+                    category: PluginCategory::Directory.to_string(),
+                    message: format!("Error page({item}) is invalid"),
+                });
+            }
+            for code in arr {
+                let code =
+                    StatusCode::from_str(code).map_err(|_| Error::Invalid {
+                        category: PluginCategory::Directory.to_string(),
+                        message: format!("Error page({item}) is invalid"),
+                    })?;
+                error_pages.insert(code.as_u16(), file.to_string());
+            }
+        }
+
+        let hashed_max_age = get_str_conf(value, "hashed_max_age");
+        let hashed_max_age = if !hashed_max_age.is_empty() {
+            Some(
+                parse_duration(&hashed_max_age)
+                    .unwrap_or_default()
+                    .as_secs() as u32,
+            )
+        } else {
+            None
+        };
+        let hashed_pattern = if hashed_max_age.is_some() {
+            let mut pattern = get_str_conf(value, "hashed_pattern");
+            if pattern.is_empty() {
+                pattern = DEFAULT_HASHED_PATTERN.to_string();
+            }
+            let reg = Regex::new(&pattern).map_err(|e| Error::Invalid {
+                category: PluginCategory::Directory.to_string(),
+                message: e.to_string(),
+            })?;
+            Some(reg)
+        } else {
+            None
+        };
+
         let cache_private = get_bool_conf(value, "private");
         let cache_private = if cache_private { Some(true) } else { None };
         let params = Self {
@@ -222,6 +374,11 @@ impl TryFrom<&PluginConf> for Directory {
             cache_private,
             plugin_step: step,
             download: get_bool_conf(value, "download"),
+            precompressed,
+            try_files,
+            error_pages,
+            hashed_max_age,
+            hashed_pattern,
             headers: Some(headers),
         };
         if ![PluginStep::Request, PluginStep::ProxyUpstream]
@@ -242,6 +399,115 @@ impl Directory {
         debug!(params = params.to_string(), "new serve static file plugin");
         Self::try_from(params)
     }
+    /// Get the precompressed file(`.br`, `.zst` or `.gz`) accepted by client,
+    /// the order is the same as precompressed config.
+    async fn get_precompressed_data(
+        &self,
+        session: &Session,
+        file: &Path,
+    ) -> Option<(&'static str, Metadata, fs::File)> {
+        if self.precompressed.is_empty() {
+            return None;
+        }
+        let accept_encoding = session
+            .get_header(header::ACCEPT_ENCODING)?
+            .to_str()
+            .unwrap_or_default();
+        let accepted = get_accept_encodings(accept_encoding);
+        for (encoding, ext) in self.precompressed.iter() {
+            if !accepted.iter().any(|item| item == encoding) {
+                continue;
+            }
+            let mut name = file.as_os_str().to_owned();
+            name.push(ext);
+            if let Ok((meta, f)) = get_data(&PathBuf::from(name)).await {
+                return Some((encoding, meta, f));
+            }
+        }
+        None
+    }
+    /// Get the file by try files, the first existing file will be used.
+    /// If none of them exists, the last one will be used,
+    /// and `=code` means response the status code directly.
+    fn get_try_file(&self, filename: &str) -> Result<PathBuf, StatusCode> {
+        let index = if self.index.is_empty() {
+            "index.html"
+        } else {
+            self.index.trim_start_matches('/')
+        };
+        let mut file = None;
+        for item in self.try_files.iter() {
+            if let Some(code) = item.strip_prefix('=') {
+                return Err(
+                    StatusCode::from_str(code).unwrap_or(StatusCode::NOT_FOUND)
+                );
+            }
+            let value = item.replace("$uri", filename);
+            let mut current = self.path.join(value.trim_start_matches('/'));
+            if value.ends_with('/') {
+                current = current.join(index);
+            }
+            if current.is_file() {
+                return Ok(current);
+            }
+            file = Some(current);
+        }
+        Ok(file.unwrap_or_else(|| {
+            self.path.join(filename.trim_start_matches('/'))
+        }))
+    }
+    /// Get the custom error page of status code from the served directory,
+    /// the default response will be used if the error page is not found.
+    async fn get_error_response(
+        &self,
+        status: StatusCode,
+        default_resp: HttpResponse,
+    ) -> HttpResponse {
+        let Some(name) = self.error_pages.get(&status.as_u16()) else {
+            return default_resp;
+        };
+        let file = self.path.join(name.trim_start_matches('/'));
+        match fs::read(&file).await {
+            Ok(body) => {
+                let (_, _, headers) = match fs::metadata(&file).await {
+                    Ok(meta) => get_cacheable_and_headers_from_meta(
+                        &file,
+                        &meta,
+                        &self.charset,
+                        None,
+                    ),
+                    Err(_) => (false, 0, vec![]),
+                };
+                // only content type is used, the error page should not be cached
+                let headers = headers
+                    .into_iter()
+                    .filter(|(name, _)| name == header::CONTENT_TYPE)
+                    .collect();
+                HttpResponse {
+                    status,
+                    body: body.into(),
+                    headers: Some(headers),
+                    ..Default::default()
+                }
+            },
+            Err(e) => {
+                error!(
+                    error = e.to_string(),
+                    file = format!("{file:?}"),
+                    "read error page fail"
+                );
+                default_resp
+            },
+        }
+    }
+    /// Returns true if the file name matches the pattern of hashed assets.
+    fn is_hashed_asset(&self, file: &Path) -> bool {
+        let Some(pattern) = &self.hashed_pattern else {
+            return false;
+        };
+        let name = file.file_name().unwrap_or_default().to_string_lossy();
+        pattern.is_match(&name)
+    }
 }
 
 static IGNORE_RESPONSE: Lazy<HttpResponse> = Lazy::new(|| HttpResponse {
@@ -292,6 +558,163 @@ fn get_autoindex_html(path: &Path) -> Result<String, String> {
     Ok(WEB_HTML.replace("{{CONTENT}}", &file_list_html.join("\n")))
 }
 
+/// Compare the etags, the weak validator is ignored.
+fn is_etag_matched(value: &str, etag: &str) -> bool {
+    let strip = |value: &str| value.trim().trim_start_matches("W/").to_string();
+    let etag = strip(etag);
+    value.trim() == "*" || value.split(',').any(|item| strip(item) == etag)
+}
+
+/// Returns true if the response of client is not modified,
+/// `If-None-Match` has higher priority than `If-Modified-Since`.
+fn is_not_modified(
+    req_header: &RequestHeader,
+    etag: Option<&str>,
+    last_modified: Option<SystemTime>,
+) -> bool {
+    if ![Method::GET, Method::HEAD].contains(&req_header.method) {
+        return false;
+    }
+    let get_value = |name| {
+        req_header
+            .headers
+            .get(name)
+            .and_then(|value: &HeaderValue| value.to_str().ok())
+    };
+    if let Some(value) = get_value(header::IF_NONE_MATCH) {
+        return etag
+            .map(|etag| is_etag_matched(value, etag))
+            .unwrap_or_default();
+    }
+    if let (Some(value), Some(last_modified)) =
+        (get_value(header::IF_MODIFIED_SINCE), last_modified)
+    {
+        if let Ok(since) = httpdate::parse_http_date(value) {
+            // the precision of http date is second
+            return httpdate::HttpDate::from(last_modified)
+                <= httpdate::HttpDate::from(since);
+        }
+    }
+    false
+}
+
+/// Get the ranges of request, `If-Range` should be matched
+/// with the etag or last modified time, the weak etag is never matched.
+fn get_ranges(
+    req_header: &RequestHeader,
+    size: u64,
+    etag: Option<&str>,
+    last_modified: Option<SystemTime>,
+) -> RangeResult {
+    if req_header.method != Method::GET {
+        return RangeResult::Full;
+    }
+    let Some(range) = req_header
+        .headers
+        .get(header::RANGE)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return RangeResult::Full;
+    };
+    if let Some(value) = req_header
+        .headers
+        .get(header::IF_RANGE)
+        .and_then(|value| value.to_str().ok())
+    {
+        let value = value.trim();
+        let matched = if value.ends_with('"') {
+            etag.map(|etag| {
+                !etag.starts_with("W/")
+                    && !value.starts_with("W/")
+                    && etag == value
+            })
+            .unwrap_or_default()
+        } else {
+            last_modified
+                .map(|last_modified| {
+                    httpdate::fmt_http_date(last_modified) == value
+                })
+                .unwrap_or_default()
+        };
+        if !matched {
+            return RangeResult::Full;
+        }
+    }
+    parse_range(range, size)
+}
+
+/// Send the `multipart/byteranges` response, each part has its own
+/// `Content-Type` and `Content-Range`.
+async fn send_multipart_ranges(
+    session: &mut Session,
+    resp: HttpResponse,
+    f: &mut fs::File,
+    ranges: &[ByteRange],
+    size: u64,
+    chunk_size: usize,
+) -> pingora::Result<()> {
+    let boundary = nanoid!(24, &nanoid::alphabet::SAFE[2..]);
+    let mut header = resp.get_response_header()?;
+    let content_type = header
+        .headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("application/octet-stream")
+        .to_string();
+    let mut parts = vec![];
+    let end = format!("\r\n--{boundary}--\r\n");
+    let mut content_length = end.len() as u64;
+    for range in ranges.iter() {
+        let part = format!(
+            "\r\n--{boundary}\r\nContent-Type: {content_type}\r\nContent-Range: {}\r\n\r\n",
+            range.content_range(size)
+        );
+        content_length += part.len() as u64 + range.size();
+        parts.push(part);
+    }
+    header.insert_header(
+        header::CONTENT_TYPE,
+        format!("multipart/byteranges; boundary={boundary}"),
+    )?;
+    header.insert_header(header::CONTENT_LENGTH, content_length.to_string())?;
+    session
+        .write_response_header(Box::new(header), false)
+        .await?;
+
+    let mut buffer = vec![0; chunk_size];
+    for (range, part) in ranges.iter().zip(parts) {
+        session
+            .write_response_body(Some(Bytes::from(part)), false)
+            .await?;
+        f.seek(SeekFrom::Start(range.start))
+            .await
+            .map_err(|e| util::new_internal_error(500, e.to_string()))?;
+        let mut remaining = range.size() as usize;
+        while remaining > 0 {
+            let limit = remaining.min(chunk_size);
+            let n = f
+                .read(&mut buffer[..limit])
+                .await
+                .map_err(|e| util::new_internal_error(500, e.to_string()))?;
+            if n == 0 {
+                break;
+            }
+            remaining -= n;
+            session
+                .write_response_body(
+                    Some(Bytes::copy_from_slice(&buffer[..n])),
+                    false,
+                )
+                .await?;
+        }
+    }
+    session
+        .write_response_body(Some(Bytes::from(end)), true)
+        .await?;
+    session.finish_body().await?;
+    Ok(())
+}
+
 #[async_trait]
 impl Plugin for Directory {
     #[inline]
@@ -315,7 +738,7 @@ impl Plugin for Directory {
             filename.clone_from(&value.into_owned());
         }
         // convert to relative path
-        let file = self.path.join(filename.substring(1, filename.len()));
+        let mut file = self.path.join(filename.substring(1, filename.len()));
         debug!(file = format!("{file:?}"), "static file serve");
         if self.autoindex && file.is_dir() {
             let resp = match get_autoindex_html(&file) {
@@ -324,16 +747,53 @@ impl Plugin for Directory {
             };
             return Ok(Some(resp));
         }
+        if !self.try_files.is_empty() {
+            match self.get_try_file(&filename) {
+                Ok(value) => file = value,
+                Err(status) => {
+                    let resp = self
+                        .get_error_response(
+                            status,
+                            HttpResponse {
+                                status,
+                                headers: Some(vec![
+                                    HTTP_HEADER_NO_STORE.clone()
+                                ]),
+                                ..Default::default()
+                            },
+                        )
+                        .await;
+                    return Ok(Some(resp));
+                },
+            }
+        }
 
         // Content-Disposition: attachment; filename="example.pdf"
 
-        let resp = match get_data(&file).await {
-            Ok((meta, mut f)) => {
+        let data = if let Some((encoding, meta, f)) =
+            self.get_precompressed_data(session, &file).await
+        {
+            // the precompressed file should not be compressed again
+            if let Some(c) = session
+                .downstream_modules_ctx
+                .get_mut::<ResponseCompression>()
+            {
+                c.adjust_level(0);
+                c.adjust_decompression(false);
+            }
+            Ok((meta, f, Some(encoding)))
+        } else {
+            get_data(&file).await.map(|(meta, f)| (meta, f, None))
+        };
+
+        let resp = match data {
+            Ok((meta, mut f, encoding)) => {
                 let (cacheable, size, mut headers) =
                     get_cacheable_and_headers_from_meta(
                         &file,
                         &meta,
                         &self.charset,
+                        encoding,
                     );
                 if self.download {
                     if let Ok(value) = HeaderValue::from_str(&format!(
@@ -346,42 +806,187 @@ impl Plugin for Directory {
                 if let Some(arr) = &self.headers {
                     headers.extend(arr.clone());
                 }
+                if !self.precompressed.is_empty() {
+                    headers.push((
+                        header::VARY,
+                        HeaderValue::from_static("Accept-Encoding"),
+                    ));
+                }
+                let last_modified = meta.modified().ok();
+                if let Some(value) = last_modified.and_then(|value| {
+                    HeaderValue::from_str(&httpdate::fmt_http_date(value)).ok()
+                }) {
+                    headers.push((header::LAST_MODIFIED, value));
+                }
+                headers.push((
+                    header::ACCEPT_RANGES,
+                    HeaderValue::from_static("bytes"),
+                ));
+                let etag = headers
+                    .iter()
+                    .find(|(name, _)| name == header::ETAG)
+                    .and_then(|(_, value)| value.to_str().ok())
+                    .map(|value| value.to_string());
+                let mut max_age = if cacheable { self.max_age } else { None };
+                if cacheable && self.is_hashed_asset(&file) {
+                    // the content of hashed asset never changes
+                    max_age = self.hashed_max_age;
+                    let category = if self.cache_private.unwrap_or_default() {
+                        "private"
+                    } else {
+                        "public"
+                    };
+                    if let Ok(value) = HeaderValue::from_str(&format!(
+                        "{category}, max-age={}, immutable",
+                        max_age.unwrap_or_default()
+                    )) {
+                        headers.push((header::CACHE_CONTROL, value));
+                    }
+                }
                 let chunk_size = self.chunk_size.unwrap_or_default().max(4096);
-                if size <= chunk_size {
-                    let mut buffer = vec![0; size];
-                    match f.read(&mut buffer).await {
-                        Ok(_) => HttpResponse {
-                            status: StatusCode::OK,
-                            max_age: self.max_age,
+                let req_header = session.req_header();
+                if is_not_modified(req_header, etag.as_deref(), last_modified) {
+                    return Ok(Some(HttpResponse {
+                        status: StatusCode::NOT_MODIFIED,
+                        max_age,
+                        cache_private: self.cache_private,
+                        headers: Some(headers),
+                        ..Default::default()
+                    }));
+                }
+                let ranges = get_ranges(
+                    req_header,
+                    size as u64,
+                    etag.as_deref(),
+                    last_modified,
+                );
+                match ranges {
+                    RangeResult::Unsatisfiable => {
+                        let content_range =
+                            HeaderValue::from_str(&format!("bytes */{size}"))
+                                .map_err(|e| {
+                                util::new_internal_error(500, e.to_string())
+                            })?;
+                        HttpResponse {
+                            status: StatusCode::RANGE_NOT_SATISFIABLE,
+                            headers: Some(vec![(
+                                header::CONTENT_RANGE,
+                                content_range,
+                            )]),
+                            ..Default::default()
+                        }
+                    },
+                    RangeResult::Ranges(ranges) if ranges.len() == 1 => {
+                        let range = ranges[0];
+                        let content_range = HeaderValue::from_str(
+                            &range.content_range(size as u64),
+                        )
+                        .map_err(|e| {
+                            util::new_internal_error(500, e.to_string())
+                        })?;
+                        headers.push((header::CONTENT_RANGE, content_range));
+                        f.seek(SeekFrom::Start(range.start)).await.map_err(
+                            |e| util::new_internal_error(500, e.to_string()),
+                        )?;
+                        let mut reader = f.take(range.size());
+                        if range.size() as usize <= chunk_size {
+                            let mut buffer = vec![];
+                            reader.read_to_end(&mut buffer).await.map_err(
+                                |e| {
+                                    util::new_internal_error(500, e.to_string())
+                                },
+                            )?;
+                            HttpResponse {
+                                status: StatusCode::PARTIAL_CONTENT,
+                                max_age,
+                                cache_private: self.cache_private,
+                                headers: Some(headers),
+                                body: buffer.into(),
+                                ..Default::default()
+                            }
+                        } else {
+                            let mut resp = HttpChunkResponse::new(&mut reader);
+                            resp.chunk_size = chunk_size;
+                            resp.status = StatusCode::PARTIAL_CONTENT;
+                            resp.max_age = max_age;
+                            resp.cache_private = self.cache_private;
+                            resp.headers = Some(headers);
+                            ctx.status = Some(StatusCode::PARTIAL_CONTENT);
+                            resp.send(session).await?;
+                            IGNORE_RESPONSE.clone()
+                        }
+                    },
+                    RangeResult::Ranges(ranges) => {
+                        let resp = HttpResponse {
+                            status: StatusCode::PARTIAL_CONTENT,
+                            max_age,
                             cache_private: self.cache_private,
                             headers: Some(headers),
-                            body: buffer.into(),
                             ..Default::default()
-                        },
-                        Err(e) => {
-                            error!(error = e.to_string(), "read data fail");
-                            HttpResponse::bad_request(e.to_string().into())
-                        },
-                    }
-                } else {
-                    let mut resp = HttpChunkResponse::new(&mut f);
-                    resp.chunk_size = chunk_size;
-                    if cacheable {
-                        resp.max_age = self.max_age;
-                    }
-                    resp.cache_private = self.cache_private;
-                    resp.headers = Some(headers);
-                    ctx.status = Some(StatusCode::OK);
-                    resp.send(session).await?;
-                    // TODO better way to handle chunk response
-                    IGNORE_RESPONSE.clone()
+                        };
+                        ctx.status = Some(StatusCode::PARTIAL_CONTENT);
+                        send_multipart_ranges(
+                            session,
+                            resp,
+                            &mut f,
+                            &ranges,
+                            size as u64,
+                            chunk_size,
+                        )
+                        .await?;
+                        IGNORE_RESPONSE.clone()
+                    },
+                    RangeResult::Full => {
+                        if size <= chunk_size {
+                            let mut buffer = vec![0; size];
+                            match f.read(&mut buffer).await {
+                                Ok(_) => HttpResponse {
+                                    status: StatusCode::OK,
+                                    max_age,
+                                    cache_private: self.cache_private,
+                                    headers: Some(headers),
+                                    body: buffer.into(),
+                                    ..Default::default()
+                                },
+                                Err(e) => {
+                                    error!(
+                                        error = e.to_string(),
+                                        "read data fail"
+                                    );
+                                    HttpResponse::bad_request(
+                                        e.to_string().into(),
+                                    )
+                                },
+                            }
+                        } else {
+                            let mut resp = HttpChunkResponse::new(&mut f);
+                            resp.chunk_size = chunk_size;
+                            resp.max_age = max_age;
+                            resp.cache_private = self.cache_private;
+                            resp.headers = Some(headers);
+                            ctx.status = Some(StatusCode::OK);
+                            resp.send(session).await?;
+                            // TODO better way to handle chunk response
+                            IGNORE_RESPONSE.clone()
+                        }
+                    },
                 }
             },
             Err(err) => {
                 if err.kind() == std::io::ErrorKind::NotFound {
-                    HttpResponse::not_found("Not Found".into())
+                    self.get_error_response(
+                        StatusCode::NOT_FOUND,
+                        HttpResponse::not_found("Not Found".into()),
+                    )
+                    .await
                 } else {
-                    HttpResponse::unknown_error("Get file data fail".into())
+                    self.get_error_response(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        HttpResponse::unknown_error(
+                            "Get file data fail".into(),
+                        ),
+                    )
+                    .await
                 }
             },
         };
@@ -392,9 +997,13 @@ impl Plugin for Directory {
 
 #[cfg(test)]
 mod tests {
-    use super::{get_cacheable_and_headers_from_meta, get_data, Directory};
+    use super::{
+        get_accept_encodings, get_cacheable_and_headers_from_meta, get_data,
+        is_etag_matched, Directory,
+    };
     use crate::state::State;
     use crate::{config::PluginConf, config::PluginStep, plugin::Plugin};
+    use http::{header, StatusCode};
     use pingora::proxy::Session;
     use pretty_assertions::{assert_eq, assert_ne};
     #[cfg(unix)]
@@ -402,6 +1011,7 @@ mod tests {
     #[cfg(windows)]
     use std::os::windows::fs::MetadataExt;
     use std::path::Path;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
     use tokio_test::io::Builder;
 
     #[test]
@@ -530,6 +1140,361 @@ download = true
         );
     }
 
+    #[test]
+    fn test_get_accept_encodings() {
+        assert_eq!(
+            vec!["gzip", "br", "zstd"],
+            get_accept_encodings("gzip, BR;q=0.8 ,zstd")
+        );
+        assert_eq!(vec!["gzip"], get_accept_encodings("gzip, br;q=0"));
+        assert_eq!(true, get_accept_encodings("").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_precompressed() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("app.js"), "console.log(1)").unwrap();
+        std::fs::write(dir.path().join("app.js.br"), "br data").unwrap();
+        std::fs::write(dir.path().join("app.js.gz"), "gzip data").unwrap();
+        let directory = Directory::new(
+            &toml::from_str::<PluginConf>(&format!(
+                r###"
+path = "{}"
+precompressed = ["br", "zstd", "gzip"]
+"###,
+                dir.path().to_string_lossy()
+            ))
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(
+            vec![("br", ".br"), ("zstd", ".zst"), ("gzip", ".gz")],
+            directory.precompressed
+        );
+
+        let directory = &directory;
+        let request = |accept_encoding: &'static str| async move {
+            let input_header = format!(
+                "GET /app.js HTTP/1.1\r\nAccept-Encoding: {accept_encoding}\r\n\r\n"
+            );
+            let mock_io = Builder::new().read(input_header.as_bytes()).build();
+            let mut session = Session::new_h1(Box::new(mock_io));
+            session.read_request().await.unwrap();
+            directory
+                .handle_request(
+                    PluginStep::Request,
+                    &mut session,
+                    &mut State::default(),
+                )
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        let resp = request("gzip, br").await;
+        assert_eq!("br data", std::str::from_utf8(&resp.body).unwrap());
+        let headers = format!("{:?}", resp.headers.unwrap());
+        assert_eq!(true, headers.contains("-br\\\"\")"));
+        assert_eq!(true, headers.contains(r#"("content-encoding", "br")"#));
+        assert_eq!(true, headers.contains(r#"("vary", "Accept-Encoding")"#));
+        assert_eq!(
+            true,
+            headers.contains(r#"("content-type", "text/javascript")"#)
+                || headers
+                    .contains(r#"("content-type", "application/javascript")"#)
+        );
+
+        let resp = request("gzip, br;q=0").await;
+        assert_eq!("gzip data", std::str::from_utf8(&resp.body).unwrap());
+
+        let resp = request("deflate").await;
+        assert_eq!("console.log(1)", std::str::from_utf8(&resp.body).unwrap());
+        let headers = format!("{:?}", resp.headers.unwrap());
+        assert_eq!(false, headers.contains("content-encoding"));
+        assert_eq!(true, headers.contains(r#"("vary", "Accept-Encoding")"#));
+
+        let result = Directory::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+path = "./"
+precompressed = ["deflate"]
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin directory invalid, message: Precompressed encoding(deflate) is not supported",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_range_and_conditional() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("data.txt"), "0123456789").unwrap();
+        let directory = Directory::new(
+            &toml::from_str::<PluginConf>(&format!(
+                r###"
+path = "{}"
+"###,
+                dir.path().to_string_lossy()
+            ))
+            .unwrap(),
+        )
+        .unwrap();
+        let directory = &directory;
+        let request = |headers: String| async move {
+            let input_header =
+                format!("GET /data.txt HTTP/1.1\r\n{headers}\r\n");
+            let mock_io = Builder::new().read(input_header.as_bytes()).build();
+            let mut session = Session::new_h1(Box::new(mock_io));
+            session.read_request().await.unwrap();
+            directory
+                .handle_request(
+                    PluginStep::Request,
+                    &mut session,
+                    &mut State::default(),
+                )
+                .await
+                .unwrap()
+                .unwrap()
+        };
+
+        let resp = request("".to_string()).await;
+        assert_eq!(StatusCode::OK, resp.status);
+        let headers = resp.headers.unwrap();
+        let get_header = |name| {
+            headers
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.to_str().unwrap().to_string())
+                .unwrap()
+        };
+        assert_eq!("bytes", get_header(header::ACCEPT_RANGES));
+        let etag = get_header(header::ETAG);
+        let last_modified = get_header(header::LAST_MODIFIED);
+
+        let resp = request(format!("If-None-Match: {etag}\r\n")).await;
+        assert_eq!(StatusCode::NOT_MODIFIED, resp.status);
+        assert_eq!(true, resp.body.is_empty());
+        assert_eq!(
+            true,
+            resp.get_response_header()
+                .unwrap()
+                .headers
+                .get(header::CONTENT_LENGTH)
+                .is_none()
+        );
+        let resp = request(format!(
+            "If-None-Match: \"abc\"\r\nIf-Modified-Since: {last_modified}\r\n"
+        ))
+        .await;
+        assert_eq!(StatusCode::OK, resp.status);
+        let resp =
+            request(format!("If-Modified-Since: {last_modified}\r\n")).await;
+        assert_eq!(StatusCode::NOT_MODIFIED, resp.status);
+
+        let resp = request("Range: bytes=2-4\r\n".to_string()).await;
+        assert_eq!(StatusCode::PARTIAL_CONTENT, resp.status);
+        assert_eq!("234", std::str::from_utf8(&resp.body).unwrap());
+        let headers = format!("{:?}", resp.headers.unwrap());
+        assert_eq!(
+            true,
+            headers.contains(r#"("content-range", "bytes 2-4/10")"#)
+        );
+
+        let resp = request("Range: bytes=-3\r\n".to_string()).await;
+        assert_eq!("789", std::str::from_utf8(&resp.body).unwrap());
+
+        let resp = request("Range: bytes=20-\r\n".to_string()).await;
+        assert_eq!(StatusCode::RANGE_NOT_SATISFIABLE, resp.status);
+        let headers = format!("{:?}", resp.headers.unwrap());
+        assert_eq!(
+            true,
+            headers.contains(r#"("content-range", "bytes */10")"#)
+        );
+
+        // if range is not matched, response the full content
+        let resp =
+            request("Range: bytes=2-4\r\nIf-Range: \"abc\"\r\n".to_string())
+                .await;
+        assert_eq!(StatusCode::OK, resp.status);
+        assert_eq!("0123456789", std::str::from_utf8(&resp.body).unwrap());
+        // the weak etag can't be used for if range
+        let resp =
+            request(format!("Range: bytes=2-4\r\nIf-Range: {etag}\r\n")).await;
+        assert_eq!(StatusCode::OK, resp.status);
+        assert_eq!("0123456789", std::str::from_utf8(&resp.body).unwrap());
+        let resp = request(format!(
+            "Range: bytes=2-4\r\nIf-Range: {last_modified}\r\n"
+        ))
+        .await;
+        assert_eq!(StatusCode::PARTIAL_CONTENT, resp.status);
+    }
+
+    #[tokio::test]
+    async fn test_try_files_and_error_page() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("index.html"), "<p>app</p>").unwrap();
+        std::fs::write(dir.path().join("404.html"), "<p>404</p>").unwrap();
+        std::fs::write(dir.path().join("main.3f2a1b4c.js"), "hashed").unwrap();
+        std::fs::create_dir(dir.path().join("docs")).unwrap();
+        std::fs::write(dir.path().join("docs/index.html"), "<p>docs</p>")
+            .unwrap();
+        let new_directory = |try_files: &str| {
+            Directory::new(
+                &toml::from_str::<PluginConf>(&format!(
+                    r###"
+path = "{}"
+index = "index.html"
+try_files = [{try_files}]
+error_page = ["404 /404.html"]
+max_age = "1h"
+hashed_max_age = "365d"
+"###,
+                    dir.path().to_string_lossy()
+                ))
+                .unwrap(),
+            )
+            .unwrap()
+        };
+        let request = |directory: Directory, uri: &'static str| async move {
+            let input_header = format!("GET {uri} HTTP/1.1\r\n\r\n");
+            let mock_io = Builder::new().read(input_header.as_bytes()).build();
+            let mut session = Session::new_h1(Box::new(mock_io));
+            session.read_request().await.unwrap();
+            directory
+                .handle_request(
+                    PluginStep::Request,
+                    &mut session,
+                    &mut State::default(),
+                )
+                .await
+                .unwrap()
+                .unwrap()
+        };
+        let spa = r#""$uri", "$uri/", "/index.html""#;
+
+        let resp = request(new_directory(spa), "/users/1").await;
+        assert_eq!(StatusCode::OK, resp.status);
+        assert_eq!("<p>app</p>", std::str::from_utf8(&resp.body).unwrap());
+        assert_eq!(
+            "private, no-cache",
+            resp.get_response_header()
+                .unwrap()
+                .headers
+                .get("cache-control")
+                .unwrap()
+        );
+
+        let resp = request(new_directory(spa), "/docs").await;
+        assert_eq!("<p>docs</p>", std::str::from_utf8(&resp.body).unwrap());
+
+        let resp = request(new_directory(spa), "/main.3f2a1b4c.js").await;
+        assert_eq!("hashed", std::str::from_utf8(&resp.body).unwrap());
+        assert_eq!(
+            "public, max-age=31536000, immutable",
+            resp.get_response_header()
+                .unwrap()
+                .headers
+                .get("cache-control")
+                .unwrap()
+        );
+
+        let resp =
+            request(new_directory(r#""$uri", "=404""#), "/users/1").await;
+        assert_eq!(StatusCode::NOT_FOUND, resp.status);
+        assert_eq!("<p>404</p>", std::str::from_utf8(&resp.body).unwrap());
+
+        let resp = request(new_directory(""), "/not-found.js").await;
+        assert_eq!(StatusCode::NOT_FOUND, resp.status);
+        assert_eq!("<p>404</p>", std::str::from_utf8(&resp.body).unwrap());
+        assert_eq!(
+            r#"[("content-type", "text/html")]"#,
+            format!("{:?}", resp.headers.unwrap())
+        );
+
+        let result = Directory::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+path = "./"
+error_page = ["/404.html"]
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin directory invalid, message: Error page(/404.html) is invalid",
+            result.err().unwrap().to_string()
+        );
+        let result = Directory::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+path = "./"
+try_files = ["$uri", "=abc"]
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin directory invalid, message: Try files(=abc) is invalid",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chunk_response_cache_control() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("main.3f2a1b4c.js"), "a".repeat(10000))
+            .unwrap();
+        let directory = Directory::new(
+            &toml::from_str::<PluginConf>(&format!(
+                r###"
+path = "{}"
+chunk_size = 4096
+hashed_max_age = "365d"
+"###,
+                dir.path().to_string_lossy()
+            ))
+            .unwrap(),
+        )
+        .unwrap();
+
+        let (mut client, server) = tokio::io::duplex(64 * 1024);
+        client
+            .write_all(b"GET /main.3f2a1b4c.js HTTP/1.1\r\n\r\n")
+            .await
+            .unwrap();
+        let mut session = Session::new_h1(Box::new(server));
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+        directory
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(Some(StatusCode::OK), ctx.status);
+        drop(session);
+
+        let mut buf = vec![];
+        client.read_to_end(&mut buf).await.unwrap();
+        let resp = std::str::from_utf8(&buf).unwrap().to_lowercase();
+        let (header, _) = resp.split_once("\r\n\r\n").unwrap();
+        assert_eq!(true, header.contains("transfer-encoding: chunked"));
+        assert_eq!(
+            true,
+            header
+                .contains("cache-control: public, max-age=31536000, immutable")
+        );
+    }
+
+    #[test]
+    fn test_is_etag_matched() {
+        assert_eq!(true, is_etag_matched("*", r#""a""#));
+        assert_eq!(true, is_etag_matched(r#""b", W/"a""#, r#""a""#));
+        assert_eq!(true, is_etag_matched(r#""a""#, r#"W/"a""#));
+        assert_eq!(false, is_etag_matched(r#""b""#, r#""a""#));
+    }
+
     #[tokio::test]
     async fn test_get_data() {
         let file = Path::new("./error.html").to_path_buf();
@@ -541,6 +1506,7 @@ download = true
             &file,
             &meta,
             &Some("utf-8".to_string()),
+            None,
         );
         assert_eq!(false, cacheable);
         assert_eq!(
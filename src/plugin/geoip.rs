@@ -0,0 +1,577 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::schema::{one_of, string, strings, PluginField, RESTRICTION_TYPES};
+use super::{
+    get_hash_key, get_step_conf, get_str_conf, get_str_slice_conf, Error,
+    Plugin, Result,
+};
+use crate::config::{PluginCategory, PluginConf, PluginStep};
+use crate::http_extra::HttpResponse;
+use crate::service::{CommonServiceTask, ServiceTask};
+use crate::state::State;
+use crate::util;
+use ahash::AHashMap;
+use arc_swap::ArcSwap;
+use async_trait::async_trait;
+use bytes::Bytes;
+use http::{HeaderName, StatusCode};
+use maxminddb::Reader;
+use once_cell::sync::Lazy;
+use pingora::proxy::Session;
+use serde::Deserialize;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+use tracing::{debug, error, info};
+
+struct GeoipDatabase {
+    modified: Option<SystemTime>,
+    reader: Reader<Vec<u8>>,
+}
+
+// the opened databases, the key is the path of database file
+static GEOIP_DATABASES: Lazy<ArcSwap<AHashMap<String, Arc<GeoipDatabase>>>> =
+    Lazy::new(|| ArcSwap::from_pointee(AHashMap::new()));
+
+fn get_modified(file: &str) -> Option<SystemTime> {
+    std::fs::metadata(file)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+}
+
+fn open_database(file: &str) -> Result<GeoipDatabase> {
+    let modified = get_modified(file);
+    let reader = Reader::open_readfile(file).map_err(|e| Error::Invalid {
+        category: PluginCategory::Geoip.to_string(),
+        message: format!("open database({file}) fail, {e}"),
+    })?;
+    Ok(GeoipDatabase { modified, reader })
+}
+
+fn store_database(file: &str, db: GeoipDatabase) -> Arc<GeoipDatabase> {
+    let db = Arc::new(db);
+    let mut databases: AHashMap<String, Arc<GeoipDatabase>> =
+        GEOIP_DATABASES.load().as_ref().clone();
+    databases.insert(file.to_string(), db.clone());
+    GEOIP_DATABASES.store(Arc::new(databases));
+    db
+}
+
+// Get the database from cache, it will be opened if not exists.
+fn get_database(file: &str) -> Result<Arc<GeoipDatabase>> {
+    if let Some(db) = GEOIP_DATABASES.load().get(file) {
+        return Ok(db.clone());
+    }
+    Ok(store_database(file, open_database(file)?))
+}
+
+/// Reload the databases whose file has been modified,
+/// the reloaded files are returned.
+fn reload_databases() -> Vec<String> {
+    let mut reloaded = vec![];
+    for (file, db) in GEOIP_DATABASES.load().iter() {
+        let modified = get_modified(file);
+        if modified.is_none() || modified == db.modified {
+            continue;
+        }
+        match open_database(file) {
+            Ok(db) => {
+                store_database(file, db);
+                reloaded.push(file.to_string());
+            },
+            Err(e) => {
+                // keep using the old database
+                error!(
+                    error = e.to_string(),
+                    file, "reload geoip database fail"
+                );
+            },
+        }
+    }
+    reloaded
+}
+
+struct GeoipReloadService {}
+
+#[async_trait]
+impl ServiceTask for GeoipReloadService {
+    async fn run(&self) -> Option<bool> {
+        let reloaded = reload_databases();
+        if !reloaded.is_empty() {
+            info!(files = reloaded.join(","), "reload geoip database success");
+        }
+        None
+    }
+    fn description(&self) -> String {
+        "GeoipReload".to_string()
+    }
+}
+
+/// Create a geoip reload service, which reloads the database
+/// when the file is modified.
+pub fn new_geoip_reload_service() -> CommonServiceTask {
+    CommonServiceTask::new(
+        // check interval: thirty seconds
+        Duration::from_secs(30),
+        GeoipReloadService {},
+    )
+}
+
+#[derive(Deserialize, Default)]
+struct GeoipCountry<'a> {
+    iso_code: Option<&'a str>,
+}
+
+// The record of country/city and asn database,
+// only the fields used by plugin are decoded.
+#[derive(Deserialize, Default)]
+struct GeoipRecord<'a> {
+    #[serde(borrow)]
+    country: Option<GeoipCountry<'a>>,
+    #[serde(borrow)]
+    registered_country: Option<GeoipCountry<'a>>,
+    autonomous_system_number: Option<u32>,
+    autonomous_system_organization: Option<&'a str>,
+}
+
+#[derive(Debug, Default, PartialEq)]
+struct GeoipInfo {
+    country: Option<String>,
+    asn: Option<u32>,
+    asn_org: Option<String>,
+}
+
+fn lookup(files: &[String], ip: IpAddr) -> GeoipInfo {
+    let mut info = GeoipInfo::default();
+    for file in files.iter() {
+        let Some(db) = GEOIP_DATABASES.load().get(file).cloned() else {
+            continue;
+        };
+        let Ok(record) = db.reader.lookup::<GeoipRecord>(ip) else {
+            continue;
+        };
+        if info.country.is_none() {
+            info.country = record
+                .country
+                .or(record.registered_country)
+                .and_then(|item| item.iso_code)
+                .map(|item| item.to_string());
+        }
+        if info.asn.is_none() {
+            info.asn = record.autonomous_system_number;
+            info.asn_org = record
+                .autonomous_system_organization
+                .map(|item| item.to_string());
+        }
+    }
+    info
+}
+
+/// The geoip plugin resolves the country and asn of client ip
+/// from the local MaxMind databases, the request can be restricted
+/// by country and the values are added to the upstream request headers.
+pub struct Geoip {
+    plugin_step: PluginStep,
+    // the files of country(city) and asn database
+    files: Vec<String>,
+    countries: Vec<String>,
+    restriction_category: String,
+    country_header: HeaderName,
+    asn_header: HeaderName,
+    forbidden_resp: HttpResponse,
+    hash_value: String,
+}
+
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        string("database"),
+        string("asn_database"),
+        string("country_header"),
+        string("asn_header"),
+        one_of("type", RESTRICTION_TYPES),
+        strings("countries"),
+        string("message"),
+    ]
+}
+
+impl TryFrom<&PluginConf> for Geoip {
+    type Error = Error;
+    fn try_from(value: &PluginConf) -> Result<Self> {
+        let hash_value = get_hash_key(value);
+        let step = get_step_conf(value);
+        let category = PluginCategory::Geoip.to_string();
+
+        let mut files = vec![];
+        for key in ["database", "asn_database"] {
+            let file = get_str_conf(value, key);
+            if file.is_empty() {
+                continue;
+            }
+            let file = util::resolve_path(&file);
+            // open the database when the plugin is created
+            get_database(&file)?;
+            files.push(file);
+        }
+        if files.is_empty() {
+            return Err(Error::Invalid {
+                category,
+                message: "Geoip database can not be empty".to_string(),
+            });
+        }
+        let get_header_name = |key: &str, default_value: &str| {
+            let mut name = get_str_conf(value, key);
+            if name.is_empty() {
+                name = default_value.to_string();
+            }
+            HeaderName::from_str(&name).map_err(|e| Error::Invalid {
+                category: category.clone(),
+                message: e.to_string(),
+            })
+        };
+        let country_header =
+            get_header_name("country_header", "X-Country-Code")?;
+        let asn_header = get_header_name("asn_header", "X-Asn")?;
+
+        let mut message = get_str_conf(value, "message");
+        if message.is_empty() {
+            message = "Request is forbidden".to_string();
+        }
+        let params = Self {
+            hash_value,
+            plugin_step: step,
+            files,
+            countries: get_str_slice_conf(value, "countries")
+                .iter()
+                .map(|item| item.to_uppercase())
+                .collect(),
+            restriction_category: get_str_conf(value, "type"),
+            country_header,
+            asn_header,
+            forbidden_resp: HttpResponse {
+                status: StatusCode::FORBIDDEN,
+                body: Bytes::from(message),
+                ..Default::default()
+            },
+        };
+        if params.plugin_step == PluginStep::Response {
+            return Err(Error::Invalid {
+                category,
+                message: "Geoip plugin should be executed at request or proxy upstream step".to_string(),
+            });
+        }
+
+        Ok(params)
+    }
+}
+
+impl Geoip {
+    pub fn new(params: &PluginConf) -> Result<Self> {
+        debug!(params = params.to_string(), "new geoip plugin");
+        Self::try_from(params)
+    }
+    fn is_allowed(&self, country: &Option<String>) -> bool {
+        if self.countries.is_empty() {
+            return true;
+        }
+        let found = country
+            .as_ref()
+            .map(|item| self.countries.contains(item))
+            .unwrap_or_default();
+        if self.restriction_category == "deny" {
+            !found
+        } else {
+            found
+        }
+    }
+}
+
+#[async_trait]
+impl Plugin for Geoip {
+    #[inline]
+    fn hash_key(&self) -> String {
+        self.hash_value.clone()
+    }
+    #[inline]
+    async fn handle_request(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+    ) -> pingora::Result<Option<HttpResponse>> {
+        if step != self.plugin_step {
+            return Ok(None);
+        }
+        let ip = if let Some(ip) = &ctx.client_ip {
+            ip.to_string()
+        } else {
+            let ip = util::get_client_ip(session);
+            ctx.client_ip = Some(ip.clone());
+            ip
+        };
+        let info = ip
+            .parse::<IpAddr>()
+            .map(|ip| lookup(&self.files, ip))
+            .unwrap_or_default();
+
+        // the headers from client are removed
+        let header = session.req_header_mut();
+        header.remove_header(&self.country_header);
+        header.remove_header(&self.asn_header);
+        if let Some(country) = &info.country {
+            let _ = header.insert_header(self.country_header.clone(), country);
+        }
+        if let Some(asn) = info.asn {
+            let _ =
+                header.insert_header(self.asn_header.clone(), asn.to_string());
+        }
+        let allowed = self.is_allowed(&info.country);
+        ctx.geoip_country = info.country;
+        ctx.geoip_asn = info.asn;
+        ctx.geoip_asn_org = info.asn_org;
+        if !allowed {
+            return Ok(Some(self.forbidden_resp.clone()));
+        }
+        Ok(None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{lookup, reload_databases, Geoip, GeoipInfo};
+    use crate::state::State;
+    use crate::{config::PluginConf, config::PluginStep, plugin::Plugin};
+    use http::StatusCode;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+    use tokio_test::io::Builder;
+
+    fn encode_size(buf: &mut Vec<u8>, data_type: u8, size: usize) {
+        // the size which is larger than 28 is stored in the next byte
+        let ctrl_size = size.min(29) as u8;
+        if data_type > 7 {
+            buf.push(ctrl_size);
+            buf.push(data_type - 7);
+        } else {
+            buf.push((data_type << 5) | ctrl_size);
+        }
+        if size >= 29 {
+            buf.push((size - 29) as u8);
+        }
+    }
+    fn encode_str(buf: &mut Vec<u8>, value: &str) {
+        encode_size(buf, 2, value.len());
+        buf.extend(value.as_bytes());
+    }
+    fn encode_uint(buf: &mut Vec<u8>, data_type: u8, value: u64) {
+        let bytes: Vec<u8> = value
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|item| *item == 0)
+            .collect();
+        encode_size(buf, data_type, bytes.len());
+        buf.extend(bytes);
+    }
+
+    // Write a ipv4 database of MaxMind format, the value of each network
+    // is the encoded data.
+    fn write_database(file: &str, networks: &[(&str, u8, Vec<u8>)]) {
+        // the children of node, none means no data
+        let mut nodes: Vec<[Option<(bool, usize)>; 2]> = vec![[None, None]];
+        for (index, (ip, prefix, _)) in networks.iter().enumerate() {
+            let ip = u32::from(ip.parse::<std::net::Ipv4Addr>().unwrap());
+            let mut node = 0;
+            for i in 0..*prefix {
+                let bit = ((ip >> (31 - i)) & 1) as usize;
+                if i == prefix - 1 {
+                    nodes[node][bit] = Some((true, index));
+                    break;
+                }
+                node = match nodes[node][bit] {
+                    Some((false, next)) => next,
+                    _ => {
+                        nodes.push([None, None]);
+                        nodes[node][bit] = Some((false, nodes.len() - 1));
+                        nodes.len() - 1
+                    },
+                };
+            }
+        }
+        let mut data: Vec<u8> = vec![];
+        let mut offsets = vec![];
+        for (_, _, value) in networks.iter() {
+            offsets.push(data.len());
+            data.extend(value);
+        }
+        let node_count = nodes.len();
+        let mut buf = vec![];
+        for node in nodes.iter() {
+            for child in node.iter() {
+                let value = match child {
+                    Some((false, next)) => *next,
+                    Some((true, index)) => node_count + 16 + offsets[*index],
+                    None => node_count,
+                };
+                buf.extend(&(value as u32).to_be_bytes()[1..]);
+            }
+        }
+        buf.extend([0; 16]);
+        buf.extend(data);
+        buf.extend(b"\xAB\xCD\xEFMaxMind.com");
+        encode_size(&mut buf, 7, 9);
+        encode_str(&mut buf, "binary_format_major_version");
+        encode_uint(&mut buf, 5, 2);
+        encode_str(&mut buf, "binary_format_minor_version");
+        encode_uint(&mut buf, 5, 0);
+        encode_str(&mut buf, "build_epoch");
+        encode_uint(&mut buf, 9, 1);
+        encode_str(&mut buf, "database_type");
+        encode_str(&mut buf, "Pingap-Test");
+        encode_str(&mut buf, "description");
+        encode_size(&mut buf, 7, 0);
+        encode_str(&mut buf, "ip_version");
+        encode_uint(&mut buf, 5, 4);
+        encode_str(&mut buf, "languages");
+        encode_size(&mut buf, 11, 0);
+        encode_str(&mut buf, "node_count");
+        encode_uint(&mut buf, 6, node_count as u64);
+        encode_str(&mut buf, "record_size");
+        encode_uint(&mut buf, 5, 24);
+        std::fs::write(file, buf).unwrap();
+    }
+
+    fn new_country(code: &str) -> Vec<u8> {
+        let mut buf = vec![];
+        encode_size(&mut buf, 7, 1);
+        encode_str(&mut buf, "country");
+        encode_size(&mut buf, 7, 1);
+        encode_str(&mut buf, "iso_code");
+        encode_str(&mut buf, code);
+        buf
+    }
+
+    fn new_asn(asn: u32, org: &str) -> Vec<u8> {
+        let mut buf = vec![];
+        encode_size(&mut buf, 7, 2);
+        encode_str(&mut buf, "autonomous_system_number");
+        encode_uint(&mut buf, 6, asn as u64);
+        encode_str(&mut buf, "autonomous_system_organization");
+        encode_str(&mut buf, org);
+        buf
+    }
+
+    #[tokio::test]
+    async fn test_geoip() {
+        let dir = tempfile::tempdir().unwrap();
+        let country_file = dir.path().join("country.mmdb");
+        let country_file = country_file.to_string_lossy().to_string();
+        let asn_file = dir.path().join("asn.mmdb");
+        let asn_file = asn_file.to_string_lossy().to_string();
+        write_database(
+            &country_file,
+            &[
+                ("1.0.0.0", 8, new_country("CN")),
+                ("2.2.0.0", 16, new_country("US")),
+            ],
+        );
+        write_database(&asn_file, &[("1.1.0.0", 16, new_asn(13335, "CF"))]);
+
+        let geoip = Geoip::new(
+            &toml::from_str::<PluginConf>(&format!(
+                r###"
+database = "{country_file}"
+asn_database = "{asn_file}"
+countries = ["cn"]
+type = "deny"
+"###
+            ))
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("request", geoip.plugin_step.to_string());
+        assert_eq!(vec!["CN".to_string()], geoip.countries);
+
+        assert_eq!(
+            GeoipInfo {
+                country: Some("CN".to_string()),
+                asn: Some(13335),
+                asn_org: Some("CF".to_string()),
+            },
+            lookup(&geoip.files, "1.1.1.1".parse().unwrap())
+        );
+        assert_eq!(
+            GeoipInfo::default(),
+            lookup(&geoip.files, "3.3.3.3".parse().unwrap())
+        );
+
+        let headers = ["X-Country-Code: CN"].join("\r\n");
+        let input_header = format!("GET / HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let mut ctx = State {
+            client_ip: Some("2.2.2.2".to_string()),
+            ..Default::default()
+        };
+        let result = geoip
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+        assert_eq!(Some("US".to_string()), ctx.geoip_country);
+        assert_eq!(
+            "US",
+            session
+                .get_header("X-Country-Code")
+                .unwrap()
+                .to_str()
+                .unwrap()
+        );
+        assert_eq!(true, session.get_header("X-Asn").is_none());
+
+        let mut ctx = State {
+            client_ip: Some("1.1.1.1".to_string()),
+            ..Default::default()
+        };
+        let result = geoip
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, result.unwrap().status);
+        assert_eq!(Some(13335), ctx.geoip_asn);
+        assert_eq!(
+            "13335",
+            session.get_header("X-Asn").unwrap().to_str().unwrap()
+        );
+
+        // the modified database will be reloaded
+        std::thread::sleep(Duration::from_millis(10));
+        write_database(&country_file, &[("1.0.0.0", 8, new_country("JP"))]);
+        let file = std::fs::File::options()
+            .write(true)
+            .open(&country_file)
+            .unwrap();
+        file.set_modified(
+            std::time::SystemTime::now() + Duration::from_secs(60),
+        )
+        .unwrap();
+        assert_eq!(true, reload_databases().contains(&country_file));
+        assert_eq!(
+            Some("JP".to_string()),
+            lookup(&geoip.files, "1.1.1.1".parse().unwrap()).country
+        );
+    }
+}
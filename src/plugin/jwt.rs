@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{duration, one_of, string, PluginField};
 use super::{get_hash_key, get_step_conf, get_str_conf, Error, Plugin, Result};
 use crate::config::{PluginCategory, PluginConf, PluginStep};
 use crate::http_extra::{HttpResponse, HTTP_HEADER_CONTENT_JSON};
@@ -43,6 +44,18 @@ pub struct JwtAuth {
     hash_value: String,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        string("auth_path"),
+        string("secret"),
+        one_of("algorithm", &["HS256", "HS512"]),
+        string("header"),
+        string("query"),
+        string("cookie"),
+        duration("delay"),
+    ]
+}
+
 impl TryFrom<&PluginConf> for JwtAuth {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
@@ -12,12 +12,15 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{duration, int, one_of, string, PluginField};
 use super::{
     get_hash_key, get_int_conf, get_step_conf, get_str_conf, Error, Plugin,
     Result,
 };
+use crate::blocklist::{self, BlockSource};
 use crate::config::{PluginCategory, PluginConf, PluginStep};
 use crate::http_extra::HttpResponse;
+use crate::limit::TtlLruLimit;
 use crate::state::State;
 use crate::util;
 use async_trait::async_trait;
@@ -27,7 +30,7 @@ use pingora::proxy::Session;
 use pingora_limits::inflight::Inflight;
 use pingora_limits::rate::Rate;
 use std::time::Duration;
-use tracing::debug;
+use tracing::{debug, info};
 
 #[derive(PartialEq, Debug)]
 pub enum LimitTag {
@@ -45,6 +48,21 @@ pub struct Limiter {
     rate: Option<Rate>,
     plugin_step: PluginStep,
     hash_value: String,
+    // block the client ip after exceeding the limit too many times
+    exceeded: Option<TtlLruLimit>,
+    block_ttl: Duration,
+}
+
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        one_of("type", &["rate", "inflight"]),
+        one_of("tag", &["cookie", "header", "query", "ip"]),
+        string("key"),
+        int("max"),
+        duration("interval"),
+        int("block_after"),
+        duration("block_ttl"),
+    ]
 }
 
 impl TryFrom<&PluginConf> for Limiter {
@@ -68,6 +86,23 @@ impl TryFrom<&PluginConf> for Limiter {
         } else {
             Duration::from_secs(10)
         };
+        let block_ttl = get_str_conf(value, "block_ttl");
+        let block_ttl = if !block_ttl.is_empty() {
+            parse_duration(&block_ttl).map_err(|e| Error::Invalid {
+                category: PluginCategory::Limit.to_string(),
+                message: e.to_string(),
+            })?
+        } else {
+            Duration::from_secs(10 * 60)
+        };
+        let block_after = get_int_conf(value, "block_after");
+        // only the ip limit supports to feed the blocklist,
+        // the exceeded count is counted within block ttl
+        let exceeded = if block_after > 0 && tag == LimitTag::Ip {
+            Some(TtlLruLimit::new(10 * 1024, block_ttl, block_after as usize))
+        } else {
+            None
+        };
         let mut inflight = None;
         let mut rate = None;
         if get_str_conf(value, "type") == "inflight" {
@@ -84,6 +119,8 @@ impl TryFrom<&PluginConf> for Limiter {
             inflight,
             rate,
             plugin_step: step,
+            exceeded,
+            block_ttl,
         };
         if ![PluginStep::Request, PluginStep::ProxyUpstream]
             .contains(&params.plugin_step)
@@ -121,10 +158,18 @@ impl Limiter {
                     .unwrap_or_default()
                     .to_string()
             },
+            // the blocklist is fed by the ip which can't be spoofed
+            _ if self.exceeded.is_some() => {
+                ctx.trusted_client_ip.clone().unwrap_or_default()
+            },
             _ => {
-                let client_ip = util::get_client_ip(session);
-                ctx.client_ip = Some(client_ip.clone());
-                client_ip
+                if let Some(client_ip) = &ctx.client_ip {
+                    client_ip.to_string()
+                } else {
+                    let client_ip = util::get_client_ip(session);
+                    ctx.client_ip = Some(client_ip.clone());
+                    client_ip
+                }
             },
         };
         if key.is_empty() {
@@ -168,6 +213,25 @@ impl Plugin for Limiter {
             return Ok(None);
         }
         if let Err(e) = self.incr(session, ctx) {
+            if let (Some(exceeded), Some(client_ip)) =
+                (&self.exceeded, &ctx.trusted_client_ip)
+            {
+                exceeded.inc(client_ip).await;
+                if !exceeded.validate(client_ip).await {
+                    info!(client_ip, "limit exceeded too many times, block it");
+                    if let Err(err) = blocklist::add(
+                        client_ip,
+                        Some(self.block_ttl),
+                        BlockSource::Limit,
+                        Some(e.to_string()),
+                    ) {
+                        debug!(
+                            error = err.to_string(),
+                            "add to blocklist fail"
+                        );
+                    }
+                }
+            }
             return Ok(Some(HttpResponse {
                 status: StatusCode::TOO_MANY_REQUESTS,
                 body: e.to_string().into(),
@@ -182,7 +246,8 @@ impl Plugin for Limiter {
 mod tests {
     use super::{LimitTag, Limiter};
     use crate::{
-        config::PluginConf, config::PluginStep, plugin::Plugin, state::State,
+        blocklist, config::PluginConf, config::PluginStep, plugin::Plugin,
+        state::State,
     };
     use http::StatusCode;
     use pingora::proxy::Session;
@@ -390,6 +455,48 @@ max = 1
         assert_eq!(true, result.is_none());
     }
 
+    #[tokio::test]
+    async fn test_limit_block() {
+        let limiter = Limiter::new(
+            &toml::from_str::<PluginConf>(
+                r###"
+type = "inflight"
+max = 0
+block_after = 2
+block_ttl = "1m"
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(true, limiter.exceeded.is_some());
+        assert_eq!(Duration::from_secs(60), limiter.block_ttl);
+
+        // the forwarded header is spoofed by client
+        let headers = ["X-Forwarded-For: 198.18.0.2"].join("\r\n");
+        let input_header =
+            format!("GET /vicanso/pingap?size=1 HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let new_ctx = || State {
+            trusted_client_ip: Some("198.18.0.1".to_string()),
+            ..Default::default()
+        };
+
+        let _ = limiter
+            .handle_request(PluginStep::Request, &mut session, &mut new_ctx())
+            .await
+            .unwrap();
+        assert_eq!(false, blocklist::is_blocked("198.18.0.1"));
+        let _ = limiter
+            .handle_request(PluginStep::Request, &mut session, &mut new_ctx())
+            .await
+            .unwrap();
+        assert_eq!(true, blocklist::is_blocked("198.18.0.1"));
+        assert_eq!(false, blocklist::is_blocked("198.18.0.2"));
+    }
+
     #[tokio::test]
     async fn test_rate_limit() {
         let limiter = Limiter::new(
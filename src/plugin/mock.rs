@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{duration, int, string, strings, PluginField};
 use super::{get_step_conf, get_str_conf, Error, Plugin, Result};
 use crate::config::{PluginCategory, PluginConf, PluginStep};
 use crate::http_extra::{convert_headers, HttpResponse};
@@ -33,6 +34,16 @@ pub struct MockResponse {
     hash_value: String,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        string("path"),
+        int("status"),
+        strings("headers"),
+        string("data"),
+        duration("delay"),
+    ]
+}
+
 impl MockResponse {
     /// Creates a new mock response upstream, which will return a mock data.
     pub fn new(params: &PluginConf) -> Result<Self> {
@@ -19,6 +19,7 @@ use crate::state::{get_admin_addr, State};
 use ahash::AHashMap;
 use arc_swap::ArcSwap;
 use async_trait::async_trait;
+use bytes::Bytes;
 use once_cell::sync::Lazy;
 use pingora::http::ResponseHeader;
 use pingora::proxy::Session;
@@ -28,15 +29,22 @@ use std::str::FromStr;
 use std::sync::Arc;
 use tracing::info;
 
+pub use cache::get_cache_backend;
+pub use geoip::new_geoip_reload_service;
+pub use schema::{get_plugin_json_schema, validate_plugin_conf};
+
 mod accept_encoding;
 mod admin;
 mod basic_auth;
 mod cache;
+mod challenge;
+mod coalesce;
 mod combined_auth;
 mod compression;
 mod cors;
 mod csrf;
 mod directory;
+mod geoip;
 mod ip_restriction;
 mod jwt;
 mod key_auth;
@@ -47,8 +55,10 @@ mod redirect;
 mod referer_restriction;
 mod request_id;
 mod response_headers;
+mod schema;
 mod stats;
 mod ua_restriction;
+mod waf;
 
 pub static ADMIN_SERVER_PLUGIN: Lazy<String> =
     Lazy::new(|| uuid::Uuid::now_v7().to_string());
@@ -94,6 +104,8 @@ pub enum Error {
         max: isize,
         value: isize,
     },
+    #[snafu(display("{path}: {message}"))]
+    Field { path: String, message: String },
     #[snafu(display("Plugin {category}, base64 decode error {source}"))]
     Base64Decode {
         category: String,
@@ -141,6 +153,15 @@ pub trait Plugin: Sync + Send {
     ) -> pingora::Result<()> {
         Ok(())
     }
+    async fn handle_request_body(
+        &self,
+        _session: &mut Session,
+        _body: &mut Option<Bytes>,
+        _end_of_stream: bool,
+        _ctx: &mut State,
+    ) -> pingora::Result<()> {
+        Ok(())
+    }
 }
 
 pub fn get_builtin_proxy_plugins() -> Vec<(String, PluginConf)> {
@@ -299,6 +320,22 @@ pub fn parse_plugins(confs: Vec<(String, PluginConf)>) -> Result<Plugins> {
                     accept_encoding::AcceptEncoding::new(conf)?;
                 plguins.insert(name.clone(), Arc::new(accept_encoding));
             },
+            PluginCategory::Waf => {
+                let w = waf::Waf::new(conf)?;
+                plguins.insert(name, Arc::new(w));
+            },
+            PluginCategory::Geoip => {
+                let g = geoip::Geoip::new(conf)?;
+                plguins.insert(name, Arc::new(g));
+            },
+            PluginCategory::Challenge => {
+                let c = challenge::Challenge::new(conf)?;
+                plguins.insert(name, Arc::new(c));
+            },
+            PluginCategory::Coalesce => {
+                let c = coalesce::Coalesce::new(conf)?;
+                plguins.insert(name, Arc::new(c));
+            },
         };
     }
 
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{boolean, string, PluginField};
 use super::{
     get_bool_conf, get_step_conf, get_str_conf, Error, Plugin, Result,
 };
@@ -33,6 +34,10 @@ pub struct Redirect {
     hash_value: String,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![string("prefix"), boolean("http_to_https")]
+}
+
 impl Redirect {
     pub fn new(params: &PluginConf) -> Result<Self> {
         debug!(params = params.to_string(), "new redirect plugin");
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{int, one_of, string, PluginField};
 use super::{
     get_hash_key, get_int_conf, get_step_conf, get_str_conf, Error, Plugin,
     Result,
@@ -36,6 +37,14 @@ pub struct RequestId {
     hash_value: String,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        one_of("algorithm", &["uuid", "nanoid"]),
+        int("size"),
+        string("header_name"),
+    ]
+}
+
 impl TryFrom<&PluginConf> for RequestId {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
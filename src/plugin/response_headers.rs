@@ -11,6 +11,7 @@
 // WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
 // See the License for the specific language governing permissions and
 // limitations under the License.
+use super::schema::{strings, PluginField};
 use super::{
     get_hash_key, get_step_conf, get_str_slice_conf, Error, Plugin, Result,
 };
@@ -32,6 +33,14 @@ pub struct ResponseHeaders {
     hash_value: String,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        strings("add_headers"),
+        strings("set_headers"),
+        strings("remove_headers"),
+    ]
+}
+
 impl TryFrom<&PluginConf> for ResponseHeaders {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
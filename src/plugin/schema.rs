@@ -0,0 +1,465 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::{
+    accept_encoding, admin, basic_auth, cache, challenge, coalesce,
+    combined_auth, compression, cors, csrf, directory, geoip, ip_restriction,
+    jwt, key_auth, limit, mock, ping, redirect, referer_restriction,
+    request_id, response_headers, stats, ua_restriction, waf, Error, Result,
+};
+use crate::config::{PluginCategory, PluginConf};
+use bytesize::ByteSize;
+use humantime::parse_duration;
+use serde_json::{json, Map, Value};
+use std::str::FromStr;
+use strum::IntoEnumIterator;
+
+/// The value type of plugin field.
+#[derive(Debug, Clone, PartialEq)]
+pub enum FieldKind {
+    String,
+    Integer,
+    Boolean,
+    StringArray,
+    // humantime duration, e.g. `30s`, `1m`
+    Duration,
+    // byte size, e.g. `1MB`
+    ByteSize,
+    // one of the values, empty string is allowed as unset
+    Enum(&'static [&'static str]),
+    // array of tables with the fields
+    TableArray(Vec<PluginField>),
+}
+
+/// The typed field of plugin config.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PluginField {
+    pub name: &'static str,
+    pub kind: FieldKind,
+}
+
+pub(super) fn field(name: &'static str, kind: FieldKind) -> PluginField {
+    PluginField { name, kind }
+}
+pub(super) fn string(name: &'static str) -> PluginField {
+    field(name, FieldKind::String)
+}
+pub(super) fn int(name: &'static str) -> PluginField {
+    field(name, FieldKind::Integer)
+}
+pub(super) fn boolean(name: &'static str) -> PluginField {
+    field(name, FieldKind::Boolean)
+}
+pub(super) fn strings(name: &'static str) -> PluginField {
+    field(name, FieldKind::StringArray)
+}
+pub(super) fn duration(name: &'static str) -> PluginField {
+    field(name, FieldKind::Duration)
+}
+pub(super) fn size(name: &'static str) -> PluginField {
+    field(name, FieldKind::ByteSize)
+}
+pub(super) fn one_of(
+    name: &'static str,
+    values: &'static [&'static str],
+) -> PluginField {
+    field(name, FieldKind::Enum(values))
+}
+
+pub(super) const RESTRICTION_TYPES: &[&str] = &["allow", "deny"];
+
+/// Get the fields of plugin category, the common fields
+/// `category`, `step` and `remark` are not included.
+/// The fields are declared next to the parsing of each plugin.
+pub fn get_plugin_fields(category: &PluginCategory) -> Vec<PluginField> {
+    match category {
+        PluginCategory::AcceptEncoding => accept_encoding::get_fields(),
+        PluginCategory::Admin => admin::get_fields(),
+        PluginCategory::BasicAuth => basic_auth::get_fields(),
+        PluginCategory::Cache => cache::get_fields(),
+        PluginCategory::Challenge => challenge::get_fields(),
+        PluginCategory::Coalesce => coalesce::get_fields(),
+        PluginCategory::CombinedAuth => combined_auth::get_fields(),
+        PluginCategory::Compression => compression::get_fields(),
+        PluginCategory::Cors => cors::get_fields(),
+        PluginCategory::Csrf => csrf::get_fields(),
+        PluginCategory::Directory => directory::get_fields(),
+        PluginCategory::Geoip => geoip::get_fields(),
+        PluginCategory::IpRestriction => ip_restriction::get_fields(),
+        PluginCategory::Jwt => jwt::get_fields(),
+        PluginCategory::KeyAuth => key_auth::get_fields(),
+        PluginCategory::Limit => limit::get_fields(),
+        PluginCategory::Mock => mock::get_fields(),
+        PluginCategory::Ping => ping::get_fields(),
+        PluginCategory::Redirect => redirect::get_fields(),
+        PluginCategory::RefererRestriction => referer_restriction::get_fields(),
+        PluginCategory::RequestId => request_id::get_fields(),
+        PluginCategory::ResponseHeaders => response_headers::get_fields(),
+        PluginCategory::Stats => stats::get_fields(),
+        PluginCategory::UaRestriction => ua_restriction::get_fields(),
+        PluginCategory::Waf => waf::get_fields(),
+    }
+}
+
+/// Get the edit distance of two strings.
+fn get_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let cost = if ca == *cb { 0 } else { 1 };
+            current.push(
+                (prev[j] + cost).min(prev[j + 1] + 1).min(current[j] + 1),
+            );
+        }
+        prev = current;
+    }
+    prev[b.len()]
+}
+
+fn invalid(path: String, message: String) -> Error {
+    Error::Field { path, message }
+}
+
+fn validate_value(
+    path: &str,
+    kind: &FieldKind,
+    value: &toml::Value,
+    unknown_fields: &mut Vec<String>,
+) -> Result<()> {
+    let expected = match kind {
+        FieldKind::String => value.is_str().then_some(()).ok_or("string"),
+        FieldKind::Integer => value.is_integer().then_some(()).ok_or("integer"),
+        FieldKind::Boolean => value.is_bool().then_some(()).ok_or("boolean"),
+        FieldKind::StringArray => value
+            .as_array()
+            .filter(|items| items.iter().all(|item| item.is_str()))
+            .map(|_| ())
+            .ok_or("array of string"),
+        FieldKind::Duration | FieldKind::ByteSize | FieldKind::Enum(_) => {
+            let Some(value) = value.as_str() else {
+                return Err(invalid(
+                    path.to_string(),
+                    "expected string".to_string(),
+                ));
+            };
+            if value.is_empty() {
+                return Ok(());
+            }
+            let message = match kind {
+                FieldKind::Duration => {
+                    parse_duration(value).err().map(|e| e.to_string())
+                },
+                FieldKind::ByteSize => ByteSize::from_str(value).err(),
+                FieldKind::Enum(values) => (!values.contains(&value))
+                    .then(|| format!("expected one of {}", values.join(", "))),
+                _ => None,
+            };
+            return match message {
+                Some(message) => Err(invalid(path.to_string(), message)),
+                None => Ok(()),
+            };
+        },
+        FieldKind::TableArray(fields) => {
+            let Some(items) = value.as_array() else {
+                return Err(invalid(
+                    path.to_string(),
+                    "expected array of table".to_string(),
+                ));
+            };
+            for (index, item) in items.iter().enumerate() {
+                let path = format!("{path}[{index}]");
+                let Some(table) = item.as_table() else {
+                    return Err(invalid(path, "expected table".to_string()));
+                };
+                validate_table(&path, fields, table, unknown_fields)?;
+            }
+            return Ok(());
+        },
+    };
+    expected.map_err(|expected| {
+        invalid(path.to_string(), format!("expected {expected}"))
+    })
+}
+
+fn validate_table(
+    path: &str,
+    fields: &[PluginField],
+    table: &toml::Table,
+    unknown_fields: &mut Vec<String>,
+) -> Result<()> {
+    for (key, value) in table.iter() {
+        let path = format!("{path}.{key}");
+        let Some(field) = fields.iter().find(|item| item.name == key) else {
+            let mut message = format!("{path}: unknown field");
+            if let Some(name) = fields
+                .iter()
+                .map(|item| (get_distance(key, item.name), item.name))
+                .filter(|(distance, _)| *distance <= 2)
+                .min()
+                .map(|(_, name)| name)
+            {
+                message = format!("{message}, did you mean `{name}`?");
+            }
+            unknown_fields.push(message);
+            continue;
+        };
+        validate_value(&path, &field.kind, value, unknown_fields)?;
+    }
+    Ok(())
+}
+
+const PLUGIN_STEPS: &[&str] =
+    &["early_request", "request", "proxy_upstream", "response"];
+
+fn get_common_fields() -> Vec<PluginField> {
+    vec![
+        string("category"),
+        one_of("step", PLUGIN_STEPS),
+        string("remark"),
+    ]
+}
+
+/// Validate the plugin config by the schema of its category,
+/// the path of invalid field is returned, e.g. `plugins.gzip.gzip_level`.
+/// The unknown fields are not treated as error for compatibility,
+/// they are returned as warnings.
+pub fn validate_plugin_conf(
+    name: &str,
+    conf: &PluginConf,
+) -> Result<Vec<String>> {
+    let path = format!("plugins.{name}");
+    let category = conf.get("category").and_then(|value| value.as_str());
+    let Some(category) = category else {
+        return Err(invalid(
+            format!("{path}.category"),
+            "category can not be empty".to_string(),
+        ));
+    };
+    let Ok(category) = PluginCategory::from_str(category) else {
+        return Err(invalid(
+            format!("{path}.category"),
+            format!("unknown category `{category}`"),
+        ));
+    };
+    let mut fields = get_common_fields();
+    fields.extend(get_plugin_fields(&category));
+    let mut unknown_fields = vec![];
+    validate_table(&path, &fields, conf, &mut unknown_fields)?;
+    Ok(unknown_fields)
+}
+
+fn get_field_schema(kind: &FieldKind) -> Value {
+    match kind {
+        FieldKind::String => json!({ "type": "string" }),
+        FieldKind::Integer => json!({ "type": "integer" }),
+        FieldKind::Boolean => json!({ "type": "boolean" }),
+        FieldKind::StringArray => {
+            json!({ "type": "array", "items": { "type": "string" } })
+        },
+        FieldKind::Duration => json!({
+            "type": "string",
+            "description": "Duration, e.g. 30s, 1m",
+        }),
+        FieldKind::ByteSize => json!({
+            "type": "string",
+            "description": "Byte size, e.g. 100KB, 1MB",
+        }),
+        FieldKind::Enum(values) => {
+            let mut values = values.to_vec();
+            values.push("");
+            json!({ "type": "string", "enum": values })
+        },
+        FieldKind::TableArray(fields) => json!({
+            "type": "array",
+            "items": get_table_schema(fields),
+        }),
+    }
+}
+
+fn get_table_schema(fields: &[PluginField]) -> Value {
+    let mut properties = Map::new();
+    for field in fields.iter() {
+        properties
+            .insert(field.name.to_string(), get_field_schema(&field.kind));
+    }
+    json!({
+        "type": "object",
+        "properties": properties,
+        "additionalProperties": false,
+    })
+}
+
+/// Get the json schema of plugin config, every category is defined
+/// in `$defs` and the plugin should match one of them.
+pub fn get_plugin_json_schema() -> Value {
+    let mut defs = Map::new();
+    let mut refs = vec![];
+    for category in PluginCategory::iter() {
+        let name = category.to_string();
+        let mut fields = get_common_fields();
+        fields.extend(get_plugin_fields(&category));
+        let mut schema = get_table_schema(&fields);
+        schema["properties"]["category"] = json!({ "const": name });
+        schema["required"] = json!(["category"]);
+        refs.push(json!({ "$ref": format!("#/$defs/{name}") }));
+        defs.insert(name, schema);
+    }
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Pingap plugin config",
+        "type": "object",
+        "properties": {
+            "plugins": {
+                "type": "object",
+                "additionalProperties": { "oneOf": refs },
+            },
+        },
+        "$defs": defs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        get_plugin_fields, get_plugin_json_schema, validate_plugin_conf,
+        PLUGIN_STEPS,
+    };
+    use crate::config::{PluginCategory, PluginConf, PluginStep};
+    use pretty_assertions::assert_eq;
+    use strum::IntoEnumIterator;
+
+    fn validate(data: &str) -> String {
+        let conf = toml::from_str::<PluginConf>(data).unwrap();
+        validate_plugin_conf("test", &conf)
+            .map_or_else(|e| e.to_string(), |warnings| warnings.join("; "))
+    }
+
+    #[test]
+    fn test_validate_plugin_conf() {
+        assert_eq!(
+            "",
+            validate(
+                r#"
+category = "compression"
+step = "early_request"
+gzip_level = 6
+remark = "compression"
+"#
+            )
+        );
+        // the unknown field is a warning
+        assert_eq!(
+            "plugins.test.gzip_levle: unknown field, did you mean `gzip_level`?",
+            validate(
+                r#"
+category = "compression"
+gzip_levle = 6
+"#
+            )
+        );
+        assert_eq!(
+            "plugins.test.gzip_level: expected integer",
+            validate(
+                r#"
+category = "compression"
+gzip_level = "6"
+"#
+            )
+        );
+        assert_eq!(
+            "plugins.test.step: expected one of early_request, request, proxy_upstream, response",
+            validate(
+                r#"
+category = "compression"
+step = "upstream"
+"#
+            )
+        );
+        assert_eq!(
+            "plugins.test.category: unknown category `gzip`",
+            validate(r#"category = "gzip""#)
+        );
+        assert_eq!(
+            "",
+            validate(
+                r#"
+category = "cache"
+lock = ""
+max_ttl = "1h"
+max_file_size = "1MB"
+"#
+            )
+        );
+        assert_eq!(
+            "plugins.test.lock: expected number at 0",
+            validate(
+                r#"
+category = "cache"
+lock = "a"
+"#
+            )
+        );
+        assert_eq!(
+            "plugins.test.authorizations[1].ip_lsit: unknown field, did you mean `ip_list`?",
+            validate(
+                r#"
+category = "combined_auth"
+[[authorizations]]
+app_id = "pingap"
+secret = "123123"
+[[authorizations]]
+app_id = "other"
+ip_lsit = ["127.0.0.1"]
+"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_plugin_fields() {
+        for category in PluginCategory::iter() {
+            assert_eq!(
+                false,
+                get_plugin_fields(&category).is_empty(),
+                "{category} has no field"
+            );
+        }
+    }
+
+    #[test]
+    fn test_plugin_steps() {
+        for step in PLUGIN_STEPS.iter() {
+            assert_eq!(
+                step.to_string(),
+                step.parse::<PluginStep>().unwrap().to_string()
+            );
+        }
+    }
+
+    #[test]
+    fn test_plugin_json_schema() {
+        let schema = get_plugin_json_schema();
+        assert_eq!(
+            "integer",
+            schema["$defs"]["compression"]["properties"]["gzip_level"]["type"]
+        );
+        assert_eq!(
+            "combined_auth",
+            schema["$defs"]["combined_auth"]["properties"]["category"]["const"]
+        );
+        assert_eq!(false, schema["$defs"]["limit"]["additionalProperties"]);
+    }
+}
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{string, PluginField};
 use super::{get_hash_key, get_step_conf, get_str_conf, Error, Plugin, Result};
 use crate::config::{PluginCategory, PluginConf, PluginStep};
 use crate::http_extra::HttpResponse;
@@ -56,6 +57,10 @@ pub struct Stats {
     hash_value: String,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![string("path")]
+}
+
 impl TryFrom<&PluginConf> for Stats {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::schema::{one_of, string, strings, PluginField, RESTRICTION_TYPES};
 use super::{
     get_hash_key, get_step_conf, get_str_conf, get_str_slice_conf, Error,
     Plugin, Result,
@@ -34,6 +35,14 @@ pub struct UaRestriction {
     hash_value: String,
 }
 
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        one_of("type", RESTRICTION_TYPES),
+        strings("ua_list"),
+        string("message"),
+    ]
+}
+
 impl TryFrom<&PluginConf> for UaRestriction {
     type Error = Error;
     fn try_from(value: &PluginConf) -> Result<Self> {
@@ -0,0 +1,733 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use super::schema::{
+    boolean, field, int, one_of, size, string, strings, FieldKind, PluginField,
+};
+use super::{
+    get_bool_conf, get_hash_key, get_int_conf, get_step_conf, get_str_conf,
+    Error, Plugin, Result,
+};
+use crate::config::{PluginCategory, PluginConf, PluginStep};
+use crate::http_extra::HttpResponse;
+use crate::state::State;
+use crate::util;
+use async_trait::async_trait;
+use bytes::{Bytes, BytesMut};
+use bytesize::ByteSize;
+use http::{HeaderName, StatusCode};
+use once_cell::sync::Lazy;
+use pingora::proxy::Session;
+use regex::{Regex, RegexSet};
+use serde::Deserialize;
+use std::borrow::Cow;
+use std::str::FromStr;
+use tracing::{debug, warn};
+
+// default max size of body to inspect
+const DEFAULT_MAX_BODY_SIZE: usize = 8 * 1024;
+const DEFAULT_SCORE: i64 = 5;
+const DEFAULT_THRESHOLD: i64 = 5;
+
+// The signatures of sql injection, they are similar to the fingerprints
+// of libinjection: the tokens for breaking out of a string or statement.
+static SQLI_SIGNATURES: Lazy<RegexSet> = Lazy::new(|| {
+    RegexSet::new([
+        r"(?i)\bunion\b[\s(]+(?:all\s+|distinct\s+)?select\b",
+        r#"(?i)['")]\s*\b(?:or|and|xor)\b\s*[('"]?\s*[\w'"]+\s*[)'"]?\s*(?:=|<>|!=|<|>|\blike\b|\bis\b)"#,
+        r#"(?i)['")]\s*(?:--|#|/\*)"#,
+        r"(?i);\s*(?:drop|delete|insert|update|truncate|alter|create|exec|shutdown)\b",
+        r"(?i)\b(?:sleep|benchmark|pg_sleep|extractvalue|updatexml|load_file)\s*\(",
+        r"(?i)\bwaitfor\s+delay\b",
+        r"(?i)\b(?:information_schema|sysobjects|syscolumns|xp_cmdshell|pg_catalog)\b",
+        r"(?i)\binto\s+(?:out|dump)file\b",
+    ])
+    .unwrap()
+});
+
+// The signatures of cross site scripting.
+static XSS_SIGNATURES: Lazy<RegexSet> = Lazy::new(|| {
+    RegexSet::new([
+        r"(?i)<\s*/?\s*script\b",
+        r"(?i)\b(?:java|vb)script\s*:",
+        r"(?i)<[^>]*\bon[a-z]{3,}\s*=",
+        r"(?i)<\s*(?:iframe|frame|object|embed|applet|meta|base|svg|math)\b",
+        r"(?i)\bsrcdoc\s*=",
+        r"(?i)\bdocument\s*\.\s*(?:cookie|domain|write)\b",
+        r"(?i)\b(?:eval|settimeout|setinterval)\s*\(",
+        r"(?i)\bdata\s*:\s*text/html\b",
+    ])
+    .unwrap()
+});
+
+// The built-in rules, the ids are the same as the OWASP core rule set.
+static BUILTIN_RULES: &str = r###"
+[[rules]]
+id = "913100"
+message = "Found user agent associated with security scanner"
+targets = ["header:user-agent"]
+pattern = "(?i)(?:nikto|sqlmap|nmap|masscan|acunetix|nessus|dirbuster|wpscan|zgrab)"
+
+[[rules]]
+id = "930100"
+message = "Path traversal attack"
+targets = ["path", "query"]
+pattern = '(?:^|[\\/])\.\.(?:[\\/]|$)'
+
+[[rules]]
+id = "930120"
+message = "OS file access attempt"
+targets = ["path", "query", "body"]
+pattern = '(?i)(?:/etc/(?:passwd|shadow|hosts)|/proc/self/|\bboot\.ini\b|\bwin\.ini\b)'
+
+[[rules]]
+id = "932100"
+message = "Remote command execution"
+targets = ["query", "body"]
+pattern = '(?i)(?:[;|`]|&&|\$\()\s*(?:cat|ls|id|whoami|uname|wget|curl|bash|sh|nc|ncat|python|perl|ping)\b'
+
+[[rules]]
+id = "941100"
+message = "XSS attack detected"
+targets = ["path", "query", "body", "header:referer"]
+operator = "xss"
+
+[[rules]]
+id = "942100"
+message = "SQL injection attack detected"
+targets = ["path", "query", "body", "header:cookie"]
+operator = "sqli"
+"###;
+
+#[derive(Debug, Clone, PartialEq)]
+enum WafTarget {
+    Path,
+    Query,
+    // all request headers
+    Headers,
+    Header(HeaderName),
+    Body,
+}
+
+impl FromStr for WafTarget {
+    type Err = Error;
+    fn from_str(value: &str) -> Result<Self> {
+        let target = match value.to_lowercase().as_str() {
+            "path" => WafTarget::Path,
+            "query" => WafTarget::Query,
+            "headers" => WafTarget::Headers,
+            "body" => WafTarget::Body,
+            value => {
+                let name = value.strip_prefix("header:").unwrap_or_default();
+                let name = HeaderName::from_str(name.trim()).map_err(|_| {
+                    Error::Invalid {
+                        category: PluginCategory::Waf.to_string(),
+                        message: format!("target({value}) is invalid"),
+                    }
+                })?;
+                WafTarget::Header(name)
+            },
+        };
+        Ok(target)
+    }
+}
+
+enum WafMatcher {
+    Regex(Regex),
+    Sqli,
+    Xss,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct WafRuleConf {
+    id: String,
+    targets: Vec<String>,
+    operator: Option<String>,
+    pattern: Option<String>,
+    score: Option<i64>,
+    message: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WafRuleSet {
+    #[serde(default)]
+    rules: Vec<WafRuleConf>,
+}
+
+struct WafRule {
+    id: String,
+    targets: Vec<WafTarget>,
+    matcher: WafMatcher,
+    score: i64,
+    message: String,
+}
+
+impl TryFrom<&WafRuleConf> for WafRule {
+    type Error = Error;
+    fn try_from(value: &WafRuleConf) -> Result<Self> {
+        let category = PluginCategory::Waf.to_string();
+        let operator = value.operator.clone().unwrap_or("regex".to_string());
+        let matcher = match operator.as_str() {
+            "sqli" => WafMatcher::Sqli,
+            "xss" => WafMatcher::Xss,
+            "regex" => {
+                let pattern = value.pattern.clone().unwrap_or_default();
+                if pattern.is_empty() {
+                    return Err(Error::Invalid {
+                        category,
+                        message: format!(
+                            "pattern of rule({}) can not be empty",
+                            value.id
+                        ),
+                    });
+                }
+                let reg = Regex::new(&pattern).map_err(|e| Error::Invalid {
+                    category: "regex".to_string(),
+                    message: e.to_string(),
+                })?;
+                WafMatcher::Regex(reg)
+            },
+            _ => {
+                return Err(Error::Invalid {
+                    category,
+                    message: format!("operator({operator}) is not supported"),
+                });
+            },
+        };
+        let mut targets = vec![];
+        for item in value.targets.iter() {
+            targets.push(WafTarget::from_str(item)?);
+        }
+        if targets.is_empty() {
+            return Err(Error::Invalid {
+                category,
+                message: format!(
+                    "targets of rule({}) can not be empty",
+                    value.id
+                ),
+            });
+        }
+        Ok(Self {
+            id: value.id.clone(),
+            targets,
+            matcher,
+            score: value.score.unwrap_or(DEFAULT_SCORE),
+            message: value.message.clone().unwrap_or_default(),
+        })
+    }
+}
+
+impl WafRule {
+    fn is_match(&self, value: &str) -> bool {
+        if value.is_empty() {
+            return false;
+        }
+        match &self.matcher {
+            WafMatcher::Regex(reg) => reg.is_match(value),
+            WafMatcher::Sqli => SQLI_SIGNATURES.is_match(value),
+            WafMatcher::Xss => XSS_SIGNATURES.is_match(value),
+        }
+    }
+}
+
+fn parse_rules(data: &str) -> Result<Vec<WafRule>> {
+    let rule_set: WafRuleSet =
+        toml::from_str(data).map_err(|e| Error::Invalid {
+            category: PluginCategory::Waf.to_string(),
+            message: e.to_string(),
+        })?;
+    let mut rules = vec![];
+    for item in rule_set.rules.iter() {
+        rules.push(WafRule::try_from(item)?);
+    }
+    Ok(rules)
+}
+
+// Decode the percent-encoded value, the raw value is returned
+// if it fails to decode.
+fn decode_value(value: &str) -> Cow<'_, str> {
+    if !value.contains('%') && !value.contains('+') {
+        return Cow::Borrowed(value);
+    }
+    let value = value.replace('+', " ");
+    match urlencoding::decode(&value) {
+        Ok(decoded) => Cow::Owned(decoded.into_owned()),
+        Err(_) => Cow::Owned(value),
+    }
+}
+
+/// The web application firewall inspects path, query, headers
+/// and the beginning of body against the rules, each matched rule adds
+/// its score, the request is blocked if the total score reaches
+/// the threshold, or it is only recorded in detect mode.
+pub struct Waf {
+    plugin_step: PluginStep,
+    rules: Vec<WafRule>,
+    detect_only: bool,
+    threshold: i64,
+    max_body_size: usize,
+    // whether any rule inspects the body
+    inspect_body: bool,
+    forbidden_resp: HttpResponse,
+    hash_value: String,
+}
+
+pub(super) fn get_fields() -> Vec<PluginField> {
+    vec![
+        one_of("mode", &["block", "detect"]),
+        int("threshold"),
+        size("max_body_size"),
+        boolean("disable_builtin_rules"),
+        string("rule_file"),
+        field(
+            "rules",
+            FieldKind::TableArray(vec![
+                string("id"),
+                strings("targets"),
+                string("operator"),
+                string("pattern"),
+                int("score"),
+                string("message"),
+            ]),
+        ),
+        string("message"),
+    ]
+}
+
+impl TryFrom<&PluginConf> for Waf {
+    type Error = Error;
+    fn try_from(value: &PluginConf) -> Result<Self> {
+        let hash_value = get_hash_key(value);
+        let step = get_step_conf(value);
+        let category = PluginCategory::Waf.to_string();
+
+        let mut rules = if get_bool_conf(value, "disable_builtin_rules") {
+            vec![]
+        } else {
+            parse_rules(BUILTIN_RULES)?
+        };
+        let rule_file = get_str_conf(value, "rule_file");
+        if !rule_file.is_empty() {
+            let data = std::fs::read_to_string(util::resolve_path(&rule_file))
+                .map_err(|e| Error::Invalid {
+                    category: category.clone(),
+                    message: format!("read rule file({rule_file}) fail, {e}"),
+                })?;
+            rules.extend(parse_rules(&data)?);
+        }
+        if let Some(items) = value.get("rules") {
+            let items: Vec<WafRuleConf> =
+                items.clone().try_into().map_err(|e: toml::de::Error| {
+                    Error::Invalid {
+                        category: category.clone(),
+                        message: e.to_string(),
+                    }
+                })?;
+            for item in items.iter() {
+                rules.push(WafRule::try_from(item)?);
+            }
+        }
+        let max_body_size = if value.contains_key("max_body_size") {
+            ByteSize::from_str(&get_str_conf(value, "max_body_size"))
+                .map_err(|e| Error::Invalid {
+                    category: category.clone(),
+                    message: e.to_string(),
+                })?
+                .as_u64() as usize
+        } else {
+            DEFAULT_MAX_BODY_SIZE
+        };
+        let inspect_body = max_body_size > 0
+            && rules
+                .iter()
+                .any(|rule| rule.targets.contains(&WafTarget::Body));
+        let threshold = get_int_conf(value, "threshold");
+
+        let mut message = get_str_conf(value, "message");
+        if message.is_empty() {
+            message = "Request is blocked by waf".to_string();
+        }
+        let params = Self {
+            hash_value,
+            plugin_step: step,
+            rules,
+            detect_only: get_str_conf(value, "mode") == "detect",
+            threshold: if threshold > 0 {
+                threshold
+            } else {
+                DEFAULT_THRESHOLD
+            },
+            max_body_size,
+            inspect_body,
+            forbidden_resp: HttpResponse {
+                status: StatusCode::FORBIDDEN,
+                body: Bytes::from(message),
+                ..Default::default()
+            },
+        };
+        if ![PluginStep::Request, PluginStep::ProxyUpstream]
+            .contains(&params.plugin_step)
+        {
+            return Err(Error::Invalid {
+                category,
+                message: "Waf plugin should be executed at request or proxy upstream step".to_string(),
+            });
+        }
+
+        Ok(params)
+    }
+}
+
+impl Waf {
+    pub fn new(params: &PluginConf) -> Result<Self> {
+        debug!(params = params.to_string(), "new waf plugin");
+        Self::try_from(params)
+    }
+    // Get the values of target from request header.
+    fn get_values<'a>(
+        &self,
+        target: &WafTarget,
+        session: &'a Session,
+    ) -> Vec<Cow<'a, str>> {
+        let header = session.req_header();
+        match target {
+            WafTarget::Path => vec![decode_value(header.uri.path())],
+            WafTarget::Query => header
+                .uri
+                .query()
+                .map(|query| vec![decode_value(query)])
+                .unwrap_or_default(),
+            WafTarget::Headers => header
+                .headers
+                .values()
+                .filter_map(|value| value.to_str().ok())
+                .map(Cow::Borrowed)
+                .collect(),
+            WafTarget::Header(name) => header
+                .headers
+                .get_all(name)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .map(Cow::Borrowed)
+                .collect(),
+            WafTarget::Body => vec![],
+        }
+    }
+    // Record the matched rule, return true if the request should be blocked.
+    fn add_matched(&self, rule: &WafRule, ctx: &mut State) -> bool {
+        let matched = ctx.waf_matched.get_or_insert_with(Vec::new);
+        if matched.contains(&rule.id) {
+            return false;
+        }
+        warn!(
+            id = rule.id,
+            message = rule.message,
+            score = rule.score,
+            detect_only = self.detect_only,
+            "waf rule is matched"
+        );
+        matched.push(rule.id.clone());
+        ctx.waf_score += rule.score;
+        if !self.detect_only && ctx.waf_score >= self.threshold {
+            ctx.waf_blocked = true;
+        }
+        ctx.waf_blocked
+    }
+    fn inspect_body(&self, body: &[u8], ctx: &mut State) -> bool {
+        let size = body.len().min(self.max_body_size);
+        let data = String::from_utf8_lossy(&body[..size]);
+        let decoded = decode_value(&data);
+        for rule in self.rules.iter() {
+            if !rule.targets.contains(&WafTarget::Body) {
+                continue;
+            }
+            if (rule.is_match(&data) || rule.is_match(&decoded))
+                && self.add_matched(rule, ctx)
+            {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[async_trait]
+impl Plugin for Waf {
+    #[inline]
+    fn hash_key(&self) -> String {
+        self.hash_value.clone()
+    }
+    #[inline]
+    async fn handle_request(
+        &self,
+        step: PluginStep,
+        session: &mut Session,
+        ctx: &mut State,
+    ) -> pingora::Result<Option<HttpResponse>> {
+        if step != self.plugin_step {
+            return Ok(None);
+        }
+        for rule in self.rules.iter() {
+            let matched = rule.targets.iter().any(|target| {
+                self.get_values(target, session)
+                    .iter()
+                    .any(|value| rule.is_match(value))
+            });
+            if matched && self.add_matched(rule, ctx) {
+                return Ok(Some(self.forbidden_resp.clone()));
+            }
+        }
+        Ok(None)
+    }
+    async fn handle_request_body(
+        &self,
+        _session: &mut Session,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+        ctx: &mut State,
+    ) -> pingora::Result<()> {
+        if !self.inspect_body || ctx.waf_body_inspected {
+            return Ok(());
+        }
+        let buf = ctx.waf_body.get_or_insert_with(BytesMut::new);
+        if let Some(data) = body.take() {
+            buf.extend(data);
+        }
+        // hold the body until it's enough to inspect,
+        // the empty data will not be sent to upstream
+        if buf.len() < self.max_body_size && !end_of_stream {
+            *body = Some(Bytes::new());
+            return Ok(());
+        }
+        let data = ctx.waf_body.take().unwrap_or_default().freeze();
+        ctx.waf_body_inspected = true;
+        if self.inspect_body(&data, ctx) {
+            return Err(util::new_internal_error(
+                self.forbidden_resp.status.as_u16(),
+                std::string::String::from_utf8_lossy(&self.forbidden_resp.body)
+                    .to_string(),
+            ));
+        }
+        if !data.is_empty() {
+            *body = Some(data);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_rules, Waf, WafTarget, BUILTIN_RULES};
+    use crate::state::State;
+    use crate::{config::PluginConf, config::PluginStep, plugin::Plugin};
+    use bytes::Bytes;
+    use http::StatusCode;
+    use pingora::proxy::Session;
+    use pretty_assertions::assert_eq;
+    use tokio_test::io::Builder;
+
+    async fn new_session(path: &str, headers: &[&str]) -> Session {
+        let headers = headers.join("\r\n");
+        let input_header = format!("GET {path} HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        session
+    }
+
+    #[test]
+    fn test_waf_params() {
+        let rules = parse_rules(BUILTIN_RULES).unwrap();
+        assert_eq!(6, rules.len());
+
+        let params = Waf::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+mode = "detect"
+threshold = 10
+max_body_size = "1kb"
+disable_builtin_rules = true
+rules = [
+    { id = "1001", targets = ["path", "header:x-debug"], pattern = "^/admin", score = 3 },
+]
+"###,
+            )
+            .unwrap(),
+        )
+        .unwrap();
+        assert_eq!("request", params.plugin_step.to_string());
+        assert_eq!(true, params.detect_only);
+        assert_eq!(10, params.threshold);
+        assert_eq!(1000, params.max_body_size);
+        assert_eq!(false, params.inspect_body);
+        assert_eq!(1, params.rules.len());
+        assert_eq!(
+            vec![
+                WafTarget::Path,
+                WafTarget::Header("x-debug".parse().unwrap())
+            ],
+            params.rules[0].targets
+        );
+
+        let result = Waf::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+rules = [
+    { id = "1001", targets = ["uri"], pattern = "^/admin" },
+]
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin waf invalid, message: target(uri) is invalid",
+            result.err().unwrap().to_string()
+        );
+
+        let result = Waf::try_from(
+            &toml::from_str::<PluginConf>(
+                r###"
+step = "response"
+"###,
+            )
+            .unwrap(),
+        );
+        assert_eq!(
+            "Plugin waf invalid, message: Waf plugin should be executed at request or proxy upstream step",
+            result.err().unwrap().to_string()
+        );
+    }
+
+    #[test]
+    fn test_waf_signatures() {
+        let rules = parse_rules(BUILTIN_RULES).unwrap();
+        let sqli = rules.iter().find(|item| item.id == "942100").unwrap();
+        for value in [
+            "id=1 UNION SELECT password FROM users",
+            "name=' or '1'='1",
+            "name=admin'--",
+            "id=1; DROP TABLE users",
+            "id=1 and sleep(5)",
+            "id=1 from information_schema.tables",
+        ] {
+            assert_eq!(true, sqli.is_match(value), "{value}");
+        }
+        for value in ["name=pingap", "q=select a union to join", "o'reilly"] {
+            assert_eq!(false, sqli.is_match(value), "{value}");
+        }
+
+        let xss = rules.iter().find(|item| item.id == "941100").unwrap();
+        for value in [
+            "<script>alert(1)</script>",
+            "<img src=x onerror=alert(1)>",
+            "javascript:alert(1)",
+            "<svg/onload=alert(1)>",
+        ] {
+            assert_eq!(true, xss.is_match(value), "{value}");
+        }
+        for value in ["a < b", "description=javascript is good"] {
+            assert_eq!(false, xss.is_match(value), "{value}");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_waf() {
+        let waf = Waf::new(&toml::from_str::<PluginConf>("").unwrap()).unwrap();
+
+        let mut ctx = State::default();
+        let mut session =
+            new_session("/users?name=pingap", &["User-Agent: pingap/1.0"])
+                .await;
+        let result = waf
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+        assert_eq!(true, ctx.waf_matched.is_none());
+
+        let mut ctx = State::default();
+        let mut session = new_session(
+            "/users?name=%27%20or%20%271%27%3D%271",
+            &["User-Agent: pingap/1.0"],
+        )
+        .await;
+        let result = waf
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(StatusCode::FORBIDDEN, result.unwrap().status);
+        assert_eq!(vec!["942100".to_string()], ctx.waf_matched.unwrap());
+        assert_eq!(5, ctx.waf_score);
+        assert_eq!(true, ctx.waf_blocked);
+
+        let mut ctx = State::default();
+        let mut session = new_session(
+            "/static/../../etc/passwd",
+            &["User-Agent: sqlmap/1.0"],
+        )
+        .await;
+        let detect = Waf::new(
+            &toml::from_str::<PluginConf>(r#"mode = "detect""#).unwrap(),
+        )
+        .unwrap();
+        let result = detect
+            .handle_request(PluginStep::Request, &mut session, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(true, result.is_none());
+        assert_eq!(
+            vec![
+                "913100".to_string(),
+                "930100".to_string(),
+                "930120".to_string()
+            ],
+            ctx.waf_matched.unwrap()
+        );
+        assert_eq!(15, ctx.waf_score);
+        assert_eq!(false, ctx.waf_blocked);
+    }
+
+    #[tokio::test]
+    async fn test_waf_request_body() {
+        let waf = Waf::new(
+            &toml::from_str::<PluginConf>(r#"max_body_size = "32b""#).unwrap(),
+        )
+        .unwrap();
+        let mut session = new_session("/users", &[]).await;
+
+        // the body is held until it's enough to inspect
+        let mut ctx = State::default();
+        let mut body = Some(Bytes::from_static(b"name=pingap&"));
+        waf.handle_request_body(&mut session, &mut body, false, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(Some(Bytes::new()), body);
+        let mut body = Some(Bytes::from_static(b"remark=hello"));
+        waf.handle_request_body(&mut session, &mut body, true, &mut ctx)
+            .await
+            .unwrap();
+        assert_eq!(Some(Bytes::from_static(b"name=pingap&remark=hello")), body);
+        assert_eq!(true, ctx.waf_body_inspected);
+
+        let mut ctx = State::default();
+        let mut body = Some(Bytes::from_static(
+            b"name=%3Cscript%3Ealert(1)%3C%2Fscript%3E&remark=hello",
+        ));
+        let result = waf
+            .handle_request_body(&mut session, &mut body, false, &mut ctx)
+            .await;
+        assert_eq!(true, result.is_err());
+        assert_eq!(vec!["941100".to_string()], ctx.waf_matched.unwrap());
+    }
+}
@@ -12,8 +12,11 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::tls_ticket::{set_ticket_key_seed, TicketKeySeed};
 use crate::acme::{
-    get_certificate_info, get_lets_encrypt_certificate, CertificateInfo,
+    get_certificate_info, get_fingerprint, get_internal_ca_certificate,
+    get_internal_ca_pem, get_lets_encrypt_certificate, get_ocsp_response,
+    CertificateInfo,
 };
 use crate::config::CertificateConf;
 use crate::{util, webhook};
@@ -21,10 +24,12 @@ use ahash::AHashMap;
 use arc_swap::ArcSwap;
 use async_trait::async_trait;
 use once_cell::sync::Lazy;
+use openssl::ex_data::Index;
 use pingora::listeners::tls::TlsSettings;
+use pingora::tls::error::ErrorStack;
 use pingora::tls::ext;
 use pingora::tls::pkey::{PKey, Private};
-use pingora::tls::ssl::{NameType, SslRef};
+use pingora::tls::ssl::{NameType, Ssl, SslRef};
 use pingora::tls::x509::X509;
 use snafu::Snafu;
 use std::collections::HashMap;
@@ -69,6 +74,7 @@ static R11_CERTIFICATE: Lazy<Option<X509>> =
     Lazy::new(|| parse_chain_certificate(R11));
 
 static LETS_ENCRYPT: &str = "lets_encrypt";
+static INTERNAL_CA: &str = "internal_ca";
 
 fn parse_certificate(
     certificate_config: &CertificateConf,
@@ -84,6 +90,13 @@ fn parse_certificate(
                 message: e.to_string(),
             })?;
             (cert.get_cert(), cert.get_key(), LETS_ENCRYPT)
+        } else if certificate_config.is_internal_ca() {
+            let cert = get_internal_ca_certificate(certificate_config)
+                .map_err(|e| Error::Invalid {
+                    category: "get_internal_ca_certificate".to_string(),
+                    message: e.to_string(),
+                })?;
+            (cert.get_cert(), cert.get_key(), INTERNAL_CA)
         } else {
             (
                 util::convert_certificate_bytes(&certificate_config.tls_cert)
@@ -120,6 +133,10 @@ fn parse_certificate(
             "R11" => R11_CERTIFICATE.clone(),
             _ => None,
         }
+    } else if category == INTERNAL_CA {
+        get_internal_ca_pem(certificate_config)
+            .ok()
+            .and_then(|value| X509::from_pem(value.as_bytes()).ok())
     } else {
         None
     };
@@ -142,6 +159,7 @@ fn parse_certificate(
     })?;
     Ok(DynamicCertificate {
         hash_key,
+        fingerprint: get_fingerprint(&cert).into(),
         chain_certificate,
         domains,
         certificate: Some((cert, key)),
@@ -224,6 +242,26 @@ pub fn get_certificate_info_list() -> Vec<(String, CertificateInfo)> {
     infos
 }
 
+/// Get the certificate and its issuer list, which is used for ocsp stapling
+pub fn get_certificate_issuer_list() -> Vec<(String, X509, X509)> {
+    let mut hash_keys = vec![];
+    let mut certificates = vec![];
+    for (name, cert) in DYNAMIC_CERTIFICATE_MAP.load().iter() {
+        // the same certificate may be used by multiple domains
+        if hash_keys.contains(&cert.hash_key) {
+            continue;
+        }
+        let (Some((x509, _)), Some(issuer)) =
+            (&cert.certificate, &cert.chain_certificate)
+        else {
+            continue;
+        };
+        hash_keys.push(cert.hash_key.clone());
+        certificates.push((name.to_string(), x509.clone(), issuer.clone()));
+    }
+    certificates
+}
+
 #[derive(Debug, Clone, Default)]
 pub struct DynamicCertificate {
     chain_certificate: Option<X509>,
@@ -231,8 +269,14 @@ pub struct DynamicCertificate {
     domains: Vec<String>,
     info: Option<CertificateInfo>,
     hash_key: String,
+    // the fingerprint of certificate, it's the key of ocsp staple
+    fingerprint: Arc<str>,
 }
 
+// the fingerprint of selected certificate, it's used by ocsp status callback
+static FINGERPRINT_INDEX: Lazy<Option<Index<Ssl, Arc<str>>>> =
+    Lazy::new(|| Ssl::new_ex_index().ok());
+
 pub struct TlsSettingParams {
     pub server_name: String,
     pub enabled_h2: bool,
@@ -240,6 +284,7 @@ pub struct TlsSettingParams {
     pub ciphersuites: Option<String>,
     pub tls_min_version: Option<String>,
     pub tls_max_version: Option<String>,
+    pub ticket_key_seed: Option<TicketKeySeed>,
 }
 
 impl DynamicCertificate {
@@ -277,6 +322,20 @@ impl DynamicCertificate {
                 error!(error = e.to_string(), name, "set ciphersuites fail");
             }
         }
+        // share session ticket keys between processes
+        if let Some(seed) = &params.ticket_key_seed {
+            if !set_ticket_key_seed(&mut tls_settings, seed.clone()) {
+                error!(
+                    name,
+                    version = openssl::version::version(),
+                    "set session ticket key callback fail, only openssl 1.1.1 to 3.x is supported"
+                );
+            }
+        }
+        // staple the ocsp response of selected certificate
+        if let Err(e) = tls_settings.set_status_callback(ocsp_status_callback) {
+            error!(error = e.to_string(), name, "set status callback fail");
+        }
         if let Some(version) =
             util::convert_tls_version(&params.tls_min_version)
         {
@@ -319,6 +378,7 @@ fn ssl_certificate(
     cert: &X509,
     key: &PKey<Private>,
     chain_certificate: &Option<X509>,
+    fingerprint: &Arc<str>,
 ) {
     // set tls certificate
     if let Err(e) = ext::ssl_use_certificate(ssl, cert) {
@@ -334,6 +394,20 @@ fn ssl_certificate(
             error!(error = e.to_string(), "ssl add chain cert fail");
         }
     }
+    if let Some(index) = *FINGERPRINT_INDEX {
+        ssl.set_ex_data(index, fingerprint.clone());
+    }
+}
+
+fn ocsp_status_callback(ssl: &mut SslRef) -> Result<bool, ErrorStack> {
+    let Some(resp) = FINGERPRINT_INDEX
+        .and_then(|index| ssl.ex_data(index))
+        .and_then(|fingerprint| get_ocsp_response(fingerprint))
+    else {
+        return Ok(false);
+    };
+    ssl.set_ocsp_status(&resp)?;
+    Ok(true)
 }
 
 #[async_trait]
@@ -342,7 +416,13 @@ impl pingora::listeners::TlsAccept for DynamicCertificate {
         // TODO add more debug log
         debug!(ssl = format!("{ssl:?}"));
         if let Some((cert, key)) = &self.certificate {
-            ssl_certificate(ssl, cert, key, &self.chain_certificate);
+            ssl_certificate(
+                ssl,
+                cert,
+                key,
+                &self.chain_certificate,
+                &self.fingerprint,
+            );
             return;
         }
         let server_name = ssl.servername(NameType::HOST_NAME);
@@ -352,7 +432,13 @@ impl pingora::listeners::TlsAccept for DynamicCertificate {
                 DYNAMIC_CERTIFICATE_MAP.load().get(DEFAULT_SERVER_NAME)
             {
                 if let Some((cert, key)) = &d.certificate {
-                    ssl_certificate(ssl, cert, key, &d.chain_certificate);
+                    ssl_certificate(
+                        ssl,
+                        cert,
+                        key,
+                        &d.chain_certificate,
+                        &d.fingerprint,
+                    );
                     return;
                 }
             }
@@ -384,14 +470,22 @@ impl pingora::listeners::TlsAccept for DynamicCertificate {
             return;
         };
         if let Some((cert, key)) = &d.certificate {
-            ssl_certificate(ssl, cert, key, &d.chain_certificate);
+            ssl_certificate(
+                ssl,
+                cert,
+                key,
+                &d.chain_certificate,
+                &d.fingerprint,
+            );
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::{parse_certificate, DynamicCertificate, TlsSettingParams};
+    use super::{
+        parse_certificate, DynamicCertificate, TicketKeySeed, TlsSettingParams,
+    };
     use crate::{
         config::CertificateConf,
         proxy::{
@@ -477,6 +571,7 @@ aqcrKJfS+xaKWxXPiNlpBMG5
                 ),
                 tls_min_version: Some("tlsv1.1".to_string()),
                 tls_max_version: Some("tlsv1.3".to_string()),
+                ticket_key_seed: Some(TicketKeySeed::new("pingap", None)),
             })
             .unwrap();
         assert_eq!(true, tls_setings.min_proto_version().is_some());
@@ -504,6 +599,8 @@ aqcrKJfS+xaKWxXPiNlpBMG5
         assert_eq!(1791253416, info.not_after);
         assert_eq!(true, dynamic_certificate.certificate.is_some());
         assert_eq!(true, dynamic_certificate.chain_certificate.is_some());
+        // sha256 fingerprint in hex
+        assert_eq!(64, dynamic_certificate.fingerprint.len());
 
         let mut map = HashMap::new();
         map.insert("pingap".to_string(), cert_info);
@@ -0,0 +1,381 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::http_extra::HttpResponse;
+use crate::util;
+use bytes::Bytes;
+use http::{header, HeaderValue};
+use pingora::http::RequestHeader;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+/// The built-in template of proxy error page,
+/// it's only used if no template is configured.
+pub static DEFAULT_ERROR_TEMPLATE: &str = include_str!("../../error.html");
+
+/// The error templates of server or location,
+/// the template is matched by status code, status class and then default.
+#[derive(Debug, Default, Clone)]
+pub struct ErrorTemplate {
+    default: Option<String>,
+    templates: HashMap<String, String>,
+}
+
+impl ErrorTemplate {
+    pub fn new(
+        default: Option<String>,
+        templates: Option<BTreeMap<String, String>>,
+    ) -> Self {
+        let templates = templates
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|(_, value)| !value.is_empty())
+            .collect();
+        Self {
+            default: default.filter(|value| !value.is_empty()),
+            templates,
+        }
+    }
+    /// Returns true if no template is configured.
+    pub fn is_empty(&self) -> bool {
+        self.default.is_none() && self.templates.is_empty()
+    }
+    /// Get the template of status code, e.g. `503` > `5xx` > default.
+    pub fn get(&self, code: u16) -> Option<&str> {
+        if let Some(value) = self.templates.get(&code.to_string()) {
+            return Some(value);
+        }
+        let class = format!("{}xx", code / 100);
+        if let Some(value) = self.templates.get(&class) {
+            return Some(value);
+        }
+        self.default.as_deref()
+    }
+}
+
+/// The information of error response, it's used for template
+/// variables and json body.
+#[derive(Debug, Default, Serialize)]
+pub struct ErrorInfo<'a> {
+    pub status: u16,
+    pub error_type: &'a str,
+    pub message: String,
+    pub request_id: Option<&'a str>,
+    pub host: Option<&'a str>,
+    pub path: &'a str,
+}
+
+fn escape_html(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    for ch in value.chars() {
+        match ch {
+            '<' => result.push_str("&lt;"),
+            '>' => result.push_str("&gt;"),
+            '&' => result.push_str("&amp;"),
+            '"' => result.push_str("&quot;"),
+            '\'' => result.push_str("&#39;"),
+            _ => result.push(ch),
+        }
+    }
+    result
+}
+
+impl ErrorInfo<'_> {
+    /// Render the template with variables, the html of value will be escaped.
+    pub fn render(&self, template: &str) -> String {
+        let message = escape_html(&self.message);
+        template
+            .replace("{{version}}", util::get_pkg_version())
+            .replace("{{status}}", &self.status.to_string())
+            .replace("{{content}}", &message)
+            .replace("{{message}}", &message)
+            // keep the old variable for compatibility
+            .replace("{{error_ype}}", self.error_type)
+            .replace("{{error_type}}", self.error_type)
+            .replace(
+                "{{request_id}}",
+                &escape_html(self.request_id.unwrap_or_default()),
+            )
+            .replace("{{host}}", &escape_html(self.host.unwrap_or_default()))
+            .replace("{{path}}", &escape_html(self.path))
+    }
+    /// Get the json body of error response.
+    pub fn to_json(&self) -> Vec<u8> {
+        serde_json::to_vec(self).unwrap_or_default()
+    }
+    /// Get the body and content type of error response,
+    /// the json body is used if the client prefers json.
+    pub fn to_body(
+        &self,
+        req_header: &RequestHeader,
+        template: &str,
+    ) -> (Bytes, &'static str) {
+        if is_accept_json(req_header) {
+            return (
+                Bytes::from(self.to_json()),
+                "application/json; charset=utf-8",
+            );
+        }
+        let buf = Bytes::from(self.render(template));
+        let content_type = if buf.starts_with(b"{") {
+            "application/json; charset=utf-8"
+        } else {
+            "text/html; charset=utf-8"
+        };
+        (buf, content_type)
+    }
+}
+
+/// Render the error response of plugin or server with the template,
+/// nothing is changed if the template is not configured,
+/// only the plain message response without content type is rendered,
+/// e.g. the html page of directory plugin is kept as it is.
+pub fn render_error_response(
+    resp: &mut HttpResponse,
+    req_header: &RequestHeader,
+    request_id: Option<&str>,
+    template: Option<&str>,
+) {
+    let Some(template) = template else {
+        return;
+    };
+    let status = resp.status;
+    let headers = resp.headers.get_or_insert_with(Vec::new);
+    if status.as_u16() < 400
+        || headers.iter().any(|(name, _)| name == header::CONTENT_TYPE)
+    {
+        return;
+    }
+    let info = ErrorInfo {
+        status: status.as_u16(),
+        error_type: status.canonical_reason().unwrap_or_default(),
+        message: String::from_utf8_lossy(&resp.body).to_string(),
+        request_id,
+        host: util::get_host(req_header),
+        path: req_header.uri.path(),
+    };
+    let (body, content_type) = info.to_body(req_header, template);
+    headers
+        .push((header::CONTENT_TYPE, HeaderValue::from_static(content_type)));
+    resp.body = body;
+}
+
+/// Parse the media range of accept header, returns the media type
+/// and its quality value(default 1.0).
+fn parse_media_range(value: &str) -> (&str, f32) {
+    let mut arr = value.split(';');
+    let media_type = arr.next().unwrap_or_default().trim();
+    let quality = arr
+        .filter_map(|param| {
+            let (key, value) = param.split_once('=')?;
+            if key.trim().eq_ignore_ascii_case("q") {
+                value.trim().parse::<f32>().ok()
+            } else {
+                None
+            }
+        })
+        .next()
+        .unwrap_or(1.0);
+    (media_type, quality)
+}
+
+/// Returns true if the client prefers json to html,
+/// e.g. `Accept: application/json` or `application/problem+json`.
+/// The media ranges are compared by quality value,
+/// and the first one is preferred if they are equal.
+pub fn is_accept_json(req_header: &RequestHeader) -> bool {
+    let Some(accept) = req_header
+        .headers
+        .get(header::ACCEPT)
+        .and_then(|value| value.to_str().ok())
+    else {
+        return false;
+    };
+    // (quality, index) of the best json and html media range
+    let mut json: Option<(f32, usize)> = None;
+    let mut html: Option<(f32, usize)> = None;
+    for (index, value) in accept.split(',').enumerate() {
+        let (media_type, quality) = parse_media_range(value);
+        let media_type = media_type.to_ascii_lowercase();
+        let best =
+            if media_type.ends_with("/json") || media_type.ends_with("+json") {
+                &mut json
+            } else if media_type == "text/html" {
+                &mut html
+            } else {
+                continue;
+            };
+        if best.map(|(q, _)| quality > q).unwrap_or(true) {
+            *best = Some((quality, index));
+        }
+    }
+    let Some((json_quality, json_index)) = json else {
+        return false;
+    };
+    if json_quality <= 0.0 {
+        return false;
+    }
+    match html {
+        Some((html_quality, html_index)) => {
+            json_quality > html_quality
+                || (json_quality == html_quality && json_index < html_index)
+        },
+        None => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_accept_json, render_error_response, ErrorInfo, ErrorTemplate,
+    };
+    use crate::http_extra::{HttpResponse, HTTP_HEADER_CONTENT_HTML};
+    use http::StatusCode;
+    use pingora::http::RequestHeader;
+    use pretty_assertions::assert_eq;
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_error_template() {
+        let template = ErrorTemplate::new(
+            Some("default".to_string()),
+            Some(BTreeMap::from([
+                ("5xx".to_string(), "5xx".to_string()),
+                ("503".to_string(), "maintenance".to_string()),
+                ("404".to_string(), "".to_string()),
+            ])),
+        );
+        assert_eq!(false, template.is_empty());
+        assert_eq!("maintenance", template.get(503).unwrap());
+        assert_eq!("5xx", template.get(502).unwrap());
+        assert_eq!("default", template.get(404).unwrap());
+        assert_eq!(true, ErrorTemplate::new(None, None).is_empty());
+        assert_eq!(None, ErrorTemplate::new(None, None).get(500));
+    }
+
+    #[test]
+    fn test_error_info() {
+        let info = ErrorInfo {
+            status: 502,
+            error_type: "ConnectRefused",
+            message: "<script>".to_string(),
+            request_id: Some("abc"),
+            host: Some("pingap.io"),
+            path: "/api",
+        };
+        assert_eq!(
+            "502 ConnectRefused &lt;script&gt; abc pingap.io /api",
+            info.render(
+                "{{status}} {{error_type}} {{message}} {{request_id}} {{host}} {{path}}"
+            )
+        );
+        assert_eq!(
+            r#"{"status":502,"error_type":"ConnectRefused","message":"<script>","request_id":"abc","host":"pingap.io","path":"/api"}"#,
+            std::str::from_utf8(&info.to_json()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_render_error_response() {
+        let req_header = RequestHeader::build("GET", b"/api", None).unwrap();
+        let template = Some("{{status}} {{error_type}} {{message}} {{path}}");
+
+        let mut resp = HttpResponse {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            body: "Request is limited".into(),
+            ..Default::default()
+        };
+        render_error_response(&mut resp, &req_header, None, template);
+        assert_eq!(
+            "429 Too Many Requests Request is limited /api",
+            std::str::from_utf8(&resp.body).unwrap()
+        );
+        assert_eq!(
+            r#"Some([("content-type", "text/html; charset=utf-8")])"#,
+            format!("{:?}", resp.headers)
+        );
+
+        // the response with content type is not changed
+        let mut resp = HttpResponse {
+            status: StatusCode::NOT_FOUND,
+            body: "<p>404</p>".into(),
+            headers: Some(vec![HTTP_HEADER_CONTENT_HTML.clone()]),
+            ..Default::default()
+        };
+        render_error_response(&mut resp, &req_header, None, template);
+        assert_eq!("<p>404</p>", std::str::from_utf8(&resp.body).unwrap());
+
+        // the success response is not changed
+        let mut resp = HttpResponse {
+            status: StatusCode::OK,
+            body: "pong".into(),
+            ..Default::default()
+        };
+        render_error_response(&mut resp, &req_header, None, template);
+        assert_eq!("pong", std::str::from_utf8(&resp.body).unwrap());
+
+        // the response is not changed if no template is configured
+        let mut resp = HttpResponse {
+            status: StatusCode::TOO_MANY_REQUESTS,
+            body: "Request is limited".into(),
+            ..Default::default()
+        };
+        render_error_response(&mut resp, &req_header, None, None);
+        assert_eq!(
+            "Request is limited",
+            std::str::from_utf8(&resp.body).unwrap()
+        );
+        assert_eq!(None, resp.headers);
+    }
+
+    #[test]
+    fn test_is_accept_json() {
+        let new_header = |accept: &str| {
+            let mut req_header =
+                RequestHeader::build("GET", b"/", None).unwrap();
+            if !accept.is_empty() {
+                req_header.insert_header("Accept", accept).unwrap();
+            }
+            req_header
+        };
+        assert_eq!(true, is_accept_json(&new_header("application/json")));
+        assert_eq!(
+            true,
+            is_accept_json(&new_header("application/problem+json, */*"))
+        );
+        assert_eq!(
+            false,
+            is_accept_json(&new_header(
+                "text/html,application/xhtml+xml,application/json;q=0.9"
+            ))
+        );
+        assert_eq!(
+            false,
+            is_accept_json(&new_header("application/json;q=0, text/html"))
+        );
+        assert_eq!(false, is_accept_json(&new_header("application/json;q=0")));
+        assert_eq!(
+            true,
+            is_accept_json(&new_header(
+                "text/html;q=0.5, application/json; charset=utf-8"
+            ))
+        );
+        assert_eq!(
+            true,
+            is_accept_json(&new_header("application/json, text/html"))
+        );
+        assert_eq!(false, is_accept_json(&new_header("*/*")));
+        assert_eq!(false, is_accept_json(&new_header("")));
+    }
+}
@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::error_template::{render_error_response, ErrorTemplate};
 use crate::config::{LocationConf, PluginStep};
 use crate::http_extra::{convert_header_value, convert_headers, HttpHeader};
 use crate::plugin::get_plugin;
@@ -19,6 +20,7 @@ use crate::state::State;
 use crate::util;
 use ahash::AHashMap;
 use arc_swap::ArcSwap;
+use bytes::Bytes;
 use once_cell::sync::Lazy;
 use pingora::http::{RequestHeader, ResponseHeader};
 use pingora::proxy::Session;
@@ -99,6 +101,7 @@ pub struct Location {
     pub processing: AtomicI32,
     pub upstream: String,
     client_max_body_size: usize,
+    error_template: ErrorTemplate,
 }
 
 impl fmt::Display for Location {
@@ -173,6 +176,10 @@ impl Location {
                 .client_max_body_size
                 .unwrap_or_default()
                 .as_u64() as usize,
+            error_template: ErrorTemplate::new(
+                conf.error_template.clone(),
+                conf.error_templates.clone(),
+            ),
         };
         debug!(location = location.to_string(), "create a new location");
 
@@ -233,6 +240,15 @@ impl Location {
         }
         Ok(())
     }
+    /// Get the error template of location by status code,
+    /// returns none if the location doesn't configure it.
+    #[inline]
+    pub fn get_error_template(&self, code: u16) -> Option<&str> {
+        if self.error_template.is_empty() {
+            return None;
+        }
+        self.error_template.get(code)
+    }
     /// Rewrite the path by the rule and returns true.
     /// If the rule is not exists, returns false.
     #[inline]
@@ -298,6 +314,7 @@ impl Location {
         step: PluginStep,
         session: &mut Session,
         ctx: &mut State,
+        error_template: &ErrorTemplate,
     ) -> pingora::Result<bool> {
         let Some(plugins) = self.plugins.as_ref() else {
             return Ok(false);
@@ -307,9 +324,18 @@ impl Location {
             if let Some(plugin) = get_plugin(name) {
                 debug!(name, step = step.to_string(), "handle request plugin");
                 let result = plugin.handle_request(step, session, ctx).await?;
-                if let Some(resp) = result {
+                if let Some(mut resp) = result {
                     // ingore http response status >= 900
                     if resp.status.as_u16() < 900 {
+                        // the template of location has higher priority
+                        let code = resp.status.as_u16();
+                        render_error_response(
+                            &mut resp,
+                            session.req_header(),
+                            ctx.request_id.as_deref(),
+                            self.get_error_template(code)
+                                .or(error_template.get(code)),
+                        );
                         ctx.status = Some(resp.status);
                         resp.send(session).await?;
                     }
@@ -319,6 +345,29 @@ impl Location {
         }
         Ok(false)
     }
+    /// Run request body plugins, the body can be held or modified
+    /// before it is sent to upstream.
+    #[inline]
+    pub async fn handle_request_body_plugin(
+        &self,
+        session: &mut Session,
+        body: &mut Option<Bytes>,
+        end_of_stream: bool,
+        ctx: &mut State,
+    ) -> pingora::Result<()> {
+        let Some(plugins) = self.plugins.as_ref() else {
+            return Ok(());
+        };
+        for name in plugins.iter() {
+            if let Some(plugin) = get_plugin(name) {
+                debug!(name, "handle request body plugin");
+                plugin
+                    .handle_request_body(session, body, end_of_stream, ctx)
+                    .await?;
+            }
+        }
+        Ok(())
+    }
     /// Run response plugins,
     #[inline]
     pub async fn handle_response_plugin(
@@ -376,7 +425,10 @@ pub fn try_init_locations(
 
 #[cfg(test)]
 mod tests {
-    use super::{format_headers, new_path_selector, Location, PathSelector};
+    use super::{
+        format_headers, new_path_selector, ErrorTemplate, Location,
+        PathSelector,
+    };
     use crate::config::{LocationConf, PluginStep};
     use crate::plugin::initialize_test_plugins;
     use crate::state::State;
@@ -648,6 +700,7 @@ mod tests {
                 &mut State {
                     ..Default::default()
                 },
+                &ErrorTemplate::default(),
             )
             .await
             .unwrap();
@@ -664,6 +717,7 @@ mod tests {
                 PluginStep::Request,
                 &mut session,
                 &mut State::default(),
+                &ErrorTemplate::default(),
             )
             .await
             .unwrap();
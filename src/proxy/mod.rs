@@ -13,17 +13,22 @@
 // limitations under the License.
 
 mod dynamic_certificate;
+mod error_template;
 mod location;
 mod logger;
+mod proxy_protocol;
 mod server;
 mod server_conf;
+mod tls_ticket;
 mod upstream;
 
 // for bench
 #[allow(unused_imports)]
 pub use location::Location;
 
-pub use dynamic_certificate::{get_certificate_info_list, init_certificates};
+pub use dynamic_certificate::{
+    get_certificate_info_list, get_certificate_issuer_list, init_certificates,
+};
 pub use location::try_init_locations;
 pub use logger::Parser;
 pub use server::*;
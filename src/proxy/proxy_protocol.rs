@@ -0,0 +1,154 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::util;
+use async_trait::async_trait;
+use pingora::connectors::L4Connect;
+use pingora::protocols::l4::ext::TcpKeepalive;
+use pingora::protocols::l4::socket::SocketAddr as PingoraSocketAddr;
+use pingora::protocols::l4::stream::Stream;
+use pingora::{ErrorType, OrErr};
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpSocket;
+
+/// The connector sends proxy protocol header to upstream
+/// after the connection is established.
+/// The tcp options of upstream are applied as pingora's connector does.
+#[derive(Debug)]
+pub struct ProxyProtocolConnector {
+    pub version: u8,
+    pub source: SocketAddr,
+    pub destination: SocketAddr,
+    pub timeout: Option<Duration>,
+    pub tcp_keepalive: Option<TcpKeepalive>,
+    pub tcp_recv_buf: Option<usize>,
+    pub tcp_fast_open: bool,
+}
+
+impl ProxyProtocolConnector {
+    fn new_socket(&self, addr: &SocketAddr) -> pingora::Result<TcpSocket> {
+        let socket = if addr.is_ipv4() {
+            TcpSocket::new_v4()
+        } else {
+            TcpSocket::new_v6()
+        }
+        .or_err(ErrorType::SocketError, "new socket fail")?;
+        #[cfg(unix)]
+        {
+            use pingora::protocols::l4::ext::{
+                set_recv_buf, set_tcp_fastopen_connect,
+            };
+            use std::os::unix::io::AsRawFd;
+            let raw = socket.as_raw_fd();
+            if self.tcp_fast_open {
+                set_tcp_fastopen_connect(raw)?;
+            }
+            if let Some(recv_buf) = self.tcp_recv_buf {
+                set_recv_buf(raw, recv_buf)?;
+            }
+        }
+        Ok(socket)
+    }
+    async fn connect_inet(&self, addr: &SocketAddr) -> pingora::Result<Stream> {
+        let connect = self.new_socket(addr)?.connect(*addr);
+        let stream = if let Some(timeout) = self.timeout {
+            tokio::time::timeout(timeout, connect)
+                .await
+                .or_err(ErrorType::ConnectTimedout, "connect timeout")?
+        } else {
+            connect.await
+        }
+        .or_err(ErrorType::ConnectError, "proxy protocol connect fail")?;
+        let mut stream: Stream = stream.into();
+        if let Some(ka) = &self.tcp_keepalive {
+            stream.set_keepalive(ka)?;
+        }
+        stream.set_nodelay()?;
+        let header = util::new_proxy_protocol_header(
+            self.version,
+            self.source,
+            self.destination,
+        )
+        .or_err(ErrorType::ConnectError, "new proxy protocol header fail")?;
+        stream.write_all(&header).await.or_err(
+            ErrorType::WriteError,
+            "write proxy protocol header fail",
+        )?;
+        // the stream is buffered, so flush the header to upstream
+        stream.flush().await.or_err(
+            ErrorType::WriteError,
+            "flush proxy protocol header fail",
+        )?;
+        Ok(stream)
+    }
+}
+
+#[async_trait]
+impl L4Connect for ProxyProtocolConnector {
+    async fn connect(
+        &self,
+        addr: &PingoraSocketAddr,
+    ) -> pingora::Result<Stream> {
+        let Some(addr) = addr.as_inet() else {
+            return Err(pingora::Error::explain(
+                pingora::ErrorType::ConnectError,
+                "proxy protocol only supports inet address",
+            ));
+        };
+        self.connect_inet(addr).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProxyProtocolConnector;
+    use pingora::connectors::L4Connect;
+    use pingora::protocols::l4::ext::TcpKeepalive;
+    use pretty_assertions::assert_eq;
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    #[tokio::test]
+    async fn test_proxy_protocol_connector() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let connector = ProxyProtocolConnector {
+            version: 1,
+            source: "1.1.1.1:3000".parse().unwrap(),
+            destination: "2.2.2.2:443".parse().unwrap(),
+            timeout: None,
+            tcp_keepalive: Some(TcpKeepalive {
+                idle: Duration::from_secs(60),
+                interval: Duration::from_secs(10),
+                count: 3,
+            }),
+            tcp_recv_buf: Some(64 * 1024),
+            tcp_fast_open: false,
+        };
+        let handle = tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 37];
+            stream.read_exact(&mut buf).await.unwrap();
+            buf
+        });
+        let _stream = connector.connect(&addr.into()).await.unwrap();
+        assert_eq!(
+            "PROXY TCP4 1.1.1.1 2.2.2.2 3000 443\r\n",
+            std::str::from_utf8(&handle.await.unwrap()).unwrap()
+        );
+    }
+}
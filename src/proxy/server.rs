@@ -13,10 +13,16 @@
 // limitations under the License.
 
 use super::dynamic_certificate::DynamicCertificate;
+use super::error_template::{
+    render_error_response, ErrorInfo, ErrorTemplate, DEFAULT_ERROR_TEMPLATE,
+};
 use super::logger::Parser;
+use super::tls_ticket::TicketKeySeed;
 use super::upstream::get_upstream;
 use super::ServerConf;
 use crate::acme::handle_lets_encrypt;
+use crate::blocklist;
+use crate::cache::VaryRules;
 use crate::config;
 use crate::config::PluginStep;
 use crate::http_extra::{HttpResponse, HTTP_HEADER_NAME_X_REQUEST_ID};
@@ -32,7 +38,7 @@ use crate::state::{accept_request, end_request};
 #[cfg(feature = "full")]
 use crate::state::{new_prometheus, new_prometheus_push_service, Prometheus};
 use crate::state::{CompressionStat, State};
-use crate::util;
+use crate::util::{self, IpRules};
 use ahash::AHashMap;
 use arc_swap::ArcSwap;
 use async_trait::async_trait;
@@ -52,8 +58,10 @@ use pingora::cache::cache_control::CacheControl;
 use pingora::cache::cache_control::DirectiveValue;
 use pingora::cache::cache_control::InterpretCacheControl;
 use pingora::cache::filters::resp_cacheable;
+use pingora::cache::key::HashBinary;
 use pingora::cache::{
-    CacheKey, CacheMetaDefaults, NoCacheReason, RespCacheable,
+    CacheKey, CacheMeta, CacheMetaDefaults, CachePhase, NoCacheReason,
+    RespCacheable,
 };
 use pingora::http::{RequestHeader, ResponseHeader};
 use pingora::listeners::TcpSocketOptions;
@@ -137,13 +145,18 @@ pub struct Server {
     accepted: AtomicU64,
     processing: AtomicI32,
     log_parser: Option<Parser>,
-    error_template: String,
+    error_template: ErrorTemplate,
     threads: Option<usize>,
     tls_cipher_list: Option<String>,
     tls_ciphersuites: Option<String>,
     tls_min_version: Option<String>,
     tls_max_version: Option<String>,
+    tls_ticket_key_seed: Option<TicketKeySeed>,
     enabled_h2: bool,
+    trusted_proxies: Option<IpRules>,
+    client_header_max_size: Option<usize>,
+    client_header_max_count: Option<usize>,
+    client_uri_max_length: Option<usize>,
     lets_encrypt_enabled: bool,
     global_certificates: bool,
     tcp_socket_options: Option<TcpSocketOptions>,
@@ -163,6 +176,22 @@ pub struct ServerServices {
 const META_DEFAULTS: CacheMetaDefaults =
     CacheMetaDefaults::new(|_| Some(1), 1, 1);
 
+/// Get the cache status of response from cache phase,
+/// it's used for `X-Cache-Status` header and access log.
+fn get_cache_status(phase: CachePhase) -> Option<&'static str> {
+    match phase {
+        CachePhase::Hit => Some("HIT"),
+        CachePhase::Miss => Some("MISS"),
+        CachePhase::Stale | CachePhase::StaleUpdating => Some("STALE"),
+        CachePhase::Revalidated | CachePhase::RevalidatedNoCache(_) => {
+            Some("REVALIDATED")
+        },
+        CachePhase::Expired => Some("EXPIRED"),
+        CachePhase::Bypass => Some("BYPASS"),
+        _ => None,
+    }
+}
+
 static HTTP_500_RESPONSE: Lazy<ResponseHeader> =
     Lazy::new(|| error_resp::gen_error_response(500));
 
@@ -202,15 +231,29 @@ impl Server {
             processing: AtomicI32::new(0),
             addr: conf.addr.clone(),
             log_parser: p,
-            error_template: conf.error_template.clone(),
+            error_template: ErrorTemplate::new(
+                Some(conf.error_template.clone()),
+                conf.error_templates.clone(),
+            ),
             tls_cipher_list: conf.tls_cipher_list.clone(),
             tls_ciphersuites: conf.tls_ciphersuites.clone(),
             tls_min_version: conf.tls_min_version.clone(),
             tls_max_version: conf.tls_max_version.clone(),
+            tls_ticket_key_seed: conf.tls_ticket_key.as_ref().map(|secret| {
+                TicketKeySeed::new(secret, conf.tls_ticket_key_rotation)
+            }),
             threads: conf.threads,
             lets_encrypt_enabled: false,
             global_certificates: conf.global_certificates,
             enabled_h2: conf.enabled_h2,
+            trusted_proxies: if conf.trusted_proxies.is_empty() {
+                None
+            } else {
+                Some(IpRules::new(&conf.trusted_proxies))
+            },
+            client_header_max_size: conf.client_header_max_size,
+            client_header_max_count: conf.client_header_max_count,
+            client_uri_max_length: conf.client_uri_max_length,
             tcp_socket_options,
             prometheus_push_mode: prometheus_metrics.contains("://"),
             #[cfg(feature = "full")]
@@ -222,6 +265,44 @@ impl Server {
         };
         Ok(s)
     }
+    /// Check the uri length, header count and header size of request,
+    /// returns 414 or 431 error if exceeded.
+    /// The request header has been read by pingora before the check,
+    /// so it's still limited by the max header size of pingora.
+    fn check_request_limits(&self, session: &Session) -> pingora::Result<()> {
+        let header = session.req_header();
+        if let Some(max) = self.client_uri_max_length {
+            if header.raw_path().len() > max {
+                return Err(util::new_internal_error(
+                    414,
+                    format!("Request uri is too long, max: {max}"),
+                ));
+            }
+        }
+        if let Some(max) = self.client_header_max_count {
+            if header.headers.len() > max {
+                return Err(util::new_internal_error(
+                    431,
+                    format!("Request header count is too large, max: {max}"),
+                ));
+            }
+        }
+        if let Some(max) = self.client_header_max_size {
+            // name: value\r\n
+            let size: usize = header
+                .headers
+                .iter()
+                .map(|(name, value)| name.as_str().len() + value.len() + 4)
+                .sum();
+            if size > max {
+                return Err(util::new_internal_error(
+                    431,
+                    format!("Request header size is too large, max: {max}"),
+                ));
+            }
+        }
+        Ok(())
+    }
     /// Enable lets encrypt proxy plugin for `/.well-known/acme-challenge` handle.
     pub fn enable_lets_encrypt(&mut self) {
         self.lets_encrypt_enabled = true;
@@ -298,6 +379,7 @@ impl Server {
         let ciphersuites = self.tls_ciphersuites.clone();
         let tls_min_version = self.tls_min_version.clone();
         let tls_max_version = self.tls_max_version.clone();
+        let ticket_key_seed = self.tls_ticket_key_seed.clone();
         let mut lb = http_proxy_service(conf, self);
         // use h2c if not tls and enable http2
         if !is_tls && enabled_h2 {
@@ -320,6 +402,7 @@ impl Server {
                         ciphersuites: ciphersuites.clone(),
                         tls_min_version: tls_min_version.clone(),
                         tls_max_version: tls_max_version.clone(),
+                        ticket_key_seed: ticket_key_seed.clone(),
                     })
                     .map_err(|e| Error::Common {
                         category: "tls".to_string(),
@@ -458,6 +541,16 @@ impl ProxyHttp for Server {
             ctx.remote_addr = Some(remote_addr);
             ctx.remote_port = Some(remote_port);
         }
+        // resolve the client ip with trusted proxies,
+        // it will be used by plugins, upstream and access log
+        if let Some(trusted_proxies) = &self.trusted_proxies {
+            let client_ip =
+                util::get_trusted_client_ip(session, trusted_proxies);
+            ctx.client_ip = Some(client_ip.clone());
+            ctx.trusted_client_ip = Some(client_ip);
+        } else {
+            ctx.trusted_client_ip.clone_from(&ctx.remote_addr);
+        }
         if let Some(addr) =
             session.server_addr().and_then(|addr| addr.as_inet())
         {
@@ -516,7 +609,12 @@ impl ProxyHttp for Server {
                 location.processing.fetch_add(1, Ordering::Relaxed) + 1;
             let _ = location
                 .clone()
-                .handle_request_plugin(PluginStep::EarlyRequest, session, ctx)
+                .handle_request_plugin(
+                    PluginStep::EarlyRequest,
+                    session,
+                    ctx,
+                    &self.error_template,
+                )
                 .await?;
         }
         Ok(())
@@ -529,6 +627,7 @@ impl ProxyHttp for Server {
     where
         Self::CTX: Send + Sync,
     {
+        self.check_request_limits(session)?;
         if self.admin {
             self.serve_admin(session, ctx).await?;
             return Ok(true);
@@ -541,6 +640,27 @@ impl ProxyHttp for Server {
             }
         }
 
+        // reject the request if client ip is in blocklist,
+        // the forwarded headers are not used unless from trusted proxies
+        if let Some(client_ip) = &ctx.trusted_client_ip {
+            if blocklist::is_blocked(client_ip) {
+                let mut resp = HttpResponse {
+                    status: StatusCode::FORBIDDEN,
+                    body: Bytes::from_static(b"Client ip is blocked"),
+                    ..Default::default()
+                };
+                render_error_response(
+                    &mut resp,
+                    session.req_header(),
+                    ctx.request_id.as_deref(),
+                    self.error_template.get(403),
+                );
+                ctx.status = Some(resp.status);
+                resp.send(session).await?;
+                return Ok(true);
+            }
+        }
+
         let header = session.req_header_mut();
 
         // prometheus pull metric
@@ -559,12 +679,17 @@ impl ProxyHttp for Server {
 
         let Some(location) = &ctx.location else {
             let host = util::get_host(header).unwrap_or_default();
-            HttpResponse::unknown_error(Bytes::from(format!(
+            let mut resp = HttpResponse::unknown_error(Bytes::from(format!(
                 "Location not found, host:{host} path:{}",
                 header.uri.path(),
-            )))
-            .send(session)
-            .await?;
+            )));
+            render_error_response(
+                &mut resp,
+                header,
+                ctx.request_id.as_deref(),
+                self.error_template.get(500),
+            );
+            resp.send(session).await?;
             return Ok(true);
         };
 
@@ -576,7 +701,12 @@ impl ProxyHttp for Server {
 
         let done = location
             .clone()
-            .handle_request_plugin(PluginStep::Request, session, ctx)
+            .handle_request_plugin(
+                PluginStep::Request,
+                session,
+                ctx,
+                &self.error_template,
+            )
             .await?;
 
         if done {
@@ -597,7 +727,12 @@ impl ProxyHttp for Server {
         if let Some(location) = &ctx.location {
             let done = location
                 .clone()
-                .handle_request_plugin(PluginStep::ProxyUpstream, session, ctx)
+                .handle_request_plugin(
+                    PluginStep::ProxyUpstream,
+                    session,
+                    ctx,
+                    &self.error_template,
+                )
                 .await?;
             if done {
                 return Ok(false);
@@ -701,9 +836,9 @@ impl ProxyHttp for Server {
     }
     async fn request_body_filter(
         &self,
-        _session: &mut Session,
+        session: &mut Session,
         body: &mut Option<Bytes>,
-        _end_of_stream: bool,
+        end_of_stream: bool,
         ctx: &mut Self::CTX,
     ) -> pingora::Result<()>
     where
@@ -715,6 +850,12 @@ impl ProxyHttp for Server {
                 location.client_body_size_limit(None, ctx)?;
             }
         }
+        if let Some(location) = &ctx.location {
+            location
+                .clone()
+                .handle_request_body_plugin(session, body, end_of_stream, ctx)
+                .await?;
+        }
         Ok(())
     }
     fn cache_key_callback(
@@ -744,6 +885,17 @@ impl ProxyHttp for Server {
                 NoCacheReason::OriginNotCache,
             ));
         }
+        // vary by `*` or cookie without normalisation rule
+        let default_vary_rules = VaryRules::default();
+        let vary_rules = ctx
+            .cache_vary_rules
+            .as_deref()
+            .unwrap_or(&default_vary_rules);
+        if !vary_rules.is_cacheable(resp) {
+            return Ok(RespCacheable::Uncacheable(NoCacheReason::Custom(
+                "vary",
+            )));
+        }
         let mut cc = CacheControl::from_resp_headers(resp);
         if let Some(ref mut c) = &mut cc {
             if c.no_cache() || c.no_store() || c.private() {
@@ -776,12 +928,65 @@ impl ProxyHttp for Server {
             }
         }
 
-        Ok(resp_cacheable(
-            cc.as_ref(),
-            resp.clone(),
-            false,
-            &META_DEFAULTS,
-        ))
+        // the stale directives of cache-control take precedence over defaults
+        let defaults = if ctx.cache_stale_while_revalidate.is_some()
+            || ctx.cache_stale_if_error.is_some()
+        {
+            CacheMetaDefaults::new(
+                |_| Some(1),
+                ctx.cache_stale_while_revalidate.unwrap_or(1),
+                ctx.cache_stale_if_error.unwrap_or(1),
+            )
+        } else {
+            META_DEFAULTS
+        };
+
+        Ok(resp_cacheable(cc.as_ref(), resp.clone(), false, &defaults))
+    }
+
+    fn cache_vary_filter(
+        &self,
+        meta: &CacheMeta,
+        ctx: &mut Self::CTX,
+        req: &RequestHeader,
+    ) -> Option<HashBinary> {
+        let default_vary_rules = VaryRules::default();
+        ctx.cache_vary_rules
+            .as_deref()
+            .unwrap_or(&default_vary_rules)
+            .get_variance(meta.response_header(), req)
+    }
+
+    fn should_serve_stale(
+        &self,
+        session: &mut Session,
+        ctx: &mut Self::CTX,
+        error: Option<&pingora::Error>,
+    ) -> bool {
+        if let Some(e) = error {
+            return e.esource() == &pingora::ErrorSource::Upstream;
+        }
+        // none means stale while revalidate, it's only enabled
+        // if the cache plugin sets it or the response carries the directive,
+        // otherwise the 1s window of default cache meta is ignored
+        if ctx.cache_stale_while_revalidate.is_some() {
+            return true;
+        }
+        // the cache meta is only available in these phases
+        if !matches!(
+            session.cache.phase(),
+            CachePhase::Stale | CachePhase::StaleUpdating | CachePhase::Expired
+        ) {
+            return false;
+        }
+        session
+            .cache
+            .maybe_cache_meta()
+            .and_then(|meta| {
+                CacheControl::from_resp_headers(meta.response_header())
+            })
+            .map(|cc| cc.directives.contains_key("stale-while-revalidate"))
+            .unwrap_or_default()
     }
 
     async fn response_filter(
@@ -794,11 +999,12 @@ impl ProxyHttp for Server {
         Self::CTX: Send + Sync,
     {
         if session.cache.enabled() {
-            // ignore insert header error
-            let _ = upstream_response.insert_header(
-                "X-Cache-Status",
-                session.cache.phase().as_str(),
-            );
+            if let Some(status) = get_cache_status(session.cache.phase()) {
+                // ignore insert header error
+                let _ =
+                    upstream_response.insert_header("X-Cache-Status", status);
+                ctx.cache_status = Some(status);
+            }
             if let Some(d) = session.cache.lookup_duration() {
                 let ms = d.as_millis() as u64;
                 let _ = upstream_response
@@ -824,6 +1030,9 @@ impl ProxyHttp for Server {
                 )
                 .await?;
         }
+        if let Some(observer) = &ctx.response_observer {
+            observer.header(upstream_response);
+        }
 
         Ok(())
     }
@@ -925,6 +1134,9 @@ impl ProxyHttp for Server {
                 }
             }
         }
+        if let Some(observer) = &ctx.response_observer {
+            observer.body(body, end_of_stream);
+        }
 
         Ok(None)
     }
@@ -962,21 +1174,27 @@ impl ProxyHttp for Server {
         };
 
         let error_type = e.etype().as_str();
-        let content = self
-            .error_template
-            .replace("{{version}}", util::get_pkg_version())
-            .replace("{{content}}", &e.to_string())
-            .replace("{{error_ype}}", error_type);
-        let buf = Bytes::from(content);
+        let req_header = server_session.req_header();
+        let info = ErrorInfo {
+            status: code,
+            error_type,
+            message: e.to_string(),
+            request_id: ctx.request_id.as_deref(),
+            host: util::get_host(req_header),
+            path: req_header.uri.path(),
+        };
+        // the template of location has higher priority than server
+        let template = ctx
+            .location
+            .as_ref()
+            .and_then(|location| location.get_error_template(code))
+            .or(self.error_template.get(code))
+            .unwrap_or(DEFAULT_ERROR_TEMPLATE);
+        let (buf, content_type) = info.to_body(req_header, template);
         ctx.status = Some(
             StatusCode::from_u16(code)
                 .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR),
         );
-        let content_type = if buf.starts_with(b"{") {
-            "application/json; charset=utf-8"
-        } else {
-            "text/html; charset=utf-8"
-        };
         let _ = resp.insert_header(http::header::CONTENT_TYPE, content_type);
         let _ = resp.insert_header("X-Pingap-EType", error_type);
         let _ = resp
@@ -1089,12 +1307,13 @@ impl ProxyHttp for Server {
 mod tests {
     use super::Server;
     use crate::config::{LocationConf, PingapConf};
-    use crate::proxy::server::get_digest_detail;
+    use crate::proxy::server::{get_cache_status, get_digest_detail};
     use crate::proxy::{
         try_init_locations, try_init_server_locations, try_init_upstreams,
         Location, ServerConf,
     };
     use crate::state::State;
+    use pingora::cache::{CachePhase, NoCacheReason};
     use pingora::http::ResponseHeader;
     use pingora::protocols::{Digest, TimingDigest};
     use pingora::proxy::{ProxyHttp, Session};
@@ -1105,6 +1324,26 @@ mod tests {
     use std::time::{Duration, SystemTime};
     use tokio_test::io::Builder;
 
+    #[test]
+    fn test_get_cache_status() {
+        assert_eq!(Some("HIT"), get_cache_status(CachePhase::Hit));
+        assert_eq!(Some("MISS"), get_cache_status(CachePhase::Miss));
+        assert_eq!(Some("STALE"), get_cache_status(CachePhase::Stale));
+        assert_eq!(Some("STALE"), get_cache_status(CachePhase::StaleUpdating));
+        assert_eq!(
+            Some("REVALIDATED"),
+            get_cache_status(CachePhase::Revalidated)
+        );
+        assert_eq!(
+            Some("REVALIDATED"),
+            get_cache_status(CachePhase::RevalidatedNoCache(
+                NoCacheReason::OriginNotCache
+            ))
+        );
+        assert_eq!(Some("EXPIRED"), get_cache_status(CachePhase::Expired));
+        assert_eq!(None, get_cache_status(CachePhase::Uninit));
+    }
+
     #[test]
     fn test_get_digest_detail() {
         let digest = Digest {
@@ -1181,6 +1420,91 @@ mod tests {
         assert_eq!(false, done);
     }
 
+    #[tokio::test]
+    async fn test_check_request_limits() {
+        let mut server = new_server();
+
+        let headers = ["Host: github.com", "X-Uuid: 138q71"].join("\r\n");
+        let input_header =
+            format!("GET /vicanso/pingap?size=1 HTTP/1.1\r\n{headers}\r\n\r\n");
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+
+        server.client_uri_max_length = Some(22);
+        server.client_header_max_count = Some(2);
+        server.client_header_max_size = Some(34);
+        assert_eq!(true, server.check_request_limits(&session).is_ok());
+
+        server.client_uri_max_length = Some(10);
+        assert_eq!(
+            " HTTPStatus context: Request uri is too long, max: 10 cause:  InternalError",
+            server
+                .check_request_limits(&session)
+                .err()
+                .unwrap()
+                .to_string()
+        );
+        server.client_uri_max_length = None;
+
+        server.client_header_max_count = Some(1);
+        assert_eq!(
+            true,
+            server
+                .check_request_limits(&session)
+                .err()
+                .unwrap()
+                .to_string()
+                .contains("Request header count is too large, max: 1")
+        );
+        server.client_header_max_count = None;
+
+        server.client_header_max_size = Some(33);
+        assert_eq!(
+            true,
+            server
+                .check_request_limits(&session)
+                .err()
+                .unwrap()
+                .to_string()
+                .contains("Request header size is too large, max: 33")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_should_serve_stale() {
+        let server = new_server();
+        let input_header = "GET /vicanso/pingap HTTP/1.1\r\n\r\n";
+        let mock_io = Builder::new().read(input_header.as_bytes()).build();
+        let mut session = Session::new_h1(Box::new(mock_io));
+        session.read_request().await.unwrap();
+        let mut ctx = State::default();
+
+        // stale while revalidate is not enabled
+        assert_eq!(
+            false,
+            server.should_serve_stale(&mut session, &mut ctx, None)
+        );
+        // stale while revalidate of cache plugin
+        ctx.cache_stale_while_revalidate = Some(10);
+        assert_eq!(
+            true,
+            server.should_serve_stale(&mut session, &mut ctx, None)
+        );
+        // stale if error of upstream
+        let mut err = pingora::Error::new(pingora::ErrorType::ConnectRefused);
+        err.esource = pingora::ErrorSource::Upstream;
+        assert_eq!(
+            true,
+            server.should_serve_stale(&mut session, &mut ctx, Some(&err))
+        );
+        err.esource = pingora::ErrorSource::Downstream;
+        assert_eq!(
+            false,
+            server.should_serve_stale(&mut session, &mut ctx, Some(&err))
+        );
+    }
+
     #[tokio::test]
     async fn test_cache_key_callback() {
         let server = new_server();
@@ -14,9 +14,9 @@
 
 use crate::config::PingapConf;
 use pingora::protocols::l4::ext::TcpKeepalive;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt;
-
-static ERROR_TEMPLATE: &str = include_str!("../../error.html");
+use std::time::Duration;
 
 #[derive(Debug, Default)]
 pub struct ServerConf {
@@ -29,12 +29,19 @@ pub struct ServerConf {
     pub tls_ciphersuites: Option<String>,
     pub tls_min_version: Option<String>,
     pub tls_max_version: Option<String>,
+    pub tls_ticket_key: Option<String>,
+    pub tls_ticket_key_rotation: Option<Duration>,
     pub threads: Option<usize>,
     pub error_template: String,
+    pub error_templates: Option<BTreeMap<String, String>>,
     pub tcp_keepalive: Option<TcpKeepalive>,
     pub tcp_fastopen: Option<usize>,
     pub global_certificates: bool,
     pub enabled_h2: bool,
+    pub trusted_proxies: Vec<String>,
+    pub client_header_max_size: Option<usize>,
+    pub client_header_max_count: Option<usize>,
+    pub client_uri_max_length: Option<usize>,
     pub prometheus_metrics: Option<String>,
     pub otlp_exporter: Option<String>,
 }
@@ -54,6 +61,18 @@ impl fmt::Display for ServerConf {
 
 impl From<PingapConf> for Vec<ServerConf> {
     fn from(conf: PingapConf) -> Self {
+        // the secret of ticket key is loaded from storage
+        let tls_ticket_keys: HashMap<String, String> = conf
+            .servers
+            .iter()
+            .filter_map(|(name, item)| {
+                let storage = item.tls_ticket_key_storage.as_ref()?;
+                conf.get_storage_value(storage)
+                    .ok()
+                    .filter(|value| !value.is_empty())
+                    .map(|value| (name.to_string(), value))
+            })
+            .collect();
         let mut upstreams = vec![];
         for (name, item) in conf.upstreams {
             upstreams.push((name, item));
@@ -69,11 +88,17 @@ impl From<PingapConf> for Vec<ServerConf> {
             // load config validate base64
             // so ignore error
 
-            let mut error_template =
-                conf.basic.error_template.clone().unwrap_or_default();
-            if error_template.is_empty() {
-                error_template = ERROR_TEMPLATE.to_string();
-            }
+            // the error template of server overrides the global one
+            // it's empty if neither of them is configured
+            let error_template = item
+                .error_template
+                .clone()
+                .filter(|value| !value.is_empty())
+                .or(conf.basic.error_template.clone())
+                .unwrap_or_default();
+            let mut error_templates =
+                conf.basic.error_templates.clone().unwrap_or_default();
+            error_templates.extend(item.error_templates.unwrap_or_default());
 
             let tcp_keepalive = if item.tcp_idle.is_some()
                 && item.tcp_probe_count.is_some()
@@ -88,6 +113,7 @@ impl From<PingapConf> for Vec<ServerConf> {
                 None
             };
 
+            let tls_ticket_key = tls_ticket_keys.get(&name).cloned();
             servers.push(ServerConf {
                 name,
                 admin: false,
@@ -95,6 +121,8 @@ impl From<PingapConf> for Vec<ServerConf> {
                 tls_ciphersuites: item.tls_ciphersuites.clone(),
                 tls_min_version: item.tls_min_version.clone(),
                 tls_max_version: item.tls_max_version.clone(),
+                tls_ticket_key,
+                tls_ticket_key_rotation: item.tls_ticket_key_rotation,
                 addr: item.addr,
                 access_log: item.access_log,
                 locations: item.locations.unwrap_or_default(),
@@ -103,11 +131,22 @@ impl From<PingapConf> for Vec<ServerConf> {
                     .global_certificates
                     .unwrap_or_default(),
                 enabled_h2: item.enabled_h2.unwrap_or_default(),
+                // the trusted proxies of server override the global one
+                trusted_proxies: item
+                    .trusted_proxies
+                    .or(conf.basic.trusted_proxies.clone())
+                    .unwrap_or_default(),
+                client_header_max_size: item
+                    .client_header_max_size
+                    .map(|item| item.as_u64() as usize),
+                client_header_max_count: item.client_header_max_count,
+                client_uri_max_length: item.client_uri_max_length,
                 tcp_keepalive,
                 tcp_fastopen: item.tcp_fastopen,
                 prometheus_metrics: item.prometheus_metrics,
                 otlp_exporter: item.otlp_exporter.clone(),
                 error_template,
+                error_templates: Some(error_templates),
             });
         }
 
@@ -0,0 +1,344 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use crate::util;
+use foreign_types::ForeignTypeRef;
+use libc::{c_int, c_uchar};
+use once_cell::sync::Lazy;
+use openssl::ex_data::Index;
+use openssl::ssl::{SslContext, SslContextBuilder, SslRef};
+use openssl_sys::{EVP_CIPHER_CTX, HMAC_CTX, SSL};
+use std::time::Duration;
+
+// SSL_CTRL_SET_TLSEXT_TICKET_KEY_CB of openssl, the value is not changed
+// from openssl 1.0 to 3.x, but it is not exported by openssl-sys
+const SSL_CTRL_SET_TLSEXT_TICKET_KEY_CB: c_int = 72;
+// the verified openssl version range of the ticket key callback: [1.1.1, 4.0)
+const MIN_OPENSSL_VERSION: i64 = 0x1010100f;
+const MAX_OPENSSL_VERSION: i64 = 0x40000000;
+const TICKET_KEY_NAME_SIZE: usize = 16;
+// the iv size of aes-256-cbc
+const AES_IV_SIZE: c_int = 16;
+// the default rotation interval of ticket key: one hour
+static DEFAULT_ROTATION: Duration = Duration::from_secs(3600);
+
+static TICKET_KEY_SEED_INDEX: Lazy<Option<Index<SslContext, TicketKeySeed>>> =
+    Lazy::new(|| SslContext::new_ex_index().ok());
+
+/// The seed of session ticket keys, all processes with the same secret
+/// derive the same keys, so tickets remain valid across graceful upgrades
+/// and instances behind a load balancer.
+#[derive(Clone)]
+pub struct TicketKeySeed {
+    secret: Vec<u8>,
+    rotation: u64,
+}
+
+#[derive(Debug, PartialEq)]
+struct TicketKey {
+    name: [u8; TICKET_KEY_NAME_SIZE],
+    hmac_key: [u8; 32],
+    aes_key: [u8; 32],
+}
+
+impl TicketKeySeed {
+    pub fn new(secret: &str, rotation: Option<Duration>) -> Self {
+        let rotation = rotation.unwrap_or(DEFAULT_ROTATION).as_secs().max(1);
+        Self {
+            secret: secret.as_bytes().to_vec(),
+            rotation,
+        }
+    }
+    // Derive the ticket key of the epoch,
+    // the key is rotated every rotation interval.
+    fn derive(&self, epoch: u64) -> TicketKey {
+        let buf = hmac_sha512::HMAC::mac(
+            format!("ticket-key:{epoch}").as_bytes(),
+            &self.secret,
+        );
+        let name_buf = hmac_sha256::HMAC::mac(
+            format!("ticket-name:{epoch}").as_bytes(),
+            &self.secret,
+        );
+        let mut key = TicketKey {
+            name: [0; TICKET_KEY_NAME_SIZE],
+            hmac_key: [0; 32],
+            aes_key: [0; 32],
+        };
+        key.name.copy_from_slice(&name_buf[..TICKET_KEY_NAME_SIZE]);
+        key.hmac_key.copy_from_slice(&buf[..32]);
+        key.aes_key.copy_from_slice(&buf[32..]);
+        key
+    }
+    fn current_epoch(&self) -> u64 {
+        util::now().as_secs() / self.rotation
+    }
+    // Find the key for decrypting ticket, the previous key is still accepted
+    // but the ticket should be renewed, and the next key is accepted for
+    // clock skew between instances.
+    fn find_decrypt_key(&self, name: &[u8]) -> Option<(TicketKey, bool)> {
+        let epoch = self.current_epoch();
+        [
+            (epoch, false),
+            (epoch.saturating_sub(1), true),
+            (epoch + 1, false),
+        ]
+        .into_iter()
+        .map(|(epoch, renew)| (self.derive(epoch), renew))
+        .find(|(key, _)| key.name == name)
+    }
+}
+
+unsafe extern "C" fn ticket_key_callback(
+    ssl: *mut SSL,
+    key_name: *mut c_uchar,
+    iv: *mut c_uchar,
+    cipher_ctx: *mut EVP_CIPHER_CTX,
+    hmac_ctx: *mut HMAC_CTX,
+    enc: c_int,
+) -> c_int {
+    let Some(index) = *TICKET_KEY_SEED_INDEX else {
+        return -1;
+    };
+    let ssl = SslRef::from_ptr(ssl);
+    let Some(seed) = ssl.ssl_context().ex_data(index) else {
+        return -1;
+    };
+    let init_hmac = |key: &TicketKey| {
+        openssl_sys::HMAC_Init_ex(
+            hmac_ctx,
+            key.hmac_key.as_ptr() as *const _,
+            key.hmac_key.len() as c_int,
+            openssl_sys::EVP_sha256(),
+            std::ptr::null_mut(),
+        )
+    };
+    // encrypt new ticket
+    if enc == 1 {
+        let key = seed.derive(seed.current_epoch());
+        if openssl_sys::RAND_bytes(iv, AES_IV_SIZE) != 1 {
+            return -1;
+        }
+        std::ptr::copy_nonoverlapping(
+            key.name.as_ptr(),
+            key_name,
+            TICKET_KEY_NAME_SIZE,
+        );
+        if openssl_sys::EVP_EncryptInit_ex(
+            cipher_ctx,
+            openssl_sys::EVP_aes_256_cbc(),
+            std::ptr::null_mut(),
+            key.aes_key.as_ptr(),
+            iv,
+        ) != 1
+        {
+            return -1;
+        }
+        if init_hmac(&key) != 1 {
+            return -1;
+        }
+        return 1;
+    }
+    let name = std::slice::from_raw_parts(key_name, TICKET_KEY_NAME_SIZE);
+    // the ticket key is not found, do a full handshake
+    let Some((key, renew)) = seed.find_decrypt_key(name) else {
+        return 0;
+    };
+    if init_hmac(&key) != 1 {
+        return -1;
+    }
+    if openssl_sys::EVP_DecryptInit_ex(
+        cipher_ctx,
+        openssl_sys::EVP_aes_256_cbc(),
+        std::ptr::null_mut(),
+        key.aes_key.as_ptr(),
+        iv,
+    ) != 1
+    {
+        return -1;
+    }
+    if renew {
+        2
+    } else {
+        1
+    }
+}
+
+/// Whether the linked tls library supports the ticket key callback,
+/// the raw ctrl and HMAC_CTX api are only verified for openssl 1.1.1 to 3.x,
+/// other libraries(boringssl, libressl) use different abi.
+pub fn is_ticket_key_callback_supported() -> bool {
+    let version = openssl::version::number() as i64;
+    openssl::version::version().starts_with("OpenSSL ")
+        && (MIN_OPENSSL_VERSION..MAX_OPENSSL_VERSION).contains(&version)
+}
+
+/// Set the session ticket key callback of tls context,
+/// the ticket keys are derived from the seed.
+/// It returns false if the tls library is not supported.
+pub fn set_ticket_key_seed(
+    builder: &mut SslContextBuilder,
+    seed: TicketKeySeed,
+) -> bool {
+    if !is_ticket_key_callback_supported() {
+        return false;
+    }
+    let Some(index) = *TICKET_KEY_SEED_INDEX else {
+        return false;
+    };
+    builder.set_ex_data(index, seed);
+    type TicketKeyCallback = unsafe extern "C" fn(
+        *mut SSL,
+        *mut c_uchar,
+        *mut c_uchar,
+        *mut EVP_CIPHER_CTX,
+        *mut HMAC_CTX,
+        c_int,
+    ) -> c_int;
+    // the ctrl api only accepts the generic callback,
+    // openssl casts it back to the ticket key callback
+    let result = unsafe {
+        let cb = std::mem::transmute::<TicketKeyCallback, unsafe extern "C" fn()>(
+            ticket_key_callback,
+        );
+        openssl_sys::SSL_CTX_callback_ctrl__fixed_rust(
+            builder.as_ptr(),
+            SSL_CTRL_SET_TLSEXT_TICKET_KEY_CB,
+            Some(cb),
+        )
+    };
+    result == 1
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        is_ticket_key_callback_supported, set_ticket_key_seed, TicketKeySeed,
+    };
+    use openssl::asn1::Asn1Time;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use openssl::ssl::{Ssl, SslContext, SslMethod, SslSession, SslVersion};
+    use openssl::x509::{X509NameBuilder, X509};
+    use pretty_assertions::assert_eq;
+    use std::io::{Read, Write};
+    use std::os::unix::net::UnixStream;
+    use std::time::Duration;
+
+    fn new_server_context(seed: TicketKeySeed) -> SslContext {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut name = X509NameBuilder::new().unwrap();
+        name.append_entry_by_text("CN", "pingap.io").unwrap();
+        let name = name.build();
+        let mut cert = X509::builder().unwrap();
+        cert.set_subject_name(&name).unwrap();
+        cert.set_issuer_name(&name).unwrap();
+        cert.set_pubkey(&key).unwrap();
+        cert.set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        cert.set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        cert.sign(&key, MessageDigest::sha256()).unwrap();
+
+        let mut builder = SslContext::builder(SslMethod::tls()).unwrap();
+        builder.set_certificate(&cert.build()).unwrap();
+        builder.set_private_key(&key).unwrap();
+        builder
+            .set_max_proto_version(Some(SslVersion::TLS1_2))
+            .unwrap();
+        assert_eq!(true, set_ticket_key_seed(&mut builder, seed));
+        builder.build()
+    }
+
+    // Handshake with the server context, and return whether
+    // the session is reused and the new session.
+    fn handshake(
+        server: &SslContext,
+        session: Option<&SslSession>,
+    ) -> (bool, SslSession) {
+        let (client_stream, server_stream) = UnixStream::pair().unwrap();
+        let ssl = Ssl::new(server).unwrap();
+        let handle = std::thread::spawn(move || {
+            let mut stream = ssl.accept(server_stream).unwrap();
+            stream.write_all(b"pingap").unwrap();
+            stream.flush().unwrap();
+            let _ = stream.shutdown();
+        });
+        let client = SslContext::builder(SslMethod::tls()).unwrap().build();
+        let mut ssl = Ssl::new(&client).unwrap();
+        if let Some(session) = session {
+            // the session is created by the same client context
+            unsafe { ssl.set_session(session).unwrap() };
+        }
+        let mut stream = ssl.connect(client_stream).unwrap();
+        let mut buf = [0; 6];
+        stream.read_exact(&mut buf).unwrap();
+        handle.join().unwrap();
+        let reused = stream.ssl().session_reused();
+        let session = stream.ssl().session().unwrap().to_owned();
+        // the session of unclean shutdown connection is not resumable
+        let _ = stream.shutdown();
+        (reused, session)
+    }
+
+    #[test]
+    fn test_ticket_key_callback() {
+        // the ci uses openssl 3.x
+        assert_eq!(true, is_ticket_key_callback_supported());
+
+        let seed = TicketKeySeed::new("pingap", None);
+        let first = new_server_context(seed.clone());
+        // another process with the same secret
+        let second = new_server_context(seed);
+        let other = new_server_context(TicketKeySeed::new("other", None));
+
+        let (reused, session) = handshake(&first, None);
+        assert_eq!(false, reused);
+        let (reused, _) = handshake(&second, Some(&session));
+        assert_eq!(true, reused);
+        let (reused, _) = handshake(&other, Some(&session));
+        assert_eq!(false, reused);
+    }
+
+    #[test]
+    fn test_ticket_key_seed() {
+        let seed = TicketKeySeed::new("pingap", Some(Duration::from_secs(60)));
+        let other = TicketKeySeed::new("pingap", Some(Duration::from_secs(60)));
+        let epoch = seed.current_epoch();
+        // the same secret derives the same key
+        assert_eq!(seed.derive(epoch), other.derive(epoch));
+        assert_eq!(false, seed.derive(epoch) == seed.derive(epoch + 1));
+        assert_eq!(
+            false,
+            seed.derive(epoch)
+                == TicketKeySeed::new("pingap1", None).derive(epoch)
+        );
+
+        let key = seed.derive(epoch);
+        let (found, renew) = seed.find_decrypt_key(&key.name).unwrap();
+        assert_eq!(key, found);
+        assert_eq!(false, renew);
+
+        let key = seed.derive(epoch - 1);
+        let (found, renew) = seed.find_decrypt_key(&key.name).unwrap();
+        assert_eq!(key, found);
+        assert_eq!(true, renew);
+
+        let key = seed.derive(epoch - 2);
+        assert_eq!(true, seed.find_decrypt_key(&key.name).is_none());
+    }
+}
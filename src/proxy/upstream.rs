@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use super::proxy_protocol::ProxyProtocolConnector;
 use crate::config::UpstreamConf;
 use crate::discovery::{
     is_dns_discovery, is_docker_discovery, is_static_discovery,
@@ -38,6 +39,7 @@ use pingora::upstreams::peer::{HttpPeer, PeerOptions, Tracer, Tracing};
 use snafu::{ResultExt, Snafu};
 use std::collections::HashMap;
 use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::sync::atomic::{AtomicI32, AtomicU32, Ordering};
 use std::sync::Arc;
 use std::time::Duration;
@@ -104,6 +106,7 @@ pub struct Upstream {
     tcp_keepalive: Option<TcpKeepalive>,
     tcp_recv_buf: Option<usize>,
     tcp_fast_open: Option<bool>,
+    proxy_protocol: Option<u8>,
     peer_tracer: Option<UpstreamPeerTracer>,
     tracer: Option<Tracer>,
     processing: AtomicI32,
@@ -376,6 +379,31 @@ fn get_hash_value(
     }
 }
 
+/// Send proxy protocol header to upstream with the address of client,
+/// the connection is grouped by the client address, so it will not
+/// be reused by other clients.
+fn set_proxy_protocol(peer: &mut HttpPeer, version: u8, session: &Session) {
+    let (Some(source), Some(destination)) = (
+        session.client_addr().and_then(|addr| addr.as_inet()),
+        session.server_addr().and_then(|addr| addr.as_inet()),
+    ) else {
+        return;
+    };
+    let (source, destination) = (*source, *destination);
+    let mut hasher = DefaultHasher::new();
+    (source, destination).hash(&mut hasher);
+    peer.group_key = hasher.finish();
+    peer.options.custom_l4 = Some(Arc::new(ProxyProtocolConnector {
+        version,
+        source,
+        destination,
+        timeout: peer.options.connection_timeout,
+        tcp_keepalive: peer.options.tcp_keepalive.clone(),
+        tcp_recv_buf: peer.options.tcp_recv_buf,
+        tcp_fast_open: peer.options.tcp_fast_open,
+    }));
+}
+
 impl Upstream {
     /// Creates a new upstream from config.
     pub fn new(name: &str, conf: &UpstreamConf) -> Result<Self> {
@@ -491,6 +519,11 @@ impl Upstream {
             tcp_recv_buf: conf.tcp_recv_buf.map(|item| item.as_u64() as usize),
             tcp_keepalive,
             tcp_fast_open: conf.tcp_fast_open,
+            proxy_protocol: match conf.proxy_protocol.as_deref() {
+                Some("v1") => Some(1),
+                Some("v2") => Some(2),
+                _ => None,
+            },
             peer_tracer,
             tracer,
             processing: AtomicI32::new(0),
@@ -532,6 +565,9 @@ impl Upstream {
                 p.options.tcp_fast_open = tcp_fast_open;
             }
             p.options.tracer.clone_from(&self.tracer);
+            if let Some(version) = self.proxy_protocol {
+                set_proxy_protocol(&mut p, version, session);
+            }
             p
         })
     }
@@ -21,6 +21,8 @@ use tracing::info;
 #[async_trait]
 pub trait ServiceTask: Sync + Send {
     async fn run(&self) -> Option<bool>;
+    /// Called once when the server is shutting down gracefully.
+    async fn shutdown(&self) {}
     fn description(&self) -> String {
         "unknown".to_string()
     }
@@ -62,6 +64,7 @@ impl BackgroundService for CommonServiceTask {
         loop {
             tokio::select! {
                 _ = shutdown.changed() => {
+                    self.task.shutdown().await;
                     break;
                 }
                 _ = period.tick() => {
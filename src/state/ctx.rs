@@ -12,6 +12,7 @@
 // See the License for the specific language governing permissions and
 // limitations under the License.
 
+use crate::cache::VaryRules;
 use crate::util::format_duration;
 use crate::{proxy::Location, util};
 use bytes::{Bytes, BytesMut};
@@ -22,6 +23,7 @@ use opentelemetry::{
     trace::{SpanKind, TraceContextExt, Tracer},
     Context,
 };
+use pingora::http::ResponseHeader;
 use pingora_limits::inflight::Guard;
 use std::{sync::Arc, time::Duration};
 
@@ -29,6 +31,13 @@ pub trait ModifyResponseBody: Sync + Send {
     fn handle(&self, data: Bytes) -> Bytes;
 }
 
+/// Observe the response which is sent to client,
+/// e.g. share the response to the coalesced requests.
+pub trait ObserveResponse: Sync + Send {
+    fn header(&self, header: &ResponseHeader);
+    fn body(&self, body: &Option<Bytes>, end_of_stream: bool);
+}
+
 pub struct CompressionStat {
     pub in_bytes: usize,
     pub out_bytes: usize,
@@ -95,6 +104,9 @@ pub struct State {
     // the upstream address
     pub upstream_address: String,
     pub client_ip: Option<String>,
+    // the client ip which can't be spoofed by request headers, it's resolved
+    // through trusted proxies, or the remote addr if they are not configured
+    pub trusted_client_ip: Option<String>,
     pub remote_port: Option<u16>,
     pub remote_addr: Option<String>,
     pub server_port: Option<u16>,
@@ -106,6 +118,13 @@ pub struct State {
     pub cache_lookup_time: Option<u64>,
     pub cache_lock_time: Option<u64>,
     pub cache_max_ttl: Option<Duration>,
+    // the default seconds of stale-while-revalidate and stale-if-error
+    pub cache_stale_while_revalidate: Option<u32>,
+    pub cache_stale_if_error: Option<u32>,
+    // the cache status of response, e.g. HIT, MISS, STALE
+    pub cache_status: Option<&'static str>,
+    // the normalisation rules of cache variants
+    pub cache_vary_rules: Option<Arc<VaryRules>>,
     pub upstream_reused: bool,
     pub upstream_processing: Option<i32>,
     // upstream connect time,
@@ -128,10 +147,25 @@ pub struct State {
     pub compression_stat: Option<CompressionStat>,
     pub modify_response_body: Option<Box<dyn ModifyResponseBody>>,
     pub response_body: Option<BytesMut>,
+    pub response_observer: Option<Box<dyn ObserveResponse>>,
     // cache reading count
     pub cache_reading: Option<u32>,
     // cache writing count
     pub cache_writing: Option<u32>,
+    // the matched rule ids of waf
+    pub waf_matched: Option<Vec<String>>,
+    // the total score of matched waf rules
+    pub waf_score: i64,
+    // the request is blocked by waf
+    pub waf_blocked: bool,
+    // the request body held for waf inspection
+    pub waf_body: Option<BytesMut>,
+    pub waf_body_inspected: bool,
+    // the country code of client ip
+    pub geoip_country: Option<String>,
+    // the autonomous system number and organization of client ip
+    pub geoip_asn: Option<u32>,
+    pub geoip_asn_org: Option<String>,
     #[cfg(feature = "full")]
     pub otel_tracer: Option<OtelTracer>,
     #[cfg(feature = "full")]
@@ -276,6 +310,36 @@ impl State {
                     buf = format_duration(buf, ms);
                 }
             },
+            "cache_status" => {
+                if let Some(value) = self.cache_status {
+                    buf.extend(value.as_bytes());
+                }
+            },
+            "waf_rules" => {
+                if let Some(value) = &self.waf_matched {
+                    buf.extend(value.join(",").as_bytes());
+                }
+            },
+            "waf_score" if self.waf_score != 0 => {
+                buf.extend(
+                    itoa::Buffer::new().format(self.waf_score).as_bytes(),
+                );
+            },
+            "geoip_country" => {
+                if let Some(value) = &self.geoip_country {
+                    buf.extend(value.as_bytes());
+                }
+            },
+            "geoip_asn" => {
+                if let Some(value) = self.geoip_asn {
+                    buf.extend(itoa::Buffer::new().format(value).as_bytes());
+                }
+            },
+            "geoip_asn_org" => {
+                if let Some(value) = &self.geoip_asn_org {
+                    buf.extend(value.as_bytes());
+                }
+            },
             "service_time" => {
                 buf = format_duration(
                     buf,
@@ -453,6 +517,40 @@ mod tests {
                 .as_ref()
         );
 
+        ctx.cache_status = Some("STALE");
+        assert_eq!(
+            b"STALE",
+            ctx.append_value(BytesMut::new(), "cache_status").as_ref()
+        );
+
+        ctx.waf_matched =
+            Some(vec!["941100".to_string(), "942100".to_string()]);
+        ctx.waf_score = 10;
+        assert_eq!(
+            b"941100,942100",
+            ctx.append_value(BytesMut::new(), "waf_rules").as_ref()
+        );
+        assert_eq!(
+            b"10",
+            ctx.append_value(BytesMut::new(), "waf_score").as_ref()
+        );
+
+        ctx.geoip_country = Some("CN".to_string());
+        ctx.geoip_asn = Some(13335);
+        ctx.geoip_asn_org = Some("Cloudflare".to_string());
+        assert_eq!(
+            b"CN",
+            ctx.append_value(BytesMut::new(), "geoip_country").as_ref()
+        );
+        assert_eq!(
+            b"13335",
+            ctx.append_value(BytesMut::new(), "geoip_asn").as_ref()
+        );
+        assert_eq!(
+            b"Cloudflare",
+            ctx.append_value(BytesMut::new(), "geoip_asn_org").as_ref()
+        );
+
         ctx.created_at = util::now().as_millis() as u64 - 1;
         assert_eq!(
             true,
@@ -24,7 +24,7 @@ pub use process::*;
 #[cfg(feature = "full")]
 pub use prom::{
     new_prometheus, new_prometheus_push_service, Prometheus,
-    CACHE_READING_TIME, CACHE_WRITING_TIME,
+    CACHE_READING_TIME, CACHE_TIER_LOOKUP, CACHE_WRITING_TIME,
 };
 
 #[cfg(feature = "full")]
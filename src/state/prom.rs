@@ -54,6 +54,19 @@ pub static CACHE_WRITING_TIME: Lazy<Box<Histogram>> = Lazy::new(|| {
     )
 });
 
+// the lookup result of each cache tier, e.g. `memory` and `file`
+pub static CACHE_TIER_LOOKUP: Lazy<Box<IntCounterVec>> = Lazy::new(|| {
+    Box::new(
+        new_int_counter_vec(
+            "",
+            "pingap_cache_tier_lookup",
+            "pingap cache tier lookup count",
+            &["tier", "result"],
+        )
+        .unwrap(),
+    )
+});
+
 pub struct Prometheus {
     r: Registry,
     http_request_accepted: Box<IntCounter>,
@@ -76,6 +89,8 @@ pub struct Prometheus {
     cache_reading: Box<IntGauge>,
     cache_writing: Box<IntGauge>,
     compression_ratio: Box<Histogram>,
+    waf_matched: Box<IntCounterVec>,
+    waf_blocked: Box<IntCounter>,
     memory: Box<IntGauge>,
     fd_count: Box<IntGauge>,
     tcp_count: Box<IntGauge>,
@@ -191,6 +206,16 @@ impl Prometheus {
         if let Some(compression_stat) = &ctx.compression_stat {
             self.compression_ratio.observe(compression_stat.ratio());
         }
+
+        // waf stats
+        if let Some(matched) = &ctx.waf_matched {
+            for id in matched.iter() {
+                self.waf_matched.with_label_values(&[id]).inc();
+            }
+        }
+        if ctx.waf_blocked {
+            self.waf_blocked.inc();
+        }
     }
     fn gather(&self) -> Vec<prometheus::proto::MetricFamily> {
         let info = get_process_system_info();
@@ -495,6 +520,17 @@ pub fn new_prometheus(server: &str) -> Result<Prometheus> {
         "pingap response compression ratio",
         &[1.0, 2.0, 3.0, 5.0, 10.0],
     )?);
+    let waf_matched = Box::new(new_int_counter_vec(
+        server,
+        "pingap_waf_matched",
+        "pingap waf matched rule count",
+        &["rule"],
+    )?);
+    let waf_blocked = Box::new(new_int_counter(
+        server,
+        "pingap_waf_blocked",
+        "pingap waf blocked request count",
+    )?);
 
     let memory = Box::new(new_int_gauge(
         server,
@@ -539,7 +575,10 @@ pub fn new_prometheus(server: &str) -> Result<Prometheus> {
         cache_writing.clone(),
         CACHE_READING_TIME.clone(),
         CACHE_WRITING_TIME.clone(),
+        CACHE_TIER_LOOKUP.clone(),
         compression_ratio.clone(),
+        waf_matched.clone(),
+        waf_blocked.clone(),
         memory.clone(),
         fd_count.clone(),
         tcp_count.clone(),
@@ -573,6 +612,8 @@ pub fn new_prometheus(server: &str) -> Result<Prometheus> {
         cache_reading,
         cache_writing,
         compression_ratio,
+        waf_matched,
+        waf_blocked,
         memory,
         fd_count,
         tcp_count,
@@ -635,10 +676,12 @@ mod tests {
                     out_bytes: 512,
                     duration: Duration::from_millis(20),
                 }),
+                waf_matched: Some(vec!["942100".to_string()]),
+                waf_blocked: true,
                 ..Default::default()
             },
         );
         let buf = p.metrics().unwrap();
-        assert_eq!(186, std::str::from_utf8(&buf).unwrap().split('\n').count());
+        assert_eq!(192, std::str::from_utf8(&buf).unwrap().split('\n').count());
     }
 }
@@ -13,12 +13,14 @@
 // limitations under the License.
 
 use super::Error;
-use super::{base64_decode, base64_encode};
+use super::{base64_decode, base64_encode, now};
 use aes_gcm_siv::{
     aead::{Aead, KeyInit},
     Aes256GcmSiv, Nonce,
 };
+use nanoid::nanoid;
 use once_cell::sync::Lazy;
+use sha2::{Digest, Sha256};
 
 type Result<T, E = Error> = std::result::Result<T, E>;
 
@@ -75,3 +77,41 @@ pub fn aes_decrypt(key: &str, data: &str) -> Result<String> {
         .unwrap_or_default()
         .to_string())
 }
+
+/// Generate a signed token, the format is `{id}.{timestamp}.{signature}`.
+pub fn generate_token(key: &str) -> String {
+    let id = nanoid!(12);
+    let prefix = format!("{id}.{:x}", now().as_secs());
+    let mut hasher = Sha256::new();
+    hasher.update(prefix.as_bytes());
+    hasher.update(key.as_bytes());
+    let hash256 = hasher.finalize();
+    format!("{prefix}.{}", base64_encode(hash256))
+}
+
+/// Validate the signed token, it will be invalid if expired(ttl > 0).
+pub fn validate_token(key: &str, ttl: u64, value: &str) -> bool {
+    let arr: Vec<&str> = value.split('.').collect();
+    if arr.len() != 3 {
+        return false;
+    }
+
+    if ttl > 0 {
+        let now = now().as_secs();
+        if now
+            .saturating_sub(u64::from_str_radix(arr[1], 16).unwrap_or_default())
+            > ttl
+        {
+            return false;
+        }
+    }
+
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}.{}", arr[0], arr[1]).as_bytes());
+    hasher.update(key.as_bytes());
+    let hash256 = hasher.finalize();
+    if arr[2] != base64_encode(hash256) {
+        return false;
+    }
+    true
+}
@@ -48,3 +48,142 @@ impl IpRules {
         Ok(found)
     }
 }
+
+// Normalize the node of forwarded header, the quotes, brackets of ipv6
+// and port will be removed, e.g. "[2001:db8:cafe::17]:4711" -> 2001:db8:cafe::17
+fn normalize_node(value: &str) -> String {
+    let value = value.trim().trim_matches('"');
+    if let Some(value) = value.strip_prefix('[') {
+        return value.split(']').next().unwrap_or_default().to_string();
+    }
+    // ipv4 with port
+    if value.matches(':').count() == 1 {
+        return value.split(':').next().unwrap_or_default().to_string();
+    }
+    value.to_string()
+}
+
+/// Get the nodes of `for` parameter from the RFC 7239 Forwarded header,
+/// e.g. `for=192.0.2.60;proto=http, for="[2001:db8:cafe::17]:4711"`.
+pub fn parse_forwarded_for(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (key, value) = pair.split_once('=')?;
+                if key.trim().eq_ignore_ascii_case("for") {
+                    Some(normalize_node(value))
+                } else {
+                    None
+                }
+            })
+        })
+        .collect()
+}
+
+/// Get the nodes of X-Forwarded-For header.
+pub fn parse_x_forwarded_for(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(normalize_node)
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+/// Resolve the client ip from the proxy hops, the hops are walked from
+/// right to left and it stops at the first untrusted hop.
+/// The hops are ignored if the remote addr is not a trusted proxy,
+/// and an invalid hop(e.g. `unknown`) stops the walk with
+/// the last trusted one.
+pub fn resolve_client_ip(
+    remote_addr: &str,
+    hops: &[String],
+    trusted: &IpRules,
+) -> String {
+    let is_trusted = |ip: &String| trusted.matched(ip).unwrap_or_default();
+    let mut client_ip = remote_addr.to_string();
+    if !is_trusted(&client_ip) {
+        return client_ip;
+    }
+    for hop in hops.iter().rev() {
+        if hop.parse::<IpAddr>().is_err() {
+            break;
+        }
+        client_ip.clone_from(hop);
+        if !is_trusted(hop) {
+            break;
+        }
+    }
+    client_ip
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_forwarded_for, parse_x_forwarded_for, resolve_client_ip, IpRules,
+    };
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_parse_forwarded_for() {
+        assert_eq!(
+            r#"["192.0.2.60", "2001:db8:cafe::17", "192.0.2.43", "unknown", "_hidden"]"#,
+            format!(
+                "{:?}",
+                parse_forwarded_for(
+                    r#"for=192.0.2.60;proto=http;by=203.0.113.43, For="[2001:db8:cafe::17]:4711", proto=https;for="192.0.2.43:47011", for=unknown, for=_hidden, by=10.0.0.1"#
+                )
+            )
+        );
+        assert_eq!(
+            r#"["1.1.1.1", "2.2.2.2", "::1"]"#,
+            format!(
+                "{:?}",
+                parse_x_forwarded_for("1.1.1.1, 2.2.2.2:3000 ,, ::1")
+            )
+        );
+    }
+
+    #[test]
+    fn test_resolve_client_ip() {
+        let trusted = IpRules::new(&vec![
+            "10.0.0.0/8".to_string(),
+            "192.168.1.1".to_string(),
+        ]);
+        let hops: Vec<String> = ["1.1.1.1", "2.2.2.2", "10.0.0.2"]
+            .iter()
+            .map(|item| item.to_string())
+            .collect();
+        // the forged hop on the left is ignored
+        assert_eq!("2.2.2.2", resolve_client_ip("10.0.0.1", &hops, &trusted));
+        // untrusted remote addr
+        assert_eq!("3.3.3.3", resolve_client_ip("3.3.3.3", &hops, &trusted));
+        // no hops
+        assert_eq!(
+            "192.168.1.1",
+            resolve_client_ip("192.168.1.1", &[], &trusted)
+        );
+        // all hops are trusted
+        assert_eq!(
+            "10.0.0.3",
+            resolve_client_ip(
+                "10.0.0.1",
+                &["10.0.0.3".to_string(), "10.0.0.2".to_string()],
+                &trusted
+            )
+        );
+        // invalid hop
+        assert_eq!(
+            "10.0.0.2",
+            resolve_client_ip(
+                "10.0.0.1",
+                &[
+                    "1.1.1.1".to_string(),
+                    "unknown".to_string(),
+                    "10.0.0.2".to_string()
+                ],
+                &trusted
+            )
+        );
+    }
+}
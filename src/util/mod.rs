@@ -38,9 +38,13 @@ pub enum Error {
 
 mod crypto;
 mod ip;
+mod proxy_protocol;
 
-pub use crypto::{aes_decrypt, aes_encrypt};
-pub use ip::IpRules;
+pub use crypto::{aes_decrypt, aes_encrypt, generate_token, validate_token};
+pub use ip::{
+    parse_forwarded_for, parse_x_forwarded_for, resolve_client_ip, IpRules,
+};
+pub use proxy_protocol::new_proxy_protocol_header;
 
 const NAME: &str = env!("CARGO_PKG_NAME");
 const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -95,6 +99,8 @@ pub fn get_remote_addr(session: &Session) -> Option<(String, u16)> {
 /// Gets client ip from X-Forwarded-For,
 /// If none, get from X-Real-Ip,
 /// If none, get remote addr.
+/// The headers are trusted from any client,
+/// use `get_trusted_client_ip` if trusted proxies are configured.
 pub fn get_client_ip(session: &Session) -> String {
     if let Some(value) = session.get_header(HTTP_HEADER_X_FORWARDED_FOR.clone())
     {
@@ -113,6 +119,58 @@ pub fn get_client_ip(session: &Session) -> String {
     "".to_string()
 }
 
+// Get the proxy hops from Forwarded header, if none, from X-Forwarded-For,
+// all values of the repeated header are used.
+fn get_proxy_hops(header: &RequestHeader) -> Vec<String> {
+    let get_values = |name: &HeaderName| -> Vec<String> {
+        header
+            .headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+            .collect()
+    };
+    let forwarded = get_values(&http::header::FORWARDED);
+    if !forwarded.is_empty() {
+        return parse_forwarded_for(&forwarded.join(","));
+    }
+    parse_x_forwarded_for(&get_values(&HTTP_HEADER_X_FORWARDED_FOR).join(","))
+}
+
+fn get_trusted_client_ip_from_header(
+    header: &RequestHeader,
+    remote_addr: &str,
+    trusted: &IpRules,
+) -> String {
+    let hops = get_proxy_hops(header);
+    if hops.is_empty() && trusted.matched(&remote_addr.to_string()) == Ok(true)
+    {
+        if let Some(value) = get_req_header_value(header, "X-Real-Ip") {
+            let value = value.trim();
+            if value.parse::<std::net::IpAddr>().is_ok() {
+                return value.to_string();
+            }
+        }
+    }
+    resolve_client_ip(remote_addr, &hops, trusted)
+}
+
+/// Gets client ip with trusted proxies, the forwarded headers
+/// (Forwarded, X-Forwarded-For and X-Real-Ip) are only used when
+/// the remote addr is a trusted proxy, and the hops are walked from right
+/// to left until the first untrusted one.
+pub fn get_trusted_client_ip(session: &Session, trusted: &IpRules) -> String {
+    let remote_addr = get_remote_addr(session)
+        .map(|(addr, _)| addr)
+        .unwrap_or_default();
+    get_trusted_client_ip_from_header(
+        session.req_header(),
+        &remote_addr,
+        trusted,
+    )
+}
+
 /// Gets string value from req header.
 pub fn get_req_header_value<'a>(
     req_header: &'a RequestHeader,
@@ -134,7 +192,7 @@ pub fn get_cookie_value<'a>(
     if let Some(cookie_value) = get_req_header_value(req_header, "Cookie") {
         for item in cookie_value.split(';') {
             if let Some((k, v)) = item.split_once('=') {
-                if k == cookie_name {
+                if k.trim() == cookie_name {
                     return Some(v.trim());
                 }
             }
@@ -397,9 +455,10 @@ pub fn format_duration(mut buf: BytesMut, ms: u64) -> BytesMut {
 #[cfg(test)]
 mod tests {
     use super::{
-        convert_tls_version, format_byte_size, format_duration, get_latency,
-        get_pkg_name, get_pkg_version, local_ip_list, remove_query_from_header,
-        resolve_path,
+        convert_tls_version, format_byte_size, format_duration, generate_token,
+        get_latency, get_pkg_name, get_pkg_version,
+        get_trusted_client_ip_from_header, local_ip_list,
+        remove_query_from_header, resolve_path, validate_token, IpRules,
     };
     use bytes::BytesMut;
     use pingora::{http::RequestHeader, tls::ssl::SslVersion};
@@ -418,6 +477,51 @@ mod tests {
         assert_eq!("/?name=pingap", req.uri.to_string());
     }
 
+    #[test]
+    fn test_get_trusted_client_ip() {
+        let trusted = IpRules::new(&vec!["10.0.0.0/8".to_string()]);
+        let mut req = RequestHeader::build("GET", b"/", None).unwrap();
+        assert_eq!(
+            "10.0.0.1",
+            get_trusted_client_ip_from_header(&req, "10.0.0.1", &trusted)
+        );
+        req.insert_header("X-Real-Ip", "2.2.2.2").unwrap();
+        assert_eq!(
+            "2.2.2.2",
+            get_trusted_client_ip_from_header(&req, "10.0.0.1", &trusted)
+        );
+        assert_eq!(
+            "3.3.3.3",
+            get_trusted_client_ip_from_header(&req, "3.3.3.3", &trusted)
+        );
+
+        req.append_header("X-Forwarded-For", "1.1.1.1, 4.4.4.4")
+            .unwrap();
+        req.append_header("X-Forwarded-For", "10.0.0.2").unwrap();
+        assert_eq!(
+            "4.4.4.4",
+            get_trusted_client_ip_from_header(&req, "10.0.0.1", &trusted)
+        );
+
+        req.insert_header(
+            "Forwarded",
+            r#"for=1.1.1.1, for="5.5.5.5:3000";proto=https"#,
+        )
+        .unwrap();
+        assert_eq!(
+            "5.5.5.5",
+            get_trusted_client_ip_from_header(&req, "10.0.0.1", &trusted)
+        );
+    }
+
+    #[test]
+    fn test_generate_token() {
+        let key = "123";
+        let value = generate_token(key);
+        assert_eq!(true, validate_token(key, 10, &value));
+        assert_eq!(false, validate_token(key, 10, &format!("{value}:1")));
+    }
+
     #[test]
     fn test_get_pkg_info() {
         assert_eq!("pingap", get_pkg_name());
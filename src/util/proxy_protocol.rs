@@ -0,0 +1,137 @@
+// Copyright 2024 Tree xie.
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+// http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+use bytes::BytesMut;
+use proxy_protocol::{version1, version2, ProxyHeader};
+use std::io;
+use std::net::SocketAddr;
+
+/// Create the proxy protocol header of version, only v1 and v2 are supported.
+pub fn new_proxy_protocol_header(
+    version: u8,
+    source: SocketAddr,
+    destination: SocketAddr,
+) -> io::Result<BytesMut> {
+    // the address family should be the same
+    let (source, destination) = match (source, destination) {
+        (SocketAddr::V4(source), SocketAddr::V6(destination)) => (
+            SocketAddr::new(source.ip().to_ipv6_mapped().into(), source.port()),
+            SocketAddr::V6(destination),
+        ),
+        (SocketAddr::V6(source), SocketAddr::V4(destination)) => (
+            SocketAddr::V6(source),
+            SocketAddr::new(
+                destination.ip().to_ipv6_mapped().into(),
+                destination.port(),
+            ),
+        ),
+        _ => (source, destination),
+    };
+    let header = match version {
+        1 => ProxyHeader::Version1 {
+            addresses: match (source, destination) {
+                (SocketAddr::V4(source), SocketAddr::V4(destination)) => {
+                    version1::ProxyAddresses::Ipv4 {
+                        source,
+                        destination,
+                    }
+                },
+                (SocketAddr::V6(source), SocketAddr::V6(destination)) => {
+                    version1::ProxyAddresses::Ipv6 {
+                        source,
+                        destination,
+                    }
+                },
+                _ => version1::ProxyAddresses::Unknown,
+            },
+        },
+        2 => ProxyHeader::Version2 {
+            command: version2::ProxyCommand::Proxy,
+            transport_protocol: version2::ProxyTransportProtocol::Stream,
+            addresses: match (source, destination) {
+                (SocketAddr::V4(source), SocketAddr::V4(destination)) => {
+                    version2::ProxyAddresses::Ipv4 {
+                        source,
+                        destination,
+                    }
+                },
+                (SocketAddr::V6(source), SocketAddr::V6(destination)) => {
+                    version2::ProxyAddresses::Ipv6 {
+                        source,
+                        destination,
+                    }
+                },
+                _ => version2::ProxyAddresses::Unspec,
+            },
+        },
+        _ => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("proxy protocol version({version}) is not supported"),
+            ))
+        },
+    };
+    proxy_protocol::encode(header)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::new_proxy_protocol_header;
+    use pretty_assertions::assert_eq;
+    use proxy_protocol::{version2, ProxyHeader};
+    use std::net::SocketAddr;
+
+    #[test]
+    fn test_new_proxy_protocol_header() {
+        let source: SocketAddr = "1.1.1.1:3000".parse().unwrap();
+        let destination: SocketAddr = "2.2.2.2:443".parse().unwrap();
+        let header = new_proxy_protocol_header(1, source, destination).unwrap();
+        assert_eq!(
+            "PROXY TCP4 1.1.1.1 2.2.2.2 3000 443\r\n",
+            std::str::from_utf8(&header).unwrap()
+        );
+
+        let header = new_proxy_protocol_header(2, source, destination).unwrap();
+        let ProxyHeader::Version2 {
+            command,
+            addresses:
+                version2::ProxyAddresses::Ipv4 {
+                    source: header_source,
+                    destination: header_destination,
+                },
+            ..
+        } = proxy_protocol::parse(&mut &header[..]).unwrap()
+        else {
+            panic!("proxy protocol v2 header is invalid");
+        };
+        assert_eq!(version2::ProxyCommand::Proxy, command);
+        assert_eq!(source, SocketAddr::from(header_source));
+        assert_eq!(destination, SocketAddr::from(header_destination));
+
+        let source: SocketAddr = "[2001:db8::1]:3000".parse().unwrap();
+        let header = new_proxy_protocol_header(1, source, destination).unwrap();
+        assert_eq!(
+            "PROXY TCP6 2001:db8::1 ::ffff:2.2.2.2 3000 443\r\n",
+            std::str::from_utf8(&header).unwrap()
+        );
+
+        assert_eq!(
+            "proxy protocol version(3) is not supported",
+            new_proxy_protocol_header(3, source, destination)
+                .unwrap_err()
+                .to_string()
+        );
+    }
+}
@@ -51,6 +51,7 @@ pub enum NotificationCategory {
     TlsValidity,
     ParseCertificateFail,
     ServiceDiscoverFail,
+    CacheDiskFail,
 }
 
 impl Display for NotificationLevel {
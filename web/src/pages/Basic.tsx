@@ -179,6 +179,7 @@ export default function Basic() {
           "reload_config_fail",
           "tls_validity",
           "service_discover_fail",
+          "cache_disk_fail",
         ].sort(),
         true,
       ),
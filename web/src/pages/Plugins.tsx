@@ -1,5 +1,5 @@
 import { LoadingPage } from "@/components/loading";
-import useConfigState from "@/states/config";
+import useConfigState, { PluginFieldSchema } from "@/states/config";
 import { ExForm, ExFormItem } from "@/components/ex-form";
 import { z } from "zod";
 import { useI18n } from "@/i18n";
@@ -24,15 +24,72 @@ function getPluginConfig(
   return (plugins[name] || {}) as Record<string, unknown>;
 }
 
+// the fields of plugin which are rendered without schema
+const commonFields = ["category", "step", "remark"];
+
+function getSchemaItems(
+  properties: Record<string, PluginFieldSchema>,
+  pluginConfig: Record<string, unknown>,
+) {
+  const items: ExFormItem[] = [];
+  Object.keys(properties).forEach((name) => {
+    if (commonFields.includes(name)) {
+      return;
+    }
+    const field = properties[name];
+    const item: ExFormItem = {
+      name,
+      label: name,
+      placeholder: field.description || "",
+      defaultValue: pluginConfig[name] as string,
+      span: 6,
+      category: ExFormItemCategory.TEXT,
+    };
+    if (field.enum) {
+      item.category = ExFormItemCategory.RADIOS;
+      item.options = newStringOptions(
+        field.enum.filter((value) => value),
+        true,
+        true,
+      );
+    } else if (field.type === "boolean") {
+      item.category = ExFormItemCategory.RADIOS;
+      item.options = newBooleanOptions();
+      item.defaultValue = pluginConfig[name] as boolean;
+    } else if (field.type === "integer") {
+      item.category = ExFormItemCategory.NUMBER;
+      item.defaultValue = pluginConfig[name] as number;
+    } else if (field.type === "array") {
+      // the array of table should be edited in toml
+      if (field.items?.type !== "string") {
+        return;
+      }
+      item.category = ExFormItemCategory.TEXTS;
+      item.defaultValue = pluginConfig[name] as string[];
+    }
+    items.push(item);
+  });
+  return items;
+}
+
 export default function Plugins() {
   const pluginI18n = useI18n("plugin");
   const [searchParams, setSearchParams] = useSearchParams();
 
-  const [config, initialized, update, remove] = useConfigState((state) => [
+  const [
+    config,
+    initialized,
+    update,
+    remove,
+    pluginSchema,
+    fetchPluginSchema,
+  ] = useConfigState((state) => [
     state.data,
     state.initialized,
     state.update,
     state.remove,
+    state.pluginSchema,
+    state.fetchPluginSchema,
   ]);
 
   const newPlugin = "*";
@@ -51,6 +108,9 @@ export default function Plugins() {
   useEffect(() => {
     setCurrentPlugin(searchParams.get("name") || newPlugin);
   }, [searchParams]);
+  useEffect(() => {
+    fetchPluginSchema().catch(console.error);
+  }, [fetchPluginSchema]);
   if (!initialized) {
     return <LoadingPage />;
   }
@@ -67,6 +127,40 @@ export default function Plugins() {
     setSearchParams(searchParams);
   };
 
+  // the categories are listed from schema if it's loaded
+  const categories = pluginSchema
+    ? Object.keys(pluginSchema.$defs)
+    : [
+        PluginCategory.STATS,
+        PluginCategory.PING,
+        PluginCategory.ADMIN,
+        PluginCategory.DIRECTORY,
+        PluginCategory.MOCK,
+        PluginCategory.REDIRECT,
+        PluginCategory.CACHE,
+
+        PluginCategory.REQUEST_ID,
+        PluginCategory.COMPRESSION,
+        PluginCategory.ACCEPT_ENCODING,
+
+        // auth
+        PluginCategory.KEY_AUTH,
+        PluginCategory.BASIC_AUTH,
+        PluginCategory.JWT,
+        PluginCategory.COMBINED_AUTH,
+
+        // limit
+        PluginCategory.LIMIT,
+        PluginCategory.IP_RESTRICTION,
+        PluginCategory.UA_RESTRICTION,
+        PluginCategory.REFERER_RESTRICTION,
+        PluginCategory.CSRF,
+        PluginCategory.CORS,
+
+        // response
+        PluginCategory.RESPONSE_HEADERS,
+      ];
+
   const items: ExFormItem[] = [];
   if (currentPlugin === newPlugin) {
     items.unshift(
@@ -77,39 +171,7 @@ export default function Plugins() {
         defaultValue: currentCategory,
         category: ExFormItemCategory.RADIOS,
         span: 6,
-        options: newStringOptions(
-          [
-            PluginCategory.STATS,
-            PluginCategory.PING,
-            PluginCategory.ADMIN,
-            PluginCategory.DIRECTORY,
-            PluginCategory.MOCK,
-            PluginCategory.REDIRECT,
-            PluginCategory.CACHE,
-
-            PluginCategory.REQUEST_ID,
-            PluginCategory.COMPRESSION,
-            PluginCategory.ACCEPT_ENCODING,
-
-            // auth
-            PluginCategory.KEY_AUTH,
-            PluginCategory.BASIC_AUTH,
-            PluginCategory.JWT,
-            PluginCategory.COMBINED_AUTH,
-
-            // limit
-            PluginCategory.LIMIT,
-            PluginCategory.IP_RESTRICTION,
-            PluginCategory.UA_RESTRICTION,
-            PluginCategory.REFERER_RESTRICTION,
-            PluginCategory.CSRF,
-            PluginCategory.CORS,
-
-            // response
-            PluginCategory.RESPONSE_HEADERS,
-          ],
-          true,
-        ),
+        options: newStringOptions(categories, true),
       },
       {
         name: "_name_",
@@ -930,6 +992,11 @@ export default function Plugins() {
       break;
     }
     default: {
+      // the fields of new category are generated from schema
+      const properties = pluginSchema?.$defs[category]?.properties;
+      if (properties) {
+        items.push(...getSchemaItems(properties, pluginConfig));
+      }
       break;
     }
   }
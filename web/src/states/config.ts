@@ -141,14 +141,30 @@ interface Config {
   storages?: Record<string, Storage>;
 }
 
+export interface PluginFieldSchema {
+  type?: string;
+  enum?: string[];
+  description?: string;
+  items?: {
+    type?: string;
+  };
+}
+
+// the json schema of plugins, it's generated by the server
+export interface PluginSchema {
+  $defs: Record<string, { properties: Record<string, PluginFieldSchema> }>;
+}
+
 interface ConfigState {
   data: Config;
+  pluginSchema?: PluginSchema;
   originalToml: string;
   fullToml: string;
   initialized: boolean;
   version: string;
   fetch: () => Promise<Config>;
   fetchToml: () => Promise<void>;
+  fetchPluginSchema: () => Promise<void>;
   update: (
     category: string,
     name: string,
@@ -185,6 +201,12 @@ const useConfigState = create<ConfigState>()((set, get) => ({
     });
     return;
   },
+  fetchPluginSchema: async () => {
+    const { data } = await request.get<PluginSchema>("/schema");
+    set({
+      pluginSchema: data,
+    });
+  },
   update: async (
     category: string,
     name: string,